@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+
+use crate::llmclient::ChatEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "JSON",
+            ExportFormat::Markdown => "Markdown",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+        }
+    }
+}
+
+pub fn export_chat_history(history: &[ChatEntry], format: ExportFormat, include_errors: bool) -> Result<String> {
+    let messages: Vec<&ChatEntry> = history
+        .iter()
+        .filter(|entry| include_errors || entry.role != "error")
+        .collect();
+
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&messages)
+            .context("Failed to serialize chat history"),
+        ExportFormat::Markdown => Ok(to_markdown(&messages)),
+    }
+}
+
+fn to_markdown(messages: &[&ChatEntry]) -> String {
+    let mut output = String::from("# Chat History\n\n");
+
+    for ChatEntry { role, content, error, .. } in messages {
+        match role.as_str() {
+            "user" => output.push_str(&format!("**You:**\n\n{}\n\n", content)),
+            "error" => {
+                // Quote errors so they stand out from the conversation
+                output.push_str("> **Error:**\n");
+                for line in content.lines() {
+                    output.push_str(&format!("> {}\n", line));
+                }
+                output.push('\n');
+            }
+            _ => {
+                output.push_str(&format!("**LLM:**\n\n{}\n\n", content));
+                if let Some(error) = error {
+                    output.push_str(&format!("> ⚠️ (response interrupted: {})\n\n", error));
+                }
+            }
+        }
+    }
+
+    output
+}
+
+/// Parse a chat history previously written by `export_chat_history` as JSON.
+/// Older exports of plain `[role, content]` pairs are accepted as well.
+pub fn import_chat_history(contents: &str) -> Result<Vec<ChatEntry>> {
+    let history: Vec<ChatEntry> = serde_json::from_str(contents)
+        .context("File is not a valid JSON chat history export")?;
+
+    if let Some(entry) = history
+        .iter()
+        .find(|entry| !matches!(entry.role.as_str(), "user" | "assistant" | "error"))
+    {
+        return Err(anyhow::anyhow!("Unknown message role '{}' in chat history", entry.role));
+    }
+
+    Ok(history)
+}
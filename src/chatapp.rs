@@ -1,526 +1,2861 @@
-use anyhow::Result;
+use anyhow::{Context as _, Result};
+use base64::{Engine as _, engine::general_purpose};
 use eframe::egui;
 use poll_promise::Promise;
-use std::time::Duration;
-use std::sync::mpsc::{self, sync_channel};
-use tokio::runtime::Runtime;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::future::Future;
+use tokio::runtime::Handle;
 
+use crate::chat_export::{self, ExportFormat};
+use crate::comparison::ComparisonSide;
+use crate::config::{AppConfig, EndpointConfig, ThemePreference, WindowGeometry, MIN_WINDOW_SIZE};
+use crate::conversation::{self, Conversation};
 use crate::endpoint_type::EndpointType;
-use crate::llmclient::LLMClient;
-use crate::sdclient::{SDClient, TextToImageRequest, SDModel, LoRA, Sampler};
+use crate::llmclient::{self, ChatEntry, ChatOptions, ClientSettings, EndpointDetection, GenerationStats, ImageInput, LLMClient, OllamaModelCard, PullProgress, ReplyVariant, StreamMessage};
+use crate::logging::{self, LogLevel};
+use crate::png_info;
+use crate::prompt_history::PromptHistory;
+use crate::sampling_preset::SamplingPreset;
+use crate::sd_filename;
+use crate::sd_mask::{self, InpaintingFill, OutpaintSide, SDMask};
+use crate::tabs::{AppTab, TABS};
+use crate::sdclient::{GeneratedImage, SDClient, SDModelOptions, SDProgress, TextToImageRequest, SDModel, LoRA, Sampler, VAE_AUTOMATIC};
 
-const DEFAULT_API_URL: &str = "http://localhost:1234/v1/chat/completions";
-const OLLAMA_API_URL: &str = "http://localhost:11434/v1/chat/completions";
+/// Weights accepted for a LoRA; negative values push the image away from it.
+pub const LORA_WEIGHT_RANGE: std::ops::RangeInclusive<f32> = -1.0..=2.0;
+
+/// Common SD 1.5 and SDXL resolutions offered in the size presets menu.
+pub const SD_SIZE_PRESETS: &[(&str, u32, u32)] = &[
+    ("512 × 512", 512, 512),
+    ("768 × 512", 768, 512),
+    ("512 × 768", 512, 768),
+    ("768 × 768", 768, 768),
+    ("SDXL 1024 × 1024", 1024, 1024),
+    ("SDXL 1152 × 896", 1152, 896),
+    ("SDXL 896 × 1152", 896, 1152),
+    ("SDXL 1216 × 832", 1216, 832),
+    ("SDXL 832 × 1216", 832, 1216),
+];
+
+/// How often the LLM endpoint is pinged to update the connection indicator.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// How long the server settings must stay unchanged before the model list is fetched again.
+const MODEL_REFRESH_DELAY: Duration = Duration::from_millis(600);
+
+/// Asks the chat model to turn the SD prompt into a detailed one; the prompt follows it.
+const SD_REFINE_INSTRUCTION: &str = "Rewrite the following image idea as a detailed Stable Diffusion prompt. \
+    Describe the subject, setting, style, lighting and composition as comma-separated phrases, \
+    followed by quality tags. Reply with only the prompt, without quotes or explanation.\n\n";
+
+/// Bytes of raw response body kept for the developer tools.
+const RAW_STREAM_LOG_LIMIT: usize = 256 * 1024;
+
+/// Last known reachability of the LLM endpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionStatus {
+    Unknown,
+    Online,
+    Offline,
+}
+
+/// Rough token estimate (~4 characters per token) used for context gauges.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Decode an encoded image (PNG, JPEG, ...) into an egui texture.
+pub fn load_texture_from_bytes(ctx: &egui::Context, name: &str, bytes: &[u8]) -> Result<egui::TextureHandle> {
+    Ok(ctx.load_texture(name, decode_color_image(bytes)?, egui::TextureOptions::default()))
+}
+
+fn decode_color_image(bytes: &[u8]) -> Result<egui::ColorImage> {
+    let image = image::load_from_memory(bytes)?;
+    let size = [image.width() as _, image.height() as _];
+    let image_buffer = image.to_rgba8();
+    let pixels = image_buffer.as_flat_samples();
+
+    Ok(egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()))
+}
+
+/// A finished Stable Diffusion image with its texture for display.
+pub struct SDImage {
+    pub generated: GeneratedImage,
+    pub texture: egui::TextureHandle,
+}
+
+/// A generation waiting in the SD queue, captured with the settings at the time it was added.
+pub struct SDQueueItem {
+    /// The prompt as typed, for the queue list.
+    pub prompt: String,
+    pub options: SDModelOptions,
+    pub request: TextToImageRequest,
+}
+
+/// Whether the SD queue is feeding generations into `sd_pending_generation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SDQueueState {
+    Idle,
+    /// The current item is generating, and the next one starts when it finishes.
+    Running,
+    /// Stopped after a failure, an interrupt or by the user, keeping the remaining items.
+    Paused,
+}
+
+/// What a generation produced, with the checkpoint it switched the server to, if any.
+pub struct SDGeneration {
+    pub loaded_options: Option<SDModelOptions>,
+    pub images: Result<Vec<GeneratedImage>>,
+}
+
+/// Source image for img2img, kept as the original file bytes.
+pub struct SDInitImage {
+    pub bytes: Vec<u8>,
+    pub texture: egui::TextureHandle,
+}
+
+/// An image attached to the next chat message, with a thumbnail for the UI.
+pub struct ImageAttachment {
+    pub image: ImageInput,
+    pub texture: egui::TextureHandle,
+}
+
+/// Opening and closing tags models use around their reasoning.
+const REASONING_TAGS: [(&str, &str); 2] = [("<think>", "</think>"), ("<thinking>", "</thinking>")];
+
+/// Part of a reply, either the answer itself or a reasoning section.
+pub enum MessageSegment<'a> {
+    Answer(&'a str),
+    /// `finished` is false while the closing tag hasn't streamed in yet.
+    Reasoning { text: &'a str, finished: bool },
+}
+
+/// Split a reply into answer and reasoning sections.
+pub fn split_reasoning(content: &str) -> Vec<MessageSegment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = content;
+
+    let find_open = |text: &str| REASONING_TAGS.iter()
+        .filter_map(|(open, close)| text.find(open).map(|start| (start, *open, *close)))
+        .min_by_key(|(start, ..)| *start);
+
+    // DeepSeek-R1 style templates put the opening tag in the prompt, so the reply starts
+    // with reasoning and only the closing tag is streamed
+    let first_close = REASONING_TAGS.iter()
+        .filter_map(|(_, close)| rest.find(close).map(|end| (end, *close)))
+        .min_by_key(|(end, _)| *end);
+    if let Some((end, close)) = first_close {
+        if find_open(rest).is_none_or(|(start, ..)| end < start) {
+            segments.push(MessageSegment::Reasoning { text: &rest[..end], finished: true });
+            rest = &rest[end + close.len()..];
+        }
+    }
+
+    while let Some((start, open, close)) = find_open(rest) {
+        if start > 0 {
+            segments.push(MessageSegment::Answer(&rest[..start]));
+        }
+        let body = &rest[start + open.len()..];
+        match body.find(close) {
+            Some(end) => {
+                segments.push(MessageSegment::Reasoning { text: &body[..end], finished: true });
+                rest = &body[end + close.len()..];
+            }
+            None => {
+                segments.push(MessageSegment::Reasoning { text: body, finished: false });
+                rest = "";
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        segments.push(MessageSegment::Answer(rest));
+    }
+    segments
+}
+
+/// Check that `url` is an http(s) URL with a host, as the SD API needs.
+pub fn validate_sd_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid SD API URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("Invalid SD API URL: {} (expected http:// or https://)", url));
+    }
+    Ok(())
+}
+
+/// Put the window back where it was last time. The commands run after the first frame,
+/// once `zoom` applies, and are in UI points, which the zoom scales.
+fn restore_window(ctx: &egui::Context, window: &WindowGeometry, zoom: f32) {
+    let size = egui::Vec2::from(window.size).max(egui::Vec2::from(MIN_WINDOW_SIZE));
+    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size / zoom));
+    if let Some(position) = window.position {
+        ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::Pos2::from(position) / zoom));
+    }
+    if window.maximized {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(true));
+    }
+}
+
+/// Take the value out of `slot` once its promise has resolved.
+fn take_ready<T: Send + 'static>(slot: &mut Option<Promise<T>>) -> Option<T> {
+    match slot.take()?.try_take() {
+        Ok(value) => Some(value),
+        Err(promise) => {
+            *slot = Some(promise);
+            None
+        }
+    }
+}
+
+/// Send the server's progress to `tx` every half second until it reports the generation
+/// finished or `stop` is set.
+async fn poll_sd_progress(sd_client: &SDClient, ctx: &egui::Context, tx: &mpsc::Sender<SDProgress>, stop: &AtomicBool) {
+    let mut last_preview = None;
+    while !stop.load(Ordering::Relaxed) {
+        let mut progress = match sd_client.check_progress().await {
+            Ok(progress) => progress,
+            Err(e) => {
+                // A missed poll only delays the next preview
+                log::warn!("Failed to check progress: {}", e);
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                continue;
+            }
+        };
+        let done = progress.percent >= 100.0;
+
+        // The server only renders a new preview every few steps
+        if progress.preview.is_some() && progress.preview == last_preview {
+            progress.preview = None;
+        } else if progress.preview.is_some() {
+            last_preview = progress.preview.clone();
+        }
+
+        if tx.send(progress).is_err() {
+            // Nobody is waiting for this generation any more
+            return;
+        }
+        ctx.request_repaint();
+
+        if done {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Remove reasoning sections, leaving the visible answer.
+/// An unclosed tag (still streaming) hides everything after it.
+pub fn strip_reasoning(content: &str) -> String {
+    let visible: String = split_reasoning(content).into_iter()
+        .filter_map(|segment| match segment {
+            MessageSegment::Answer(text) => Some(text),
+            MessageSegment::Reasoning { .. } => None,
+        })
+        .collect();
+    visible.trim().to_string()
+}
 
 pub struct ChatApp {
     pub client: LLMClient,
-    pub runtime: Runtime,
+    /// Runs the streaming and generation requests behind the promises below.
+    pub runtime: Handle,
     pub input: String,
-    pub chat_history: Vec<(String, String)>,
-    pub pending_response: Option<Promise<Result<()>>>,
-    pub response_receiver: Option<mpsc::Receiver<String>>,
+    /// Messages of the active conversation, written back to `conversations` on switch and save.
+    pub chat_history: Vec<ChatEntry>,
+    pub conversations: Vec<Conversation>,
+    pub active_conversation: usize,
+    /// Index and draft text of the user message being edited.
+    pub editing_message: Option<(usize, String)>,
+    /// Message shown as plain text so it can be selected in one go.
+    pub plain_text_message: Option<usize>,
+    /// Earlier replies of the turn being regenerated or continued, and where the new reply goes
+    /// among them once it's done.
+    pub replaced_replies: Option<(Vec<ReplyVariant>, usize)>,
+    /// Reply whose variants are shown side by side.
+    pub compare_replies: Option<usize>,
+    pub theme: ThemePreference,
+    pub ui_scale: f32,
+    pub window: Option<WindowGeometry>,
+    /// The window as of the last frame, to notice when it moves or resizes.
+    pub window_seen: Option<WindowGeometry>,
+    pub restore_chat_history: bool,
+    pub interrupt_on_send: bool,
+    pub fence_dropped_text: bool,
+    pub show_reasoning: bool,
+    /// Show each message's time next to its name, not only on hover.
+    pub show_timestamps: bool,
+    pub user_name: String,
+    /// Name shown on replies, empty for the selected model.
+    pub assistant_name: String,
+    pub user_avatar: String,
+    pub assistant_avatar: String,
+    pub developer_tools: bool,
+    pub log_level: LogLevel,
+    pub log_file: String,
+    /// The last chat and SD requests as `curl` commands, only kept with developer tools on.
+    pub last_chat_curl: Option<String>,
+    pub sd_last_curl: Option<String>,
+    pub chat_prompts: PromptHistory,
+    pub sd_prompts: PromptHistory,
+    pub export_format: ExportFormat,
+    pub export_include_errors: bool,
+    pub scroll_to_bottom: bool,
+    /// Whether the chat keeps scrolling to new output; off while the user reads further up.
+    pub follow_chat: bool,
+    pub pending_response: Option<Promise<Result<GenerationStats>>>,
+    /// Frame of the last send, so Enter and the Send button firing together only send once.
+    pub last_send_frame: Option<u64>,
+    pub response_receiver: Option<mpsc::Receiver<StreamMessage>>,
+    /// Show the response body as it arrives, for the developer tools.
+    pub raw_stream: bool,
+    /// Raw body of the last reply while `raw_stream` is on.
+    pub raw_stream_log: String,
+    /// Name typed into the Ollama "Pull model" field.
+    pub pull_model_input: String,
+    pub pending_pull: Option<Promise<Result<()>>>,
+    pub pull_receiver: Option<mpsc::Receiver<PullProgress>>,
+    pub pull_progress: Option<PullProgress>,
+    pub pull_cancel: Option<Arc<AtomicBool>>,
+    /// How the last pull ended, as a message or an error.
+    pub pull_result: Option<Result<String, String>>,
+    /// Model waiting for the user to confirm its deletion.
+    pub confirm_delete_model: Option<String>,
+    pub pending_delete: Option<Promise<Result<String>>>,
+    /// The model shown in the model info window, with its info once it has loaded.
+    pub model_card: Option<(String, Option<OllamaModelCard>)>,
+    pub pending_model_card: Option<Promise<Result<OllamaModelCard>>>,
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+    pub attached_image: Option<ImageAttachment>,
     pub current_response: String,
+    /// Further replies streaming in when several were asked for, by choice index - 1;
+    /// empty until their first text arrives.
+    pub current_alternatives: Vec<String>,
+    /// The prefill the streaming replies started from, which the further ones begin with too.
+    pub current_prefill: String,
+    /// When the streaming reply last grew, so its cursor stays solid while text arrives.
+    pub response_updated: Option<Instant>,
     pub show_settings: bool,
     pub protocol: String,
     pub server: String,
     pub port: String,
     pub endpoint: String,
     pub endpoint_type: EndpointType,
+    pub api_key: String,
+    /// Extra request headers as typed, including invalid rows so they can be fixed.
+    pub custom_headers: Vec<(String, String)>,
+    pub connect_timeout: u64,
+    pub request_timeout: u64,
+    pub retry_attempts: u32,
+    pub ollama_generate: bool,
+    pub accept_invalid_certs: bool,
+    pub disable_streaming: bool,
     pub available_models: Vec<String>,
+    /// Search text in the model dropdown, cleared when it closes.
+    pub model_filter: String,
     pub selected_model: String,
-    pub models_loading: bool,
+    /// Saved settings of the other endpoint types, restored when switching back to one.
+    pub endpoint_configs: HashMap<EndpointType, EndpointConfig>,
+    /// Model list being fetched for the settings window.
+    pub pending_models: Option<Promise<Result<Vec<String>>>>,
+    pub pending_detection: Option<Promise<Result<EndpointDetection>>>,
+    /// What the last Detect found, until the connection settings change.
+    pub detection_message: Option<String>,
+    /// When to fetch the model list again after the client settings changed.
+    pub model_refresh_due: Option<Instant>,
+    pub connection_status: ConnectionStatus,
+    pub last_health_check: Option<Instant>,
+    pub pending_health_check: Option<Promise<bool>>,
+    pub model_context_lengths: HashMap<String, u32>,
+    pub context_length_requested: Option<String>,
+    /// Context length of a model, once the server has reported it.
+    pub pending_context_length: Option<Promise<Option<(String, u32)>>>,
     pub error_message: Option<String>,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub top_k: u32,
+    pub max_tokens: u32,
+    pub presence_penalty: f32,
+    pub frequency_penalty: f32,
+    pub stop_sequences: Vec<String>,
+    pub max_history_messages: u32,
+    /// Ollama's context window, 0 for the server default.
+    pub num_ctx: u32,
+    pub json_mode: bool,
+    pub completions: u32,
+    /// Start of the next reply, written for the model to continue.
+    pub prefill: String,
+    /// Keep the prefill for every message instead of clearing it after one.
+    pub prefill_sticky: bool,
+    pub custom_presets: Vec<SamplingPreset>,
+    pub preset_name_input: String,
+    /// Index into `tabs::TABS`.
     pub active_tab: usize,
     pub active_settings_tab: usize,
+    /// The second model answering the same prompts in comparison mode, None when not comparing.
+    pub comparison: Option<ComparisonSide>,
+    /// Whether the command palette is showing, with its filter and highlighted entry.
+    pub command_palette_open: bool,
+    pub command_palette_query: String,
+    pub command_palette_selected: usize,
     pub sd_client: SDClient,
+    /// The SD API URL as typed; only applied to `sd_client` once it is valid.
+    pub sd_url_input: String,
+    pub sd_connection_status: Option<String>,
+    /// Outcome of "Test SD Connection", as a status message or an error.
+    pub sd_connection_pending: Option<Promise<Result<String>>>,
+    /// Parameters embedded in a dropped image, if it has any.
+    pub sd_png_info_pending: Option<Promise<Result<Option<String>>>>,
     pub sd_prompt: String,
+    /// The chat model's rewrite of the SD prompt, on its way.
+    pub sd_refine_pending: Option<Promise<Result<String>>>,
     pub sd_generating: bool,
     pub sd_progress: f32,
-    pub sd_image_bytes: Option<Vec<u8>>,
-    pub sd_image_texture: Option<egui::TextureHandle>,
-    pub sd_pending_generation: Option<Promise<Result<Vec<u8>>>>,
+    pub sd_eta: f32,
+    pub sd_preview_texture: Option<egui::TextureHandle>,
+    pub sd_images: Vec<SDImage>,
+    pub sd_selected_image: usize,
+    pub sd_pending_generation: Option<Promise<SDGeneration>>,
+    pub sd_progress_receiver: Option<mpsc::Receiver<SDProgress>>,
+    pub sd_generation_started: Option<Instant>,
+    pub sd_queue: VecDeque<SDQueueItem>,
+    pub sd_queue_state: SDQueueState,
+    /// The queue item generating right now, put back at the front if it fails.
+    pub sd_queue_current: Option<SDQueueItem>,
+    /// Items finished in this queue run; their images are kept in the gallery together.
+    pub sd_queue_finished: usize,
+    /// How long the images on display took to generate.
+    pub sd_generation_time: Option<Duration>,
+    /// Upscaled bytes of the gallery image at the given index.
+    pub sd_pending_upscale: Option<(usize, Promise<Result<Vec<u8>>>)>,
+    pub sd_upscalers: Vec<String>,
+    pub sd_upscalers_pending: Option<Promise<Result<Vec<String>>>>,
+    pub sd_selected_upscaler: String,
+    pub sd_upscale_factor: f32,
+    pub sd_stop_flag: Option<Arc<AtomicBool>>,
+    pub sd_interrupt_pending: Option<Promise<Result<()>>>,
     pub sd_error_message: Option<String>,
+    /// Model options and request of the last generation, kept for a retry until it succeeds.
+    pub sd_last_request: Option<(SDModelOptions, TextToImageRequest)>,
+    /// Checkpoint and VAE this app last switched the server to.
+    pub sd_loaded_options: Option<SDModelOptions>,
     pub sd_models: Vec<SDModel>,
+    pub sd_model_filter: String,
     pub sd_selected_model: String,
     pub sd_loras: Vec<LoRA>,
-    pub sd_selected_lora: Option<String>,
-    pub sd_lora_weight: f32,
+    /// LoRAs added to the prompt, by name with their weight.
+    pub sd_selected_loras: Vec<(String, f32)>,
     pub sd_samplers: Vec<Sampler>,
     pub sd_selected_sampler: String,
     pub sd_schedulers: Vec<String>,
     pub sd_selected_scheduler: String,
-    pub sd_schedulers_loading: bool,
+    pub sd_schedulers_pending: Option<Promise<Result<Vec<String>>>>,
+    pub sd_vaes: Vec<String>,
+    pub sd_selected_vae: String,
+    pub sd_vaes_pending: Option<Promise<Result<Vec<String>>>>,
+    pub sd_seed: Option<i64>,
+    pub sd_batch_size: u32,
+    pub sd_batch_count: u32,
+    pub sd_embed_metadata: bool,
+    /// Folder the save dialogs start in, or quick saves go to.
+    pub sd_save_folder: String,
+    pub sd_filename_template: String,
+    /// Save straight into `sd_save_folder` without asking.
+    pub sd_quick_save: bool,
+    pub sd_save_status: Option<String>,
+    /// Opened on the first image copy and kept, as on X11 the image is only offered while it lives.
+    pub image_clipboard: Option<arboard::Clipboard>,
+    pub sd_init_image: Option<SDInitImage>,
+    pub sd_img2img_denoising: f32,
+    /// Inpainting mask over `sd_init_image`, only sent when something is painted on it.
+    pub sd_mask: Option<SDMask>,
+    pub sd_mask_editing: bool,
+    /// Brush radius in source image pixels.
+    pub sd_mask_brush: f32,
+    pub sd_mask_erase: bool,
+    pub sd_mask_blur: u32,
+    pub sd_inpainting_fill: InpaintingFill,
+    pub sd_inpaint_full_res: bool,
+    pub sd_inpaint_padding: u32,
+    pub sd_outpaint_amount: u32,
+    pub sd_tiling: bool,
+    pub sd_restore_faces: bool,
+    pub sd_face_restorers: Vec<String>,
+    pub sd_face_restorers_pending: Option<Promise<Result<Vec<String>>>>,
+    pub sd_selected_face_restorer: String,
+    /// CodeFormer's fidelity, 0 for the strongest restoration and 1 for the least.
+    pub sd_codeformer_weight: f32,
+    pub sd_hires_enabled: bool,
+    pub sd_hr_scale: f32,
+    pub sd_hr_upscaler: String,
+    pub sd_denoising_strength: f32,
     pub sd_steps: u32,
     pub sd_cfg_scale: f32,
     pub sd_width: u32,
     pub sd_height: u32,
     pub sd_negative_prompt: String,
-    pub sd_models_loading: bool,
-    pub sd_loras_loading: bool,
-    pub sd_samplers_loading: bool,
+    pub sd_models_pending: Option<Promise<Result<Vec<SDModel>>>>,
+    pub sd_loras_pending: Option<Promise<Result<Vec<LoRA>>>>,
+    pub sd_samplers_pending: Option<Promise<Result<Vec<Sampler>>>>,
+    /// Set when loading the SD options failed, so they aren't requested again every frame.
+    pub sd_options_failed: bool,
 }
 
 impl ChatApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let endpoint_type = EndpointType::Ollama;
-        let protocol = "http".to_string();
-        let server = "localhost".to_string();
-        let port = "11434".to_string();
-        let endpoint = "v1/chat/completions".to_string();
+        let config = AppConfig::load(cc.storage);
+        let endpoint_type = config.endpoint_type;
+        let protocol = config.protocol;
+        let server = config.server;
+        let port = config.port;
+        let endpoint = config.endpoint;
+        let mut conversations = if config.restore_chat_history {
+            config.conversations
+        } else {
+            Vec::new()
+        };
+        // Saves from before conversations existed hold a single history
+        if conversations.is_empty() && config.restore_chat_history && !config.chat_history.is_empty() {
+            let mut migrated = Conversation::new(0);
+            migrated.history = config.chat_history;
+            migrated.auto_title();
+            conversations.push(migrated);
+        }
+        if conversations.is_empty() {
+            conversations.push(Conversation::new(0));
+        }
+        let active_conversation = config.active_conversation.min(conversations.len() - 1);
+        let chat_history = conversations[active_conversation].history.clone();
+
+        cc.egui_ctx.set_zoom_factor(config.ui_scale);
+        if let Some(window) = &config.window {
+            restore_window(&cc.egui_ctx, window, config.ui_scale);
+        }
+
+        logging::set_level(config.log_level);
+        logging::redact(config.api_key.trim());
+        let log_file_error = logging::set_log_file(&config.log_file).err().map(|e| format!("{:#}", e));
         
         Self {
-            client: LLMClient::new(protocol.clone(), server.clone(), port.clone(), endpoint.clone(), endpoint_type),
-            runtime: Runtime::new().unwrap(),
+            client: LLMClient::new(
+                protocol.clone(),
+                server.clone(),
+                port.clone(),
+                endpoint.clone(),
+                endpoint_type,
+                ClientSettings {
+                    api_key: Some(config.api_key.trim().to_string()).filter(|key| !key.is_empty()),
+                    connect_timeout: config.connect_timeout,
+                    request_timeout: config.request_timeout,
+                    retry_attempts: config.retry_attempts,
+                    ollama_generate: config.ollama_generate,
+                    custom_headers: llmclient::header_map(&config.custom_headers),
+                    accept_invalid_certs: config.accept_invalid_certs,
+                    disable_streaming: config.disable_streaming,
+                },
+            ),
+            runtime: Handle::current(),
             input: String::new(),
-            chat_history: Vec::new(),
+            chat_history,
+            conversations,
+            active_conversation,
+            editing_message: None,
+            replaced_replies: None,
+            compare_replies: None,
+            plain_text_message: None,
+            theme: config.theme,
+            ui_scale: config.ui_scale,
+            window: config.window,
+            window_seen: None,
+            restore_chat_history: config.restore_chat_history,
+            interrupt_on_send: config.interrupt_on_send,
+            fence_dropped_text: config.fence_dropped_text,
+            show_reasoning: config.show_reasoning,
+            show_timestamps: config.show_timestamps,
+            user_name: config.user_name,
+            assistant_name: config.assistant_name,
+            user_avatar: config.user_avatar,
+            assistant_avatar: config.assistant_avatar,
+            developer_tools: config.developer_tools,
+            log_level: config.log_level,
+            log_file: config.log_file,
+            last_chat_curl: None,
+            sd_last_curl: None,
+            chat_prompts: config.chat_prompts,
+            sd_prompts: config.sd_prompts,
+            export_format: ExportFormat::Markdown,
+            export_include_errors: false,
+            scroll_to_bottom: false,
+            follow_chat: true,
             pending_response: None,
+            last_send_frame: None,
             response_receiver: None,
+            raw_stream: false,
+            raw_stream_log: String::new(),
+            pull_model_input: String::new(),
+            pending_pull: None,
+            pull_receiver: None,
+            pull_progress: None,
+            pull_cancel: None,
+            pull_result: None,
+            confirm_delete_model: None,
+            pending_delete: None,
+            model_card: None,
+            pending_model_card: None,
+            cancel_flag: None,
+            attached_image: None,
             current_response: String::new(),
+            current_alternatives: Vec::new(),
+            current_prefill: String::new(),
+            response_updated: None,
             show_settings: true,
             protocol,
             server,
             port,
             endpoint,
             endpoint_type,
+            api_key: config.api_key,
+            custom_headers: config.custom_headers,
+            connect_timeout: config.connect_timeout,
+            request_timeout: config.request_timeout,
+            retry_attempts: config.retry_attempts,
+            ollama_generate: config.ollama_generate,
+            accept_invalid_certs: config.accept_invalid_certs,
+            disable_streaming: config.disable_streaming,
             available_models: Vec::new(),
-            selected_model: "local-model".to_string(),
-            models_loading: false,
-            error_message: None,
+            model_filter: String::new(),
+            selected_model: config.selected_model,
+            endpoint_configs: config.endpoint_configs,
+            pending_models: None,
+            pending_detection: None,
+            detection_message: None,
+            model_refresh_due: None,
+            connection_status: ConnectionStatus::Unknown,
+            last_health_check: None,
+            pending_health_check: None,
+            model_context_lengths: config.model_context_lengths,
+            context_length_requested: None,
+            pending_context_length: None,
+            error_message: log_file_error,
+            temperature: config.temperature,
+            top_p: config.top_p,
+            top_k: config.top_k,
+            max_tokens: config.max_tokens,
+            presence_penalty: config.presence_penalty,
+            frequency_penalty: config.frequency_penalty,
+            stop_sequences: config.stop_sequences,
+            max_history_messages: config.max_history_messages,
+            num_ctx: config.num_ctx,
+            json_mode: config.json_mode,
+            completions: config.completions,
+            prefill: config.prefill,
+            prefill_sticky: config.prefill_sticky,
+            custom_presets: config.custom_presets,
+            preset_name_input: String::new(),
             active_tab: 0,
             active_settings_tab: 0,
-            sd_client: SDClient::new("http://localhost:7860".to_string()),
+            comparison: None,
+            command_palette_open: false,
+            command_palette_query: String::new(),
+            command_palette_selected: 0,
+            sd_url_input: config.sd_base_url.clone(),
+            sd_client: SDClient::new(config.sd_base_url),
+            sd_connection_status: None,
+            sd_connection_pending: None,
+            sd_png_info_pending: None,
             sd_prompt: String::new(),
             sd_generating: false,
             sd_progress: 0.0,
-            sd_image_bytes: None,
-            sd_image_texture: None,
+            sd_eta: 0.0,
+            sd_preview_texture: None,
+            sd_images: Vec::new(),
+            sd_selected_image: 0,
             sd_pending_generation: None,
+            sd_progress_receiver: None,
+            sd_refine_pending: None,
+            sd_generation_started: None,
+            sd_queue: VecDeque::new(),
+            sd_queue_state: SDQueueState::Idle,
+            sd_queue_current: None,
+            sd_queue_finished: 0,
+            sd_generation_time: None,
+            sd_pending_upscale: None,
+            sd_upscalers: Vec::new(),
+            sd_upscalers_pending: None,
+            sd_selected_upscaler: "R-ESRGAN 4x+".to_string(),
+            sd_upscale_factor: 2.0,
+            sd_stop_flag: None,
+            sd_interrupt_pending: None,
             sd_error_message: None,
+            sd_last_request: None,
+            sd_loaded_options: None,
             sd_models: Vec::new(),
+            sd_model_filter: String::new(),
             sd_selected_model: "".to_string(),
             sd_loras: Vec::new(),
-            sd_selected_lora: None,
-            sd_lora_weight: 0.7,
+            sd_selected_loras: Vec::new(),
             sd_samplers: Vec::new(),
             sd_selected_sampler: "Euler a".to_string(),
             sd_schedulers: Vec::new(),
             sd_selected_scheduler: "Automatic".to_string(),
-            sd_schedulers_loading: false,
+            sd_schedulers_pending: None,
+            sd_vaes: Vec::new(),
+            sd_selected_vae: VAE_AUTOMATIC.to_string(),
+            sd_vaes_pending: None,
+            sd_seed: None,
+            sd_batch_size: 1,
+            sd_batch_count: 1,
+            sd_embed_metadata: true,
+            sd_save_folder: config.sd_save_folder,
+            sd_filename_template: config.sd_filename_template,
+            sd_quick_save: config.sd_quick_save,
+            sd_save_status: None,
+            image_clipboard: None,
+            sd_init_image: None,
+            sd_img2img_denoising: 0.75,
+            sd_mask: None,
+            sd_mask_editing: false,
+            sd_mask_brush: 24.0,
+            sd_mask_erase: false,
+            sd_mask_blur: 4,
+            sd_inpainting_fill: InpaintingFill::Original,
+            sd_inpaint_full_res: false,
+            sd_inpaint_padding: 32,
+            sd_outpaint_amount: 128,
+            sd_tiling: false,
+            sd_restore_faces: false,
+            sd_face_restorers: Vec::new(),
+            sd_face_restorers_pending: None,
+            sd_selected_face_restorer: String::new(),
+            sd_codeformer_weight: 0.5,
+            sd_hires_enabled: false,
+            sd_hr_scale: 2.0,
+            sd_hr_upscaler: "Latent".to_string(),
+            sd_denoising_strength: 0.55,
             sd_steps: 20,
             sd_cfg_scale: 7.0,
             sd_width: 512,
             sd_height: 512,
             sd_negative_prompt: "blurry, low quality, deformed, distorted".to_string(),
-            sd_models_loading: false,
-            sd_loras_loading: false,
-            sd_samplers_loading: false,
+            sd_models_pending: None,
+            sd_loras_pending: None,
+            sd_samplers_pending: None,
+            sd_options_failed: false,
+        }
+    }
+
+    pub fn to_config(&self) -> AppConfig {
+        AppConfig {
+            endpoint_type: self.endpoint_type,
+            api_key: self.api_key.clone(),
+            custom_headers: self.custom_headers.clone(),
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
+            retry_attempts: self.retry_attempts,
+            ollama_generate: self.ollama_generate,
+            accept_invalid_certs: self.accept_invalid_certs,
+            disable_streaming: self.disable_streaming,
+            protocol: self.protocol.clone(),
+            server: self.server.clone(),
+            port: self.port.clone(),
+            endpoint: self.endpoint.clone(),
+            selected_model: self.selected_model.clone(),
+            endpoint_configs: self.endpoint_configs.clone(),
+            model_context_lengths: self.model_context_lengths.clone(),
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            max_tokens: self.max_tokens,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            stop_sequences: self.stop_sequences.clone(),
+            max_history_messages: self.max_history_messages,
+            num_ctx: self.num_ctx,
+            json_mode: self.json_mode,
+            completions: self.completions,
+            // A one-off prefill belongs to the message it was typed for
+            prefill: if self.prefill_sticky { self.prefill.clone() } else { String::new() },
+            prefill_sticky: self.prefill_sticky,
+            custom_presets: self.custom_presets.clone(),
+            sd_base_url: self.sd_client.base_url.clone(),
+            sd_save_folder: self.sd_save_folder.clone(),
+            sd_filename_template: self.sd_filename_template.clone(),
+            sd_quick_save: self.sd_quick_save,
+            theme: self.theme,
+            ui_scale: self.ui_scale,
+            window: self.window,
+            restore_chat_history: self.restore_chat_history,
+            interrupt_on_send: self.interrupt_on_send,
+            fence_dropped_text: self.fence_dropped_text,
+            show_reasoning: self.show_reasoning,
+            show_timestamps: self.show_timestamps,
+            user_name: self.user_name.clone(),
+            assistant_name: self.assistant_name.clone(),
+            user_avatar: self.user_avatar.clone(),
+            assistant_avatar: self.assistant_avatar.clone(),
+            developer_tools: self.developer_tools,
+            log_level: self.log_level,
+            log_file: self.log_file.clone(),
+            chat_prompts: self.chat_prompts.clone(),
+            sd_prompts: self.sd_prompts.clone(),
+            conversations: if self.restore_chat_history {
+                let mut conversations = self.conversations.clone();
+                conversations[self.active_conversation].history = self.chat_history.clone();
+                conversations
+            } else {
+                Vec::new()
+            },
+            active_conversation: self.active_conversation,
+            chat_history: Vec::new(),
         }
     }
 
     pub fn refresh_models(&mut self, ctx: &egui::Context) {
-        self.models_loading = true;
         self.error_message = None;  // Clear any previous errors
+        self.model_refresh_due = None;
         
         let client = self.client.clone();
-        let ctx = ctx.clone();
-        
-        tokio::spawn(async move {
-            match client.list_models().await {
-                Ok(models) => {
-                    ctx.memory_mut(|mem| {
-                        mem.data.insert_temp(egui::Id::new("available_models").into(), models);
-                    });
-                }
-                Err(e) => {
-                    let error_msg = format!("Failed to fetch models: {}", e);
-                    println!("{}", error_msg);
-                    ctx.memory_mut(|mem| {
-                        mem.data.insert_temp(egui::Id::new("models_error").into(), error_msg);
-                    });
-                }
-            }
-        });
+        self.pending_models = Some(self.spawn_promise(ctx, async move {
+            client.list_models().await
+        }));
     }
 
-    pub fn send_message(&mut self, _ctx: &egui::Context) {
-        if self.input.is_empty() || self.pending_response.is_some() {
+    /// Probe the server in the connection settings for the API it speaks.
+    pub fn detect_endpoint_type(&mut self, ctx: &egui::Context) {
+        if self.pending_detection.is_some() {
             return;
         }
-
-        let prompt = std::mem::take(&mut self.input);
-        self.chat_history.push(("user".to_string(), prompt.clone()));
+        self.error_message = None;
+        self.detection_message = None;
 
         let client = self.client.clone();
-        let model = self.selected_model.clone();
-        let chat_history = self.chat_history.clone();
-        
-        // Create a channel with a large buffer for fast chunks
-        let (tx, rx) = sync_channel(16384); // 16K buffer
-        self.response_receiver = Some(rx);
-        
-        self.pending_response = Some(Promise::spawn_thread("llm_response".to_string(), move || {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async move {
-                client.chat_stream(&chat_history, &prompt, &model, tx).await
-            })
+        self.pending_detection = Some(self.spawn_promise(ctx, async move {
+            client.detect_endpoint_type().await
         }));
     }
 
-    pub fn reset_to_defaults(&mut self) {
-        self.protocol = "http".to_string();
-        self.server = "localhost".to_string();
-        self.port = "11434".to_string();
-        self.endpoint = "v1/chat/completions".to_string();
-        self.client = LLMClient::new(
-            self.protocol.clone(),
-            self.server.clone(),
-            self.port.clone(),
-            self.endpoint.clone(),
-            self.endpoint_type
-        );
-    }
+    /// Switch to the endpoint type Detect found, keeping the server it was found on.
+    pub fn process_endpoint_detection(&mut self, ctx: &egui::Context) {
+        let Some(result) = take_ready(&mut self.pending_detection) else {
+            return;
+        };
 
-    pub fn update_endpoint_type(&mut self, new_endpoint_type: EndpointType) {
-        self.endpoint_type = new_endpoint_type;
-        self.port = new_endpoint_type.default_port().to_string();
-        self.endpoint = new_endpoint_type.default_endpoint().to_string();
-        self.selected_model = "local-model".to_string();
-        self.available_models.clear();
+        let detection = match result {
+            Ok(detection) => detection,
+            Err(e) => {
+                log::error!("Endpoint detection failed: {}", e);
+                self.error_message = Some(format!("Failed to detect the server type: {}", e));
+                return;
+            }
+        };
+
+        log::info!("{}", detection.summary());
+        if detection.endpoint_type != self.endpoint_type {
+            // Unlike switching by hand, the server entered belongs to the new type rather than
+            // being remembered for the old one, and the new type's remembered server is replaced
+            self.endpoint_configs.remove(&detection.endpoint_type);
+            self.endpoint_type = detection.endpoint_type;
+            self.endpoint = detection.endpoint_type.default_endpoint_under(&self.endpoint);
+            // The model remembered for this type may not be on this server
+            self.selected_model = "local-model".to_string();
+            self.available_models.clear();
+            self.update_client_url();
+            self.refresh_models(ctx);
+        }
+        self.detection_message = Some(detection.summary());
     }
 
-    pub fn clear_chat(&mut self) {
-        self.chat_history.clear();
-        self.current_response.clear();
-        self.input.clear();
-        self.pending_response = None;
-        self.response_receiver = None;
-        self.error_message = None;
+    /// Have the Ollama server download the model named in `pull_model_input`.
+    pub fn pull_model(&mut self, ctx: &egui::Context) {
+        let model = self.pull_model_input.trim().to_string();
+        if model.is_empty() || self.pending_pull.is_some() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.pull_receiver = Some(rx);
+        self.pull_cancel = Some(cancel.clone());
+        self.pull_progress = None;
+        self.pull_result = None;
+
+        let client = self.client.clone();
+        self.pending_pull = Some(self.spawn_promise(ctx, async move {
+            client.pull_model(&model, tx, cancel).await
+        }));
     }
 
-    pub fn update_client_url(&mut self) {
-        self.client = LLMClient::new(
-            self.protocol.clone(),
-            self.server.clone(),
-            self.port.clone(),
-            self.endpoint.clone(),
-            self.endpoint_type
-        );
+    pub fn cancel_pull(&mut self) {
+        if let Some(cancel) = &self.pull_cancel {
+            cancel.store(true, Ordering::Relaxed);
+        }
     }
 
-    pub fn process_response_chunks(&mut self, ctx: &egui::Context) {
-        if let Some(rx) = &self.response_receiver {
-            if let Ok(new_content) = rx.try_recv() {
-                self.current_response.push_str(&new_content);
-                ctx.request_repaint();
+    /// Follow a running pull, refreshing the model list once it has finished.
+    pub fn process_model_pull(&mut self, ctx: &egui::Context) {
+        if let Some(rx) = &self.pull_receiver {
+            if let Some(progress) = rx.try_iter().last() {
+                self.pull_progress = Some(progress);
             }
         }
 
-        if let Some(promise) = &self.pending_response {
-            if let Some(result) = promise.ready() {
-                match result {
-                    Err(e) => {
-                        if self.current_response.is_empty() {
-                            self.chat_history.push(("error".to_string(), format!("Error: {}", e)));
-                        } else {
-                            self.chat_history.push(("assistant".to_string(), self.current_response.clone()));
-                        }
-                    }
-                    Ok(()) => {
-                        if !self.current_response.is_empty() {
-                            self.chat_history.push(("assistant".to_string(), self.current_response.clone()));
-                        }
-                    }
-                }
-                self.current_response.clear();
-                self.pending_response = None;
-                self.response_receiver = None;
-                ctx.request_repaint();
+        let Some(result) = take_ready(&mut self.pending_pull) else {
+            return;
+        };
+        self.pull_receiver = None;
+        self.pull_cancel = None;
+        self.pull_progress = None;
+
+        match result {
+            Ok(()) => {
+                let model = self.pull_model_input.trim().to_string();
+                self.pull_result = Some(Ok(format!("Pulled {}", model)));
+                self.pull_model_input.clear();
+                self.refresh_models(ctx);
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                self.pull_result = Some(Err(e.to_string()));
             }
         }
     }
 
-    pub fn load_sd_options(&mut self, ctx: &egui::Context) {
-        // Loading models
-        if self.sd_models.is_empty() && !self.sd_models_loading {
-            self.sd_models_loading = true;
-            
-            let sd_client = self.sd_client.clone();
-            let ctx_clone = ctx.clone();
-            
-            tokio::spawn(async move {
-                match sd_client.get_available_models().await {
-                    Ok(models) => {
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_models"), models);
-                        });
-                    }
-                    Err(e) => {
-                        println!("Failed to fetch SD models: {}", e);
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_models_error"), format!("{}", e));
-                        });
-                    }
+    /// Delete the model awaiting confirmation from the Ollama server.
+    pub fn delete_model(&mut self, ctx: &egui::Context) {
+        let Some(model) = self.confirm_delete_model.take() else {
+            return;
+        };
+        let client = self.client.clone();
+        self.pending_delete = Some(self.spawn_promise(ctx, async move {
+            client.delete_model(&model).await.map(|()| model)
+        }));
+    }
+
+    /// Refresh the model list once a deletion has gone through.
+    pub fn process_model_delete(&mut self, ctx: &egui::Context) {
+        let Some(result) = take_ready(&mut self.pending_delete) else {
+            return;
+        };
+
+        match result {
+            Ok(model) => {
+                log::info!("Deleted {}", model);
+                if self.selected_model == model {
+                    // Lets the refresh select the first remaining model
+                    self.selected_model = "local-model".to_string();
                 }
-            });
-        }
-        
-        // Loading LoRAs
-        if self.sd_loras.is_empty() && !self.sd_loras_loading {
-            self.sd_loras_loading = true;
-            
-            let sd_client = self.sd_client.clone();
-            let ctx_clone = ctx.clone();
-            
-            tokio::spawn(async move {
-                match sd_client.get_available_loras().await {
-                    Ok(loras) => {
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_loras"), loras);
-                        });
-                    }
-                    Err(e) => {
-                        println!("Failed to fetch LoRAs: {}", e);
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_loras_error"), format!("{}", e));
-                        });
-                    }
+                if self.model_card.as_ref().is_some_and(|(name, _)| *name == model) {
+                    self.model_card = None;
                 }
-            });
+                self.refresh_models(ctx);
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                self.error_message = Some(e.to_string());
+            }
         }
-        
-        // Loading samplers
-        if self.sd_samplers.is_empty() && !self.sd_samplers_loading {
-            self.sd_samplers_loading = true;
-            
-            let sd_client = self.sd_client.clone();
-            let ctx_clone = ctx.clone();
-            
-            tokio::spawn(async move {
-                match sd_client.get_available_samplers().await {
-                    Ok(samplers) => {
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_samplers"), samplers);
-                        });
-                    }
-                    Err(e) => {
-                        println!("Failed to fetch samplers: {}", e);
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_samplers_error"), format!("{}", e));
-                        });
-                    }
+    }
+
+    /// Open the model info window for the selected model and fetch what Ollama knows about it.
+    pub fn show_model_card(&mut self, ctx: &egui::Context) {
+        let model = self.selected_model.clone();
+        self.model_card = Some((model.clone(), None));
+        let client = self.client.clone();
+        self.pending_model_card = Some(self.spawn_promise(ctx, async move {
+            client.show_model(&model).await
+        }));
+    }
+
+    pub fn process_model_card(&mut self) {
+        let Some(result) = take_ready(&mut self.pending_model_card) else {
+            return;
+        };
+
+        match result {
+            Ok(card) => {
+                if let Some((_, slot)) = &mut self.model_card {
+                    *slot = Some(card);
                 }
-            });
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                self.error_message = Some(e.to_string());
+                self.model_card = None;
+            }
         }
-        
-        // Load scheduler types based on the selected sampler
-        if !self.sd_schedulers_loading && !self.sd_selected_sampler.is_empty() {
-            self.sd_schedulers_loading = true;
-            
-            let sd_client = self.sd_client.clone();
-            let ctx_clone = ctx.clone();
-            let sampler_name = self.sd_selected_sampler.clone();
-            
-            tokio::spawn(async move {
-                match sd_client.get_available_schedulers(&sampler_name).await {
-                    Ok(schedulers) => {
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_schedulers"), schedulers);
-                        });
-                    }
-                    Err(e) => {
-                        println!("Failed to fetch schedulers: {}", e);
-                        ctx_clone.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_schedulers_error"), format!("{}", e));
-                        });
-                    }
-                }
-            });
+    }
+
+    /// Remember the window's size and position for the next launch.
+    pub fn track_window(&mut self, ctx: &egui::Context) {
+        let zoom = ctx.zoom_factor();
+        let (inner, outer, maximized, minimized) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (viewport.inner_rect, viewport.outer_rect, viewport.maximized == Some(true), viewport.minimized == Some(true))
+        });
+        let Some(inner) = inner.filter(|_| !minimized) else {
+            return;
+        };
+        let current = WindowGeometry {
+            size: (inner.size() * zoom).into(),
+            position: outer.map(|outer| (outer.min.to_vec2() * zoom).into()),
+            maximized,
+        };
+        // Only changes count, so the default window shown before the restore applies isn't saved
+        if self.window_seen.replace(current).is_none_or(|seen| seen == current) {
+            return;
         }
-        
-        // Check if data is ready and process it
-        if let Some(models) = ctx.memory_mut(|mem| mem.data.remove_temp::<Vec<SDModel>>(egui::Id::new("sd_models"))) {
-            self.sd_models = models;
-            self.sd_models_loading = false;
-            
-            if !self.sd_models.is_empty() && self.sd_selected_model.is_empty() {
-                self.sd_selected_model = self.sd_models[0].model_name.clone();
-            }
+
+        match &mut self.window {
+            // Keep the size to restore to, not the maximized one
+            Some(window) if maximized => window.maximized = true,
+            window => *window = Some(current),
         }
-        
-        if let Some(loras) = ctx.memory_mut(|mem| mem.data.remove_temp::<Vec<LoRA>>(egui::Id::new("sd_loras"))) {
-            self.sd_loras = loras;
-            self.sd_loras_loading = false;
+    }
+
+    /// Fetch the model list once the client settings have stopped changing for a moment.
+    pub fn process_scheduled_model_refresh(&mut self, ctx: &egui::Context) {
+        let Some(due) = self.model_refresh_due else {
+            return;
+        };
+
+        let remaining = due.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            self.refresh_models(ctx);
+        } else {
+            ctx.request_repaint_after(remaining);
         }
-        
-        if let Some(samplers) = ctx.memory_mut(|mem| mem.data.remove_temp::<Vec<Sampler>>(egui::Id::new("sd_samplers"))) {
-            self.sd_samplers = samplers;
-            self.sd_samplers_loading = false;
-            
-            // Select Euler a by default if available
-            if self.sd_selected_sampler.is_empty() {
-                self.sd_selected_sampler = self.sd_samplers
-                    .iter()
-                    .find(|s| s.name == "Euler a")
-                    .map(|s| s.name.clone())
-                    .unwrap_or_else(|| {
-                        if !self.sd_samplers.is_empty() {
-                            self.sd_samplers[0].name.clone()
-                        } else {
-                            "Euler a".to_string()
-                        }
-                    });
+    }
+
+    /// Pick up the model list once `refresh_models` has it.
+    pub fn process_model_refresh(&mut self) {
+        let Some(result) = take_ready(&mut self.pending_models) else {
+            return;
+        };
+
+        match result {
+            Ok(models) => {
+                self.available_models = models;
+                self.connection_status = ConnectionStatus::Online;
+
+                // Select the first model if none selected
+                if self.selected_model == "local-model" && !self.available_models.is_empty() {
+                    self.selected_model = self.available_models[0].clone();
+                }
             }
-            
-            // When samplers are loaded or changed, we reload schedulers
-            self.sd_schedulers_loading = false;
-        }
-        
-        if let Some(schedulers) = ctx.memory_mut(|mem| mem.data.remove_temp::<Vec<String>>(egui::Id::new("sd_schedulers"))) {
-            self.sd_schedulers = schedulers;
-            self.sd_schedulers_loading = false;
-            
-            // Select Automatic by default if empty
-            if self.sd_selected_scheduler.is_empty() && !self.sd_schedulers.is_empty() {
-                self.sd_selected_scheduler = self.sd_schedulers[0].clone();
+            Err(e) => {
+                let error_msg = format!("Failed to fetch models: {}", e);
+                log::error!("{}", error_msg);
+                self.error_message = Some(error_msg);
+                self.connection_status = ConnectionStatus::Offline;
+
+                // Still offer the models known for this endpoint type, if any
+                let known_models = self.endpoint_type.known_models();
+                if !known_models.is_empty() {
+                    self.available_models = known_models.iter().map(|model| model.to_string()).collect();
+                }
             }
         }
     }
 
-    pub fn generate_sd_image(&mut self, ctx: &egui::Context) {
-        self.sd_generating = true;
-        self.sd_progress = 0.0;
-        self.sd_error_message = None; // Clear any previous errors
-        
-        let mut prompt = self.sd_prompt.clone();
-        let negative_prompt = self.sd_negative_prompt.clone();
-        let steps = self.sd_steps;
-        let cfg_scale = self.sd_cfg_scale;
-        let width = self.sd_width;
-        let height = self.sd_height;
-        let sampler_name = self.sd_selected_sampler.clone();
-        let scheduler = Some(self.sd_selected_scheduler.clone());
-        let model_name = self.sd_selected_model.clone();
-        
-        // Add LoRA to prompt instead of using alwayson_scripts
-        if let Some(lora_name) = &self.sd_selected_lora {
-            // Add the LoRA to the prompt with the weight
-            // Format: <lora:name:weight>
-            prompt = format!("{} <lora:{}:{:.1}>", prompt, lora_name, self.sd_lora_weight);
+    pub fn check_connection_health(&mut self, ctx: &egui::Context) {
+        if let Some(online) = take_ready(&mut self.pending_health_check) {
+            self.connection_status = if online { ConnectionStatus::Online } else { ConnectionStatus::Offline };
         }
-        
-        let sd_client = self.sd_client.clone();
-        let ctx_clone = ctx.clone();
-        
-        // Start the image generation in a separate thread
-        self.sd_pending_generation = Some(Promise::spawn_thread("sd_generation", move || {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async move {
-                // Change model if needed
-                if !model_name.is_empty() {
-                    if let Err(e) = sd_client.change_model(&model_name).await {
-                        println!("Failed to change model: {}", e);
-                        return Err(anyhow::anyhow!("Failed to change model: {}", e));
-                    }
-                }
-                
-                // Create the request (without alwayson_scripts)
-                let request = TextToImageRequest {
-                    prompt,
-                    negative_prompt: Some(negative_prompt),
-                    steps,
-                    cfg_scale,
-                    width,
-                    height,
-                    sampler_name,
-                    scheduler,
-                    seed: None, // Random seed
-                    // Add the new hires.fix fields as None, they'll be filled with default values in generate_image
-                    enable_hr: None,
-                    hr_scale: None,
-                    hr_upscaler: None,
-                    hr_second_pass_steps: None,
-                    denoising_strength: None,
-                    alwayson_scripts: serde_json::json!({}), // Empty, since we're using prompt-based LoRA
-                };
-                
-                // Log the actual request for debugging
-                println!("Sending request: {}", serde_json::to_string_pretty(&request).unwrap_or_default());
-                
-                // Start the generation
-                let image_data_result = sd_client.generate_image(request).await;
-                
-                // Check progress periodically while waiting
-                let progress_client = sd_client.clone();
-                let ctx_progress = ctx_clone.clone();
-                
-                tokio::spawn(async move {
-                    while let Ok(progress) = progress_client.check_progress().await {
-                        // Send progress update to UI
-                        ctx_progress.memory_mut(|mem| {
-                            mem.data.insert_temp(egui::Id::new("sd_progress"), progress);
-                        });
-                        
-                        if progress >= 100.0 {
-                            break;
+
+        if let Some(last) = self.last_health_check {
+            // Wake up for the next check even if the app is idle
+            ctx.request_repaint_after(HEALTH_CHECK_INTERVAL.saturating_sub(last.elapsed()));
+        }
+
+        // A model refresh from the settings window already tells us whether the server is up
+        if self.pending_health_check.is_some()
+            || self.pending_models.is_some()
+            || self.last_health_check.is_some_and(|last| last.elapsed() < HEALTH_CHECK_INTERVAL)
+        {
+            return;
+        }
+
+        self.last_health_check = Some(Instant::now());
+
+        let client = self.client.clone();
+        self.pending_health_check = Some(self.spawn_promise(ctx, async move {
+            client.ping().await
+        }));
+    }
+
+    pub fn refresh_context_length(&mut self, ctx: &egui::Context) {
+        // Pick up a context length fetched by a previous request
+        if let Some((model, length)) = take_ready(&mut self.pending_context_length).flatten() {
+            // Never clobber a value the user has overridden in the meantime
+            self.model_context_lengths.entry(model).or_insert(length);
+        }
+
+        if self.selected_model == "local-model"
+            || self.model_context_lengths.contains_key(&self.selected_model)
+            || self.context_length_requested.as_ref() == Some(&self.selected_model)
+        {
+            return;
+        }
+
+        self.context_length_requested = Some(self.selected_model.clone());
+
+        let client = self.client.clone();
+        let model = self.selected_model.clone();
+
+        self.pending_context_length = Some(self.spawn_promise(ctx, async move {
+            match client.get_model_info(&model).await.map(|info| info.context_length) {
+                Ok(Some(length)) => Some((model, length)),
+                Ok(None) => {
+                    log::info!("No context length reported for model {}", model);
+                    None
+                }
+                Err(e) => {
+                    log::warn!("Failed to fetch context length for {}: {}", model, e);
+                    None
+                }
+            }
+        }));
+    }
+
+    /// The context window replies are generated with: Ollama's num_ctx when it is set,
+    /// otherwise the length known for the selected model.
+    pub fn context_length(&self) -> Option<u32> {
+        if self.endpoint_type == EndpointType::Ollama && self.num_ctx > 0 {
+            return Some(self.num_ctx);
+        }
+        self.model_context_length()
+    }
+
+    /// The selected model's context length, as reported by the server or overridden by the user.
+    pub fn model_context_length(&self) -> Option<u32> {
+        self.model_context_lengths.get(&self.selected_model).copied()
+    }
+
+    /// Tokens the next request would take up: the history it sends, the reply so far and the message being typed.
+    pub fn estimate_context_tokens(&self) -> usize {
+        LLMClient::sent_history(&self.chat_history, self.chat_options().max_history_messages()).into_iter()
+            .map(|entry| estimate_tokens(&entry.content))
+            .sum::<usize>()
+            + estimate_tokens(&self.current_response)
+            + estimate_tokens(&self.input)
+    }
+
+    pub fn send_message(&mut self, ctx: &egui::Context) {
+        if self.input.trim().is_empty() && self.attached_image.is_none() {
+            return;
+        }
+        if self.last_send_frame == Some(ctx.frame_nr()) {
+            return;
+        }
+
+        if self.pending_response.is_some() {
+            if !self.interrupt_on_send {
+                return;
+            }
+            // Keep the partial reply and start answering the new message instead
+            self.cancel_response();
+        }
+
+        self.last_send_frame = Some(ctx.frame_nr());
+        self.replaced_replies = None;
+        let prompt = std::mem::take(&mut self.input);
+        self.chat_prompts.record(&prompt);
+        let images: Vec<ImageInput> = self.attached_image.take()
+            .map(|attachment| attachment.image)
+            .into_iter()
+            .collect();
+        let mut entry = ChatEntry::new("user", prompt.clone());
+        entry.images = images.clone();
+        self.chat_history.push(entry);
+        self.store_active_conversation();
+        self.start_comparison_request(ctx, &prompt, &images);
+        self.start_chat_request(ctx, prompt, images);
+    }
+
+    /// Stream a reply to `prompt`, which must already be the last user turn in `chat_history`.
+    fn start_chat_request(&mut self, ctx: &egui::Context, prompt: String, images: Vec<ImageInput>) {
+        let chat_history = self.chat_history.clone();
+        let mut options = self.chat_options();
+        options.prefill = self.prefill.clone();
+        // The reply is shown and kept with its prefilled start, which the server doesn't send back
+        self.current_response = options.prefill.clone();
+        if !self.prefill_sticky {
+            self.prefill.clear();
+        }
+        self.stream_reply(ctx, chat_history, prompt, images, options);
+    }
+
+    fn stream_reply(&mut self, ctx: &egui::Context, chat_history: Vec<ChatEntry>, prompt: String, images: Vec<ImageInput>, mut options: ChatOptions) {
+        let client = self.client.clone();
+        let model = self.selected_model.clone();
+        if self.developer_tools {
+            self.last_chat_curl = client.curl_command(&chat_history, &prompt, &images, &model, &options).ok();
+        }
+        options.raw_stream = self.developer_tools && self.raw_stream;
+        self.raw_stream_log.clear();
+        self.current_alternatives.clear();
+        self.current_prefill = options.prefill.clone();
+        
+        // Unbounded, so a fast model never blocks the streaming thread on a slow frame
+        let (tx, rx) = mpsc::channel();
+        self.response_receiver = Some(rx);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.cancel_flag = Some(cancel.clone());
+        
+        self.pending_response = Some(self.spawn_promise(ctx, async move {
+            client.chat_stream(&chat_history, &prompt, &images, &model, &options, tx, cancel).await
+        }));
+    }
+
+    /// Hand files dropped on the window to the open tab.
+    pub fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<egui::DroppedFile> = ctx.input(|i| i.raw.dropped_files.clone());
+
+        for file in dropped {
+            let name = file.path.as_ref()
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| file.name.clone());
+
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => bytes.to_vec(),
+                (None, Some(path)) => match std::fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        self.current_tab().report_error(self, format!("Failed to read {}: {}", path.display(), e));
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
+
+            self.current_tab().drop_file(self, ctx, &name, bytes);
+        }
+    }
+
+    /// The open tab, falling back to the first one if the index is out of range.
+    pub fn current_tab(&self) -> &'static dyn AppTab {
+        TABS.get(self.active_tab).copied().unwrap_or(TABS[0])
+    }
+
+    pub fn insert_dropped_text(&mut self, name: &str, bytes: &[u8]) {
+        let Ok(text) = std::str::from_utf8(bytes) else {
+            self.error_message = Some(format!("{} is not a text file", name));
+            return;
+        };
+
+        if !self.input.is_empty() && !self.input.ends_with('\n') {
+            self.input.push('\n');
+        }
+
+        if self.fence_dropped_text {
+            // Use the extension as the fence language so code gets highlighted
+            let language = std::path::Path::new(name)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_string())
+                .unwrap_or_default();
+            self.input.push_str(&format!("{}:\n```{}\n{}\n```\n", name, language, text.trim_end()));
+        } else {
+            self.input.push_str(text);
+        }
+        self.error_message = None;
+    }
+
+    pub fn set_sd_init_image(&mut self, ctx: &egui::Context, bytes: Vec<u8>) {
+        match load_texture_from_bytes(ctx, "sd-init-image", &bytes) {
+            Ok(texture) => {
+                self.sd_init_image = Some(SDInitImage { bytes, texture });
+                self.sd_error_message = None;
+                // A mask only fits the image it was painted on
+                self.sd_mask = None;
+                self.sd_mask_editing = false;
+            }
+            Err(e) => self.sd_error_message = Some(format!("Failed to load image: {}", e)),
+        }
+    }
+
+    /// Use the image shown in the gallery as the img2img source, e.g. to inpaint part of it.
+    pub fn use_sd_image_as_source(&mut self, ctx: &egui::Context) {
+        if let Some(image) = self.selected_sd_image() {
+            let bytes = image.generated.bytes.clone();
+            self.set_sd_init_image(ctx, bytes);
+        }
+    }
+
+    /// Open the mask editor, starting from an empty mask the size of the source image.
+    pub fn start_sd_mask_editing(&mut self, ctx: &egui::Context) {
+        let Some(init) = &self.sd_init_image else {
+            return;
+        };
+        if self.sd_mask.is_none() {
+            let [width, height] = init.texture.size();
+            self.sd_mask = Some(SDMask::blank(ctx, width as u32, height as u32));
+        }
+        self.sd_mask_editing = true;
+    }
+
+    /// Use a black and white image from disk as the mask, white marking what to regenerate.
+    pub fn load_sd_mask_file(&mut self, ctx: &egui::Context) {
+        let Some(init) = &self.sd_init_image else {
+            return;
+        };
+        let path = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "webp", "bmp"])
+            .pick_file();
+
+        // The user cancelled the dialog
+        let Some(path) = path else {
+            return;
+        };
+
+        let [width, height] = init.texture.size();
+        let result = std::fs::read(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+            .and_then(|bytes| SDMask::from_file_bytes(ctx, &bytes, width as u32, height as u32));
+        match result {
+            Ok(mask) => self.sd_mask = Some(mask),
+            Err(e) => self.sd_error_message = Some(format!("Failed to load mask: {}", e)),
+        }
+    }
+
+    /// Grow the source image on one side and mask the new strip, so img2img paints it in.
+    pub fn extend_sd_canvas(&mut self, ctx: &egui::Context, side: OutpaintSide) {
+        let Some(init) = &self.sd_init_image else {
+            return;
+        };
+
+        let mask = self.sd_mask.as_ref().map(|mask| &mask.image);
+        let result = sd_mask::extend_canvas(&init.bytes, mask, side, self.sd_outpaint_amount)
+            .and_then(|(bytes, mask)| {
+                let texture = load_texture_from_bytes(ctx, "sd-init-image", &bytes)?;
+                Ok((SDInitImage { bytes, texture }, mask))
+            });
+        match result {
+            Ok((init, mask)) => {
+                // Generate at the new aspect ratio, within what the size controls allow
+                let [width, height] = init.texture.size();
+                let scale = (2048.0 / width.max(height) as f32).min(1.0);
+                let fit = |size: usize| (((size as f32 * scale) as u32 / 8) * 8).clamp(64, 2048);
+                self.sd_width = fit(width);
+                self.sd_height = fit(height);
+
+                self.sd_init_image = Some(init);
+                self.sd_mask = Some(SDMask::new(ctx, mask));
+            }
+            Err(e) => self.sd_error_message = Some(format!("Failed to extend image: {}", e)),
+        }
+    }
+
+    pub fn pick_image_attachment(&mut self, ctx: &egui::Context) {
+        let path = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "webp", "gif", "bmp"])
+            .pick_file();
+
+        // The user cancelled the dialog
+        let Some(path) = path else {
+            return;
+        };
+
+        let result = std::fs::read(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+            .and_then(|bytes| self.attach_image_bytes(ctx, &bytes));
+
+        if let Err(e) = result {
+            self.error_message = Some(format!("Failed to attach image: {}", e));
+        }
+    }
+
+    pub fn attach_image_bytes(&mut self, ctx: &egui::Context, bytes: &[u8]) -> Result<()> {
+        let format = image::guess_format(bytes)
+            .map_err(|e| anyhow::anyhow!("Unsupported image format: {}", e))?;
+        let texture = load_texture_from_bytes(ctx, "attached-image", bytes)?;
+
+        self.attached_image = Some(ImageAttachment {
+            image: ImageInput {
+                mime_type: format.to_mime_type().to_string(),
+                base64: general_purpose::STANDARD.encode(bytes),
+            },
+            texture,
+        });
+        self.error_message = None;
+
+        Ok(())
+    }
+
+    pub fn can_regenerate(&self) -> bool {
+        self.pending_response.is_none()
+            && matches!(self.chat_history.last(), Some(entry) if entry.role == "assistant" || entry.role == "error")
+    }
+
+    pub fn regenerate_response(&mut self, ctx: &egui::Context) {
+        if !self.can_regenerate() {
+            return;
+        }
+
+        // Drop the reply (or error) being replaced, keeping replies as variants of the new one
+        let mut replies = Vec::new();
+        while matches!(self.chat_history.last(), Some(entry) if entry.role != "user") {
+            if let Some(entry) = self.chat_history.pop().filter(|entry| entry.role == "assistant") {
+                replies.splice(0..0, entry.replies());
+            }
+        }
+        let index = replies.len();
+        self.replaced_replies = Some((replies, index));
+
+        if let Some(entry) = self.chat_history.last() {
+            let prompt = entry.content.clone();
+            let images = entry.images.clone();
+            self.start_chat_request(ctx, prompt, images);
+        }
+    }
+
+    /// The name and avatar shown above messages from `role`. Replies go by the selected model
+    /// unless an assistant name is set.
+    pub fn speaker(&self, role: &str) -> (&str, &str) {
+        let (name, avatar, fallback) = if role == "user" {
+            (self.user_name.trim(), self.user_avatar.trim(), "You")
+        } else if self.assistant_name.trim().is_empty() {
+            (self.selected_model.trim(), self.assistant_avatar.trim(), "LLM")
+        } else {
+            (self.assistant_name.trim(), self.assistant_avatar.trim(), "LLM")
+        };
+        (if name.is_empty() { fallback } else { name }, avatar)
+    }
+
+    /// Show another of the replies generated for the turn at `index`.
+    pub fn select_reply(&mut self, index: usize, reply: usize) {
+        if self.pending_response.is_some() {
+            return;
+        }
+        if let Some(entry) = self.chat_history.get_mut(index) {
+            entry.select_reply(reply);
+            self.store_active_conversation();
+        }
+    }
+
+    /// Whether the last reply was cut off by the token limit.
+    pub fn can_continue(&self) -> bool {
+        self.pending_response.is_none()
+            && matches!(self.chat_history.last(), Some(entry) if entry.role == "assistant"
+                && entry.stats.as_ref().and_then(|stats| stats.stop_note()) == Some("length"))
+    }
+
+    /// Ask for the rest of a reply cut off by the token limit, streaming it onto the end of that reply.
+    pub fn continue_response(&mut self, ctx: &egui::Context) {
+        if !self.can_continue() {
+            return;
+        }
+
+        let chat_history = self.chat_history.clone();
+        // Streamed on from the partial reply, which is stored again once the continuation finishes
+        let Some(partial) = self.chat_history.pop() else {
+            return;
+        };
+        self.current_response = partial.content;
+        // The continued reply takes the partial one's place among the turn's replies
+        self.replaced_replies = Some((partial.other_replies, partial.reply_index));
+        self.scroll_to_bottom = true;
+
+        let mut options = self.chat_options();
+        options.continue_reply = true;
+        self.stream_reply(ctx, chat_history, String::new(), Vec::new(), options);
+    }
+
+    /// Replace the user message at `index` with `text`, dropping everything after it,
+    /// and ask for a new reply.
+    pub fn resend_edited_message(&mut self, ctx: &egui::Context, index: usize, text: String) {
+        if self.pending_response.is_some() || text.trim().is_empty() {
+            return;
+        }
+        if !matches!(self.chat_history.get(index), Some(entry) if entry.role == "user") {
+            return;
+        }
+
+        let images = std::mem::take(&mut self.chat_history[index].images);
+        self.chat_history.truncate(index);
+        self.replaced_replies = None;
+        let mut entry = ChatEntry::new("user", text.clone());
+        entry.images = images.clone();
+        self.chat_history.push(entry);
+        self.store_active_conversation();
+        self.scroll_to_bottom = true;
+        self.start_chat_request(ctx, text, images);
+    }
+
+    pub fn chat_options(&self) -> ChatOptions {
+        ChatOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            max_tokens: self.max_tokens,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            // Sequences are typed with escapes so that newlines can be entered
+            stop: self.stop_sequences.iter()
+                .filter(|sequence| !sequence.is_empty())
+                .map(|sequence| sequence.replace("\\n", "\n").replace("\\t", "\t"))
+                .collect(),
+            max_history_messages: self.max_history_messages,
+            num_ctx: self.num_ctx,
+            json_mode: self.json_mode,
+            completions: self.completions,
+            continue_reply: false,
+            prefill: String::new(),
+            raw_stream: false,
+        }
+    }
+
+    pub fn cancel_response(&mut self) {
+        if let Some(side) = &mut self.comparison {
+            side.cancel();
+        }
+        if self.pending_response.is_none() {
+            return;
+        }
+
+        // Signal the streaming thread to stop reading
+        if let Some(cancel) = self.cancel_flag.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+
+        // Keep whatever already arrived so the partial reply isn't lost
+        self.drain_response_channel();
+
+        let reply = (!self.current_response.is_empty())
+            .then(|| ChatEntry::new("assistant", self.current_response.clone()));
+        self.push_reply(reply);
+
+        self.current_response.clear();
+        self.pending_response = None;
+        self.response_receiver = None;
+    }
+
+    /// Add a finished reply to the history together with the replies it replaced and any others
+    /// generated alongside it. Without a reply the last replaced one is shown again, so a failed
+    /// regeneration loses nothing.
+    fn push_reply(&mut self, reply: Option<ChatEntry>) {
+        let (mut replies, index) = self.replaced_replies.take().unwrap_or_default();
+        let position = index.min(replies.len());
+        replies.extend(std::mem::take(&mut self.current_alternatives).into_iter()
+            .filter(|content| !content.is_empty())
+            .map(ReplyVariant::new));
+        let entry = match reply {
+            Some(mut reply) => {
+                reply.reply_index = position;
+                reply.other_replies = replies;
+                Some(reply)
+            }
+            None => ChatEntry::from_replies(replies, index),
+        };
+        if let Some(entry) = entry {
+            self.chat_history.push(entry);
+        }
+    }
+
+    pub fn apply_preset(&mut self, preset: &SamplingPreset) {
+        self.temperature = preset.temperature;
+        self.top_p = preset.top_p;
+        self.presence_penalty = preset.presence_penalty;
+        self.frequency_penalty = preset.frequency_penalty;
+    }
+
+    /// Save the current parameters under the typed name, replacing a custom preset of that name.
+    pub fn save_custom_preset(&mut self) {
+        let name = self.preset_name_input.trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+
+        let preset = SamplingPreset {
+            name,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+        };
+        match self.custom_presets.iter_mut().find(|existing| existing.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.custom_presets.push(preset),
+        }
+        self.preset_name_input.clear();
+    }
+
+    pub fn reset_to_defaults(&mut self) {
+        self.protocol = "http".to_string();
+        self.server = "localhost".to_string();
+        self.port = "11434".to_string();
+        self.endpoint = "v1/chat/completions".to_string();
+        self.update_client_url();
+    }
+
+    pub fn client_settings(&self) -> ClientSettings {
+        ClientSettings {
+            api_key: Some(self.api_key.trim().to_string()).filter(|key| !key.is_empty()),
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
+            retry_attempts: self.retry_attempts,
+            ollama_generate: self.ollama_generate,
+            custom_headers: llmclient::header_map(&self.custom_headers),
+            accept_invalid_certs: self.accept_invalid_certs,
+            disable_streaming: self.disable_streaming,
+        }
+    }
+
+    pub fn update_endpoint_type(&mut self, new_endpoint_type: EndpointType) {
+        if new_endpoint_type == self.endpoint_type {
+            return;
+        }
+
+        // Keep the settings of the type being left, and pick up the new type's where it was left
+        let previous = EndpointConfig {
+            protocol: std::mem::take(&mut self.protocol),
+            server: std::mem::take(&mut self.server),
+            port: std::mem::take(&mut self.port),
+            endpoint: std::mem::take(&mut self.endpoint),
+            selected_model: std::mem::take(&mut self.selected_model),
+        };
+        self.endpoint_configs.insert(self.endpoint_type, previous);
+        let config = self.endpoint_configs.remove(&new_endpoint_type)
+            .unwrap_or_else(|| EndpointConfig::defaults(new_endpoint_type));
+
+        self.endpoint_type = new_endpoint_type;
+        self.protocol = config.protocol;
+        self.server = config.server;
+        self.port = config.port;
+        self.endpoint = config.endpoint;
+        self.selected_model = config.selected_model;
+        self.available_models.clear();
+        self.update_client_url();
+    }
+
+    /// Switch the visuals to match the theme preference. `system_theme` is what the OS
+    /// reports, if anything; without it the current visuals are kept.
+    pub fn apply_theme(&self, ctx: &egui::Context, system_theme: Option<eframe::Theme>) {
+        let dark_mode = match self.theme {
+            ThemePreference::Dark => true,
+            ThemePreference::Light => false,
+            ThemePreference::System => match system_theme {
+                Some(theme) => theme == eframe::Theme::Dark,
+                None => ctx.style().visuals.dark_mode,
+            },
+        };
+
+        if ctx.style().visuals.dark_mode != dark_mode {
+            ctx.set_visuals(if dark_mode { egui::Visuals::dark() } else { egui::Visuals::light() });
+        }
+    }
+
+    /// Write the working history back into the active conversation.
+    pub fn store_active_conversation(&mut self) {
+        let active = &mut self.conversations[self.active_conversation];
+        active.history = self.chat_history.clone();
+        active.auto_title();
+    }
+
+    pub fn switch_conversation(&mut self, index: usize) {
+        if index == self.active_conversation || index >= self.conversations.len() {
+            return;
+        }
+
+        // A reply still streaming stays with the conversation it was asked in
+        self.cancel_response();
+        self.store_active_conversation();
+
+        self.active_conversation = index;
+        self.chat_history = self.conversations[index].history.clone();
+        self.restart_comparison();
+        self.editing_message = None;
+        self.plain_text_message = None;
+        self.error_message = None;
+        self.scroll_to_bottom = true;
+    }
+
+    pub fn new_conversation(&mut self) {
+        self.cancel_response();
+        self.store_active_conversation();
+
+        // Reuse the current conversation if nothing has been said in it yet
+        if self.chat_history.is_empty() {
+            return;
+        }
+
+        self.conversations.push(Conversation::new(conversation::next_conversation_id(&self.conversations)));
+        self.switch_conversation(self.conversations.len() - 1);
+    }
+
+    /// Whether a reply is streaming in, on either side when comparing.
+    pub fn is_streaming(&self) -> bool {
+        self.pending_response.is_some() || self.comparison.as_ref().is_some_and(ComparisonSide::is_streaming)
+    }
+
+    /// Start or stop answering every prompt with a second model as well. The second side
+    /// starts from the current endpoint and model, and from the conversation so far.
+    pub fn toggle_comparison(&mut self, ctx: &egui::Context) {
+        if let Some(mut side) = self.comparison.take() {
+            side.cancel();
+            return;
+        }
+
+        self.comparison = Some(ComparisonSide::new(self.endpoint_type, self.selected_model.clone(), self.chat_history.clone()));
+        self.refresh_comparison_models(ctx);
+    }
+
+    /// Start the second side over from the conversation now shown.
+    fn restart_comparison(&mut self) {
+        if let Some(side) = &mut self.comparison {
+            side.cancel();
+            side.history = self.chat_history.clone();
+        }
+    }
+
+    /// Point the second side at another endpoint type, with the model last used there.
+    pub fn set_comparison_endpoint_type(&mut self, ctx: &egui::Context, endpoint_type: EndpointType) {
+        let model = if endpoint_type == self.endpoint_type {
+            self.selected_model.clone()
+        } else {
+            self.endpoint_configs.get(&endpoint_type)
+                .map(|config| config.selected_model.clone())
+                .unwrap_or_else(|| EndpointConfig::defaults(endpoint_type).selected_model)
+        };
+        let Some(side) = &mut self.comparison else {
+            return;
+        };
+        if side.endpoint_type == endpoint_type {
+            return;
+        }
+        side.cancel();
+        side.endpoint_type = endpoint_type;
+        side.model = model;
+        side.available_models.clear();
+        self.refresh_comparison_models(ctx);
+    }
+
+    /// A client for `endpoint_type` with the server it was last set up with, and the same
+    /// key, timeouts and headers as the main one.
+    fn comparison_client(&self, endpoint_type: EndpointType) -> LLMClient {
+        let config = if endpoint_type == self.endpoint_type {
+            EndpointConfig {
+                protocol: self.protocol.clone(),
+                server: self.server.clone(),
+                port: self.port.clone(),
+                endpoint: self.endpoint.clone(),
+                selected_model: self.selected_model.clone(),
+            }
+        } else {
+            self.endpoint_configs.get(&endpoint_type).cloned()
+                .unwrap_or_else(|| EndpointConfig::defaults(endpoint_type))
+        };
+        LLMClient::new(config.protocol, config.server, config.port, config.endpoint, endpoint_type, self.client_settings())
+    }
+
+    pub fn refresh_comparison_models(&mut self, ctx: &egui::Context) {
+        let Some(endpoint_type) = self.comparison.as_ref().map(|side| side.endpoint_type) else {
+            return;
+        };
+        let client = self.comparison_client(endpoint_type);
+        let promise = self.spawn_promise(ctx, async move {
+            client.list_models().await
+        });
+        if let Some(side) = &mut self.comparison {
+            side.pending_models = Some(promise);
+        }
+    }
+
+    /// Send `prompt` to the second side too, which answers from its own conversation.
+    fn start_comparison_request(&mut self, ctx: &egui::Context, prompt: &str, images: &[ImageInput]) {
+        let Some(endpoint_type) = self.comparison.as_ref().map(|side| side.endpoint_type) else {
+            return;
+        };
+        let client = self.comparison_client(endpoint_type);
+        let mut options = self.chat_options();
+        options.prefill = self.prefill.clone();
+        // Variants are only kept on the main side
+        options.completions = 1;
+
+        let Some(side) = &mut self.comparison else {
+            return;
+        };
+        side.cancel();
+        side.history.push(ChatEntry::new("user", prompt));
+        side.current_response = options.prefill.clone();
+        let chat_history = side.history.clone();
+        let model = side.model.clone();
+        let prompt = prompt.to_string();
+        let images = images.to_vec();
+
+        let (tx, rx) = mpsc::channel();
+        side.response_receiver = Some(rx);
+        let cancel = Arc::new(AtomicBool::new(false));
+        side.cancel_flag = Some(cancel.clone());
+
+        let promise = self.spawn_promise(ctx, async move {
+            client.chat_stream(&chat_history, &prompt, &images, &model, &options, tx, cancel).await
+        });
+        if let Some(side) = &mut self.comparison {
+            side.pending_response = Some(promise);
+        }
+    }
+
+    /// Pick up the second side's model list and streamed reply.
+    pub fn process_comparison(&mut self, ctx: &egui::Context) {
+        let Some(side) = &mut self.comparison else {
+            return;
+        };
+
+        match take_ready(&mut side.pending_models) {
+            Some(Ok(models)) => {
+                if side.model == "local-model" || side.model.is_empty() {
+                    if let Some(first) = models.first() {
+                        side.model = first.clone();
+                    }
+                }
+                side.available_models = models;
+            }
+            Some(Err(e)) => {
+                log::error!("Failed to fetch models for comparison: {}", e);
+                side.available_models = side.endpoint_type.known_models().iter().map(|model| model.to_string()).collect();
+            }
+            None => {}
+        }
+
+        if side.process_response() {
+            ctx.request_repaint();
+        }
+    }
+
+    /// Empty the current conversation, keeping its place and title.
+    pub fn clear_chat(&mut self) {
+        self.cancel_response();
+        self.chat_history.clear();
+        self.replaced_replies = None;
+        self.editing_message = None;
+        self.plain_text_message = None;
+        self.error_message = None;
+        self.store_active_conversation();
+        self.restart_comparison();
+    }
+
+    pub fn toggle_settings(&mut self, ctx: &egui::Context) {
+        self.show_settings = !self.show_settings;
+        if self.show_settings && self.available_models.is_empty() {
+            self.refresh_models(ctx);
+        }
+    }
+
+    pub fn delete_conversation(&mut self, index: usize) {
+        if index >= self.conversations.len() {
+            return;
+        }
+
+        if index == self.active_conversation {
+            self.cancel_response();
+        }
+        self.store_active_conversation();
+        self.conversations.remove(index);
+        self.editing_message = None;
+        self.plain_text_message = None;
+
+        if self.conversations.is_empty() {
+            self.conversations.push(Conversation::new(0));
+        }
+        if index < self.active_conversation || self.active_conversation >= self.conversations.len() {
+            self.active_conversation = self.active_conversation.saturating_sub(1);
+        }
+        self.chat_history = self.conversations[self.active_conversation].history.clone();
+    }
+
+    pub fn export_chat_history(&mut self) {
+        let format = self.export_format;
+        let path = rfd::FileDialog::new()
+            .set_file_name(format!("chat_history.{}", format.extension()))
+            .add_filter(format.label(), &[format.extension()])
+            .save_file();
+
+        // The user cancelled the dialog
+        let Some(path) = path else {
+            return;
+        };
+
+        let result = chat_export::export_chat_history(&self.chat_history, format, self.export_include_errors)
+            .and_then(|contents| {
+                std::fs::write(&path, contents)
+                    .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+            });
+
+        match result {
+            Ok(()) => {
+                log::info!("Exported chat history to {}", path.display());
+                self.error_message = None;
+            }
+            Err(e) => {
+                self.error_message = Some(format!("Failed to export chat history: {}", e));
+            }
+        }
+    }
+
+    pub fn import_chat_history(&mut self, ctx: &egui::Context) {
+        if self.pending_response.is_some() {
+            self.error_message = Some("Cannot import chat history while a response is being generated".to_string());
+            return;
+        }
+
+        let path = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file();
+
+        // The user cancelled the dialog
+        let Some(path) = path else {
+            return;
+        };
+
+        let result = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+            .and_then(|contents| chat_export::import_chat_history(&contents));
+
+        match result {
+            Ok(history) => {
+                log::info!("Imported {} messages from {}", history.len(), path.display());
+                self.chat_history = history;
+                self.restart_comparison();
+                self.current_response.clear();
+                self.error_message = None;
+                self.scroll_to_bottom = true;
+                ctx.request_repaint();
+            }
+            Err(e) => {
+                self.error_message = Some(format!("Failed to import chat history: {}", e));
+            }
+        }
+    }
+
+    /// Start copying the log to `log_file`, or stop if it was cleared.
+    pub fn apply_log_file(&mut self) {
+        match logging::set_log_file(&self.log_file) {
+            Ok(()) => log::info!("Logging to {}", if self.log_file.trim().is_empty() { "stderr only" } else { self.log_file.trim() }),
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    pub fn update_client_url(&mut self) {
+        logging::redact(self.api_key.trim());
+        self.client = LLMClient::new(
+            self.protocol.clone(),
+            self.server.clone(),
+            self.port.clone(),
+            self.endpoint.clone(),
+            self.endpoint_type,
+            self.client_settings(),
+        );
+        self.connection_status = ConnectionStatus::Unknown;
+        // A list or detection still on its way would be from the old server
+        self.pending_models = None;
+        self.pending_detection = None;
+        self.detection_message = None;
+        // Waiting a moment keeps typing in the server field from sending a request per key
+        self.model_refresh_due = Some(Instant::now() + MODEL_REFRESH_DELAY);
+    }
+
+    /// Append every chunk received so far to `current_response`, and raw ones to the raw stream log,
+    /// returning whether there were any.
+    fn drain_response_channel(&mut self) -> bool {
+        let Some(rx) = &self.response_receiver else {
+            return false;
+        };
+
+        let mut received = false;
+        while let Ok(message) = rx.try_recv() {
+            match message {
+                StreamMessage::Text(new_content) => self.current_response.push_str(&new_content),
+                StreamMessage::Alternative(index, new_content) => {
+                    let slot = index.saturating_sub(1);
+                    if self.current_alternatives.len() <= slot {
+                        self.current_alternatives.resize(slot + 1, String::new());
+                    }
+                    let buffer = &mut self.current_alternatives[slot];
+                    if buffer.is_empty() {
+                        buffer.push_str(&self.current_prefill);
+                    }
+                    buffer.push_str(&new_content);
+                }
+                StreamMessage::Raw(raw) => {
+                    self.raw_stream_log.push_str(&raw);
+                    // Keep the end, which is what's being debugged, within the limit
+                    if self.raw_stream_log.len() > RAW_STREAM_LOG_LIMIT {
+                        let mut cut = self.raw_stream_log.len() - RAW_STREAM_LOG_LIMIT;
+                        while !self.raw_stream_log.is_char_boundary(cut) {
+                            cut += 1;
                         }
-                        
-                        tokio::time::sleep(Duration::from_millis(500)).await;
+                        self.raw_stream_log.drain(..cut);
+                    }
+                }
+            }
+            received = true;
+        }
+        received
+    }
+
+    pub fn process_response_chunks(&mut self, ctx: &egui::Context) {
+        if self.drain_response_channel() {
+            self.response_updated = Some(Instant::now());
+            ctx.request_repaint();
+        }
+
+        if self.pending_response.as_ref().is_some_and(|promise| promise.ready().is_some()) {
+            // The stream thread has finished, so its sender is gone and this
+            // picks up everything it sent after the drain above
+            self.drain_response_channel();
+        }
+
+        if let Some(promise) = &self.pending_response {
+            if let Some(result) = promise.ready() {
+                let (reply, error) = match result {
+                    Err(e) if self.current_response.is_empty() => {
+                        (None, Some(ChatEntry::new("error", format!("Error: {}", e))))
+                    }
+                    Err(e) => {
+                        // Keep what arrived, but mark it so it isn't mistaken for a full reply
+                        let mut entry = ChatEntry::new("assistant", self.current_response.clone());
+                        entry.error = Some(e.to_string());
+                        (Some(entry), None)
+                    }
+                    Ok(stats) => {
+                        let reply = (!self.current_response.is_empty()).then(|| {
+                            let mut entry = ChatEntry::new("assistant", self.current_response.clone());
+                            entry.stats = (!stats.is_empty()).then(|| stats.clone());
+                            entry
+                        });
+                        (reply, None)
+                    }
+                };
+                self.push_reply(reply);
+                self.chat_history.extend(error);
+                self.current_response.clear();
+                self.pending_response = None;
+                self.response_receiver = None;
+                self.cancel_flag = None;
+                ctx.request_repaint();
+            }
+        }
+    }
+
+    /// Request whichever SD option lists are still empty.
+    pub fn load_sd_options(&mut self, ctx: &egui::Context) {
+        if self.sd_options_failed {
+            return;
+        }
+
+        if self.sd_models.is_empty() && self.sd_models_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_models_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_models().await }));
+        }
+        if self.sd_loras.is_empty() && self.sd_loras_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_loras_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_loras().await }));
+        }
+        if self.sd_samplers.is_empty() && self.sd_samplers_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_samplers_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_samplers().await }));
+        }
+        if self.sd_schedulers.is_empty() && self.sd_schedulers_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_schedulers_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_schedulers().await }));
+        }
+        if self.sd_vaes.is_empty() && self.sd_vaes_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_vaes_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_vaes().await }));
+        }
+        if self.sd_upscalers.is_empty() && self.sd_upscalers_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_upscalers_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_upscalers().await }));
+        }
+        if self.sd_face_restorers.is_empty() && self.sd_face_restorers_pending.is_none() {
+            let sd_client = self.sd_client.clone();
+            self.sd_face_restorers_pending = Some(self.spawn_promise(ctx, async move { sd_client.get_available_face_restorers().await }));
+        }
+    }
+
+    /// Load the SD option lists again after a failure, e.g. from the refresh button.
+    pub fn reload_sd_options(&mut self, ctx: &egui::Context) {
+        self.sd_options_failed = false;
+        self.load_sd_options(ctx);
+    }
+
+    /// Have the chat model rewrite `sd_prompt` as a detailed Stable Diffusion prompt.
+    pub fn refine_sd_prompt(&mut self, ctx: &egui::Context) {
+        if self.sd_prompt.trim().is_empty() || self.sd_refine_pending.is_some() {
+            return;
+        }
+
+        let client = self.client.clone();
+        let model = self.selected_model.clone();
+        let prompt = format!("{}{}", SD_REFINE_INSTRUCTION, self.sd_prompt.trim());
+        let mut options = self.chat_options();
+        options.json_mode = false;
+        options.completions = 1;
+        self.sd_refine_pending = Some(self.spawn_promise(ctx, async move {
+            client.complete(&prompt, &model, &options).await
+        }));
+    }
+
+    /// Replace the SD prompt with the rewrite once it arrives, keeping the old one in the recent prompts.
+    pub fn process_sd_prompt_refine(&mut self) {
+        let Some(result) = take_ready(&mut self.sd_refine_pending) else {
+            return;
+        };
+
+        match result {
+            Ok(reply) => {
+                let refined = strip_reasoning(&reply);
+                let refined = refined.trim().trim_matches('"').trim();
+                if refined.is_empty() {
+                    self.sd_error_message = Some("The model returned an empty prompt".to_string());
+                } else {
+                    self.sd_prompts.record(&self.sd_prompt);
+                    self.sd_prompt = refined.to_string();
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to refine prompt: {:#}", e);
+                self.sd_error_message = Some(format!("Failed to refine prompt: {:#}", e));
+            }
+        }
+    }
+
+    /// Pick up the SD option lists requested by `load_sd_options`.
+    pub fn process_sd_options(&mut self) {
+        if let Some(result) = take_ready(&mut self.sd_models_pending) {
+            if let Some(models) = self.sd_options_result("models", result) {
+                self.sd_models = models;
+                if !self.sd_models.is_empty() && self.sd_selected_model.is_empty() {
+                    self.sd_selected_model = self.sd_models[0].model_name.clone();
+                }
+            }
+        }
+
+        if let Some(result) = take_ready(&mut self.sd_loras_pending) {
+            if let Some(loras) = self.sd_options_result("LoRAs", result) {
+                self.sd_loras = loras;
+            }
+        }
+
+        if let Some(result) = take_ready(&mut self.sd_samplers_pending) {
+            if let Some(samplers) = self.sd_options_result("samplers", result) {
+                self.sd_samplers = samplers;
+
+                // Select Euler a by default if available
+                if self.sd_selected_sampler.is_empty() {
+                    self.sd_selected_sampler = self.sd_samplers
+                        .iter()
+                        .find(|s| s.name == "Euler a")
+                        .or_else(|| self.sd_samplers.first())
+                        .map_or_else(|| "Euler a".to_string(), |s| s.name.clone());
+                }
+            }
+        }
+
+        if let Some(result) = take_ready(&mut self.sd_schedulers_pending) {
+            if let Some(schedulers) = self.sd_options_result("schedulers", result) {
+                self.sd_schedulers = schedulers;
+
+                // Select Automatic by default if empty
+                if self.sd_selected_scheduler.is_empty() && !self.sd_schedulers.is_empty() {
+                    self.sd_selected_scheduler = self.sd_schedulers[0].clone();
+                }
+            }
+        }
+
+        if let Some(result) = take_ready(&mut self.sd_vaes_pending) {
+            if let Some(vaes) = self.sd_options_result("VAEs", result) {
+                self.sd_vaes = vaes;
+            }
+        }
+
+        if let Some(result) = take_ready(&mut self.sd_upscalers_pending) {
+            if let Some(upscalers) = self.sd_options_result("upscalers", result) {
+                self.sd_upscalers = upscalers;
+
+                if !self.sd_upscalers.contains(&self.sd_selected_upscaler) {
+                    if let Some(first) = self.sd_upscalers.first() {
+                        self.sd_selected_upscaler = first.clone();
+                    }
+                }
+            }
+        }
+
+        if let Some(result) = take_ready(&mut self.sd_face_restorers_pending) {
+            if let Some(restorers) = self.sd_options_result("face restorers", result) {
+                self.sd_face_restorers = restorers;
+
+                if !self.sd_face_restorers.contains(&self.sd_selected_face_restorer) {
+                    if let Some(first) = self.sd_face_restorers.first() {
+                        self.sd_selected_face_restorer = first.clone();
                     }
-                });
-                
-                image_data_result
-            })
+                }
+            }
+        }
+    }
+
+    /// The loaded list, or None after reporting why it couldn't be loaded.
+    fn sd_options_result<T>(&mut self, what: &str, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(list) => Some(list),
+            Err(e) => {
+                log::error!("Failed to fetch {}: {}", what, e);
+                self.sd_error_message = Some(format!("Failed to fetch {}: {}", what, e));
+                self.sd_options_failed = true;
+                None
+            }
+        }
+    }
+
+    pub fn generate_sd_image(&mut self, ctx: &egui::Context) {
+        if self.sd_busy() {
+            return;
+        }
+        self.sd_prompts.record(&self.sd_prompt);
+        let request = self.build_sd_request();
+        let options = SDModelOptions {
+            model: self.sd_selected_model.clone(),
+            vae: self.sd_selected_vae.clone(),
+        };
+        self.sd_last_request = Some((options.clone(), request.clone()));
+        self.start_sd_generation(ctx, options, request);
+    }
+
+    /// Whether a generation is running, including one started earlier in this frame.
+    pub fn sd_busy(&self) -> bool {
+        self.sd_generating || self.sd_pending_generation.is_some()
+    }
+
+    /// Queue a generation with the current prompt and settings.
+    pub fn add_sd_to_queue(&mut self) {
+        self.sd_prompts.record(&self.sd_prompt);
+        let request = self.build_sd_request();
+        let options = SDModelOptions {
+            model: self.sd_selected_model.clone(),
+            vae: self.sd_selected_vae.clone(),
+        };
+        self.sd_queue.push_back(SDQueueItem { prompt: self.sd_prompt.clone(), options, request });
+    }
+
+    /// Start (or resume) generating the queued items one after another.
+    pub fn start_sd_queue(&mut self, ctx: &egui::Context) {
+        if self.sd_queue.is_empty() || self.sd_queue_state == SDQueueState::Running {
+            return;
+        }
+        if self.sd_queue_state == SDQueueState::Idle {
+            self.sd_queue_finished = 0;
+        }
+        self.sd_queue_state = SDQueueState::Running;
+        // A generation already running finishes first, then the queue takes over
+        if !self.sd_busy() {
+            self.advance_sd_queue(ctx);
+        }
+    }
+
+    /// Stop after the current item, keeping the rest queued.
+    pub fn pause_sd_queue(&mut self) {
+        if self.sd_queue_state == SDQueueState::Running {
+            self.sd_queue_state = SDQueueState::Paused;
+        }
+    }
+
+    pub fn clear_sd_queue(&mut self) {
+        self.sd_queue.clear();
+        if self.sd_queue_current.is_none() {
+            self.sd_queue_state = SDQueueState::Idle;
+        }
+    }
+
+    /// Start the next queued item, or finish the run when none are left.
+    fn advance_sd_queue(&mut self, ctx: &egui::Context) {
+        let Some(item) = self.sd_queue.pop_front() else {
+            self.sd_queue_state = SDQueueState::Idle;
+            return;
+        };
+        self.start_sd_generation(ctx, item.options.clone(), item.request.clone());
+        self.sd_queue_current = Some(item);
+    }
+
+    /// Called when a queued item's generation ends, to start the next or pause the run.
+    fn finish_sd_queue_item(&mut self, ctx: &egui::Context, succeeded: bool) {
+        let Some(item) = self.sd_queue_current.take() else {
+            return;
+        };
+        if succeeded {
+            self.sd_queue_finished += 1;
+        } else {
+            // Resuming tries it again, so the retry button would only run it twice
+            self.sd_queue.push_front(item);
+            self.sd_last_request = None;
+            self.sd_queue_state = SDQueueState::Paused;
+        }
+        match self.sd_queue_state {
+            SDQueueState::Running => self.advance_sd_queue(ctx),
+            // Paused with nothing left means the run is over
+            SDQueueState::Paused if self.sd_queue.is_empty() => self.sd_queue_state = SDQueueState::Idle,
+            _ => {}
+        }
+    }
+
+    /// Run the last failed generation again as it was sent, without reading the current settings.
+    pub fn retry_sd_generation(&mut self, ctx: &egui::Context) {
+        if self.sd_busy() {
+            return;
+        }
+        if let Some((options, request)) = self.sd_last_request.clone() {
+            self.start_sd_generation(ctx, options, request);
+        }
+    }
+
+    fn build_sd_request(&self) -> TextToImageRequest {
+        let mut prompt = self.sd_prompt.clone();
+        let init_images: Vec<String> = self.sd_init_image.iter()
+            .map(|init| general_purpose::STANDARD.encode(&init.bytes))
+            .collect();
+        let img2img = !init_images.is_empty();
+        let mask = self.sd_mask.as_ref()
+            .filter(|mask| img2img && !mask.is_empty())
+            .and_then(|mask| match mask.to_png_base64() {
+                Ok(mask) => Some(mask),
+                Err(e) => {
+                    log::error!("Leaving out the inpainting mask: {}", e);
+                    None
+                }
+            });
+        let inpaint = mask.is_some();
+        let hires_enabled = self.sd_hires_enabled && !img2img;
+        let denoising_strength = if img2img { self.sd_img2img_denoising } else { self.sd_denoising_strength };
+        
+        // Add LoRAs to prompt instead of using alwayson_scripts
+        // Format: <lora:name:weight>
+        for (lora_name, weight) in &self.sd_selected_loras {
+            // Skip LoRAs the server no longer lists, and ones that would have no effect
+            if !self.sd_loras.iter().any(|lora| lora.name == *lora_name) || *weight == 0.0 {
+                continue;
+            }
+            let weight = weight.clamp(*LORA_WEIGHT_RANGE.start(), *LORA_WEIGHT_RANGE.end());
+            prompt = format!("{} <lora:{}:{:.2}>", prompt, lora_name, weight);
+        }
+
+        // Create the request (without alwayson_scripts)
+        TextToImageRequest {
+            prompt,
+            negative_prompt: Some(self.sd_negative_prompt.clone()),
+            steps: self.sd_steps,
+            cfg_scale: self.sd_cfg_scale,
+            width: self.sd_width,
+            height: self.sd_height,
+            sampler_name: self.sd_selected_sampler.clone(),
+            scheduler: Some(self.sd_selected_scheduler.clone()),
+            seed: self.sd_seed,
+            batch_size: self.sd_batch_size,
+            n_iter: self.sd_batch_count,
+            // Hires.fix is only sent when enabled; generate_image fills the second pass steps
+            enable_hr: hires_enabled.then_some(true),
+            hr_scale: hires_enabled.then_some(self.sd_hr_scale),
+            hr_upscaler: hires_enabled.then(|| self.sd_hr_upscaler.clone()),
+            hr_second_pass_steps: None,
+            // Used by hires.fix, or by img2img for how far to move from the source image
+            denoising_strength: (hires_enabled || img2img).then_some(denoising_strength),
+            alwayson_scripts: serde_json::json!({}), // Empty, since we're using prompt-based LoRA
+            init_images,
+            mask,
+            mask_blur: inpaint.then_some(self.sd_mask_blur),
+            inpainting_fill: inpaint.then(|| self.sd_inpainting_fill.api_value()),
+            inpaint_full_res: inpaint.then_some(self.sd_inpaint_full_res),
+            inpaint_full_res_padding: (inpaint && self.sd_inpaint_full_res).then_some(self.sd_inpaint_padding),
+            tiling: self.sd_tiling.then_some(true),
+            restore_faces: self.sd_restore_faces.then_some(true),
+            override_settings: self.sd_face_restoration_settings(),
+        }
+    }
+
+    /// The face restoration model and CodeFormer weight, sent as per-request overrides.
+    fn sd_face_restoration_settings(&self) -> serde_json::Value {
+        let mut settings = serde_json::Map::new();
+        if self.sd_restore_faces && !self.sd_selected_face_restorer.is_empty() {
+            settings.insert("face_restoration_model".to_string(), serde_json::json!(self.sd_selected_face_restorer));
+            if self.sd_selected_face_restorer == "CodeFormer" {
+                settings.insert("code_former_weight".to_string(), serde_json::json!(self.sd_codeformer_weight));
+            }
+        }
+        serde_json::Value::Object(settings)
+    }
+
+    /// Add a row for the first LoRA not already in use, if there is one.
+    pub fn add_sd_lora(&mut self) {
+        let unused = self.sd_loras.iter()
+            .find(|lora| !self.sd_selected_loras.iter().any(|(name, _)| *name == lora.name));
+        if let Some(lora) = unused {
+            self.sd_selected_loras.push((lora.name.clone(), 0.7));
+        }
+    }
+
+    fn start_sd_generation(&mut self, ctx: &egui::Context, options: SDModelOptions, request: TextToImageRequest) {
+        self.sd_generating = true;
+        // Show the results rather than the mask being edited
+        self.sd_mask_editing = false;
+        self.sd_generation_started = Some(Instant::now());
+        self.sd_progress = 0.0;
+        self.sd_eta = 0.0;
+        self.sd_preview_texture = None;
+        self.sd_error_message = None; // Clear any previous errors
+        if self.developer_tools {
+            self.sd_last_curl = Some(self.sd_client.curl_command(request.clone()));
+        }
+
+        // Switching checkpoints is slow, so skip it when the server already has these options
+        let change_model = self.sd_loaded_options.as_ref() != Some(&options);
+        
+        let sd_client = self.sd_client.clone();
+        let ctx_clone = ctx.clone();
+
+        // Stops progress polling once the generation is interrupted
+        let stop = Arc::new(AtomicBool::new(false));
+        self.sd_stop_flag = Some(stop.clone());
+        let (tx, rx) = mpsc::channel();
+        self.sd_progress_receiver = Some(rx);
+        
+        // Start the image generation in the background
+        self.sd_pending_generation = Some(self.spawn_promise(ctx, async move {
+            // Change model if needed
+            let loaded_options = if change_model {
+                if let Err(e) = sd_client.change_model(&options).await {
+                    log::error!("Failed to change model: {}", e);
+                    return SDGeneration { loaded_options: None, images: Err(anyhow::anyhow!("Failed to change model: {}", e)) };
+                }
+                Some(options)
+            } else {
+                None
+            };
+            
+            // Log the actual request for debugging
+            log::debug!("Sending request: {}", serde_json::to_string_pretty(&request).unwrap_or_default());
+            
+            // Check progress periodically while the generation runs
+            let generation = sd_client.generate_image(request);
+            tokio::pin!(generation);
+            let images = tokio::select! {
+                images = &mut generation => images,
+                () = poll_sd_progress(&sd_client, &ctx_clone, &tx, &stop) => generation.await,
+            };
+            
+            SDGeneration { loaded_options, images }
         }));
     }
     
-    pub fn save_sd_image(&self) {
-        if let Some(image_data) = &self.sd_image_bytes {
-            // Use a file dialog to select where to save the file
-            // For simplicity, we're just saving to a fixed location
-            std::fs::write("generated_image.png", image_data)
-                .expect("Failed to save image");
+    /// Point `sd_client` at `sd_url_input` if it holds a usable URL.
+    pub fn apply_sd_url(&mut self) {
+        let url = self.sd_url_input.trim().trim_end_matches('/');
+        if url == self.sd_client.base_url {
+            return;
+        }
+
+        match validate_sd_url(url) {
+            Ok(()) => {
+                self.sd_client = SDClient::new(url.to_string());
+                // A different server may have any checkpoint loaded
+                self.sd_loaded_options = None;
+                self.sd_connection_status = None;
+                self.sd_error_message = None;
+                self.sd_options_failed = false;
+            }
+            Err(e) => self.sd_error_message = Some(e),
         }
     }
+
+    /// Check that the SD server answers by asking it for its samplers.
+    pub fn test_sd_connection(&mut self, ctx: &egui::Context) {
+        self.sd_connection_status = None;
+        if let Err(e) = validate_sd_url(self.sd_url_input.trim()) {
+            self.sd_error_message = Some(e);
+            return;
+        }
+        self.apply_sd_url();
+
+        let sd_client = self.sd_client.clone();
+        self.sd_connection_pending = Some(self.spawn_promise(ctx, async move {
+            let samplers = sd_client.get_available_samplers().await
+                .with_context(|| format!("Failed to connect to {}", sd_client.base_url))?;
+            Ok(format!("Connected to {} ({} samplers)", sd_client.base_url, samplers.len()))
+        }));
+    }
+
+    pub fn interrupt_sd_generation(&mut self, ctx: &egui::Context) {
+        if !self.sd_generating || self.sd_interrupt_pending.is_some() {
+            return;
+        }
+
+        let sd_client = self.sd_client.clone();
+        self.sd_interrupt_pending = Some(self.spawn_promise(ctx, async move {
+            sd_client.interrupt().await
+        }));
+    }
+
+    /// Ask the server for the parameters embedded in `bytes` and fill the SD fields from them.
+    pub fn load_sd_png_info(&mut self, ctx: &egui::Context, bytes: Vec<u8>) {
+        let sd_client = self.sd_client.clone();
+        self.sd_png_info_pending = Some(self.spawn_promise(ctx, async move {
+            sd_client.get_png_info(&bytes).await
+        }));
+    }
+
+    fn apply_sd_png_info(&mut self, info: &str) {
+        let Some(parameters) = png_info::parse_parameters(info) else {
+            self.sd_error_message = Some("The image has no embedded generation parameters".to_string());
+            return;
+        };
+
+        self.sd_prompt = parameters.prompt;
+        self.sd_negative_prompt = parameters.negative_prompt;
+        if let Some(steps) = parameters.steps {
+            self.sd_steps = steps;
+        }
+        if let Some(cfg_scale) = parameters.cfg_scale {
+            self.sd_cfg_scale = cfg_scale;
+        }
+        if let Some((width, height)) = parameters.size {
+            self.sd_width = width;
+            self.sd_height = height;
+        }
+        if let Some(sampler) = parameters.sampler {
+            self.sd_selected_sampler = sampler;
+        }
+        if let Some(scheduler) = parameters.scheduler {
+            self.sd_selected_scheduler = scheduler;
+        }
+        if let Some(seed) = parameters.seed {
+            self.sd_seed = Some(seed);
+        }
+        self.sd_error_message = None;
+    }
+
+    pub fn selected_sd_image(&self) -> Option<&SDImage> {
+        self.sd_images.get(self.sd_selected_image)
+    }
+
+    /// Upscale the image currently shown; the result is added to the gallery next to it.
+    pub fn upscale_sd_image(&mut self, ctx: &egui::Context) {
+        if self.sd_pending_upscale.is_some() {
+            return;
+        }
+        let Some(image) = self.selected_sd_image() else {
+            return;
+        };
+
+        let bytes = image.generated.bytes.clone();
+        let sd_client = self.sd_client.clone();
+        let upscaler = self.sd_selected_upscaler.clone();
+        let scale = self.sd_upscale_factor;
+        self.sd_error_message = None;
+
+        let promise = self.spawn_promise(ctx, async move {
+            sd_client.upscale_image(&bytes, &upscaler, scale).await
+        });
+        self.sd_pending_upscale = Some((self.sd_selected_image, promise));
+    }
+
+    /// Run `future` on the shared runtime; the promise resolves with its output,
+    /// repainting so the next frame picks it up.
+    fn spawn_promise<T: Send + 'static>(&self, ctx: &egui::Context, future: impl Future<Output = T> + Send + 'static) -> Promise<T> {
+        let (sender, promise) = Promise::new();
+        let ctx = ctx.clone();
+        self.runtime.spawn(async move {
+            sender.send(future.await);
+            ctx.request_repaint();
+        });
+        promise
+    }
+
+    /// Seed of the image currently shown, for reuse in the next generation.
+    pub fn sd_last_seed(&self) -> Option<i64> {
+        self.selected_sd_image()?.generated.seed()
+    }
+
+    /// The bytes to write when saving `image`, with its generation parameters embedded if enabled.
+    fn sd_image_file_bytes(&self, image: &GeneratedImage) -> Vec<u8> {
+        if !self.sd_embed_metadata {
+            return image.bytes.clone();
+        }
+
+        let Some(parameters) = image.infotext() else {
+            return image.bytes.clone();
+        };
+
+        match png_info::embed_parameters(&image.bytes, &parameters) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                // Still save the image, just as the server sent it
+                log::warn!("Failed to embed generation parameters: {}", e);
+                image.bytes.clone()
+            }
+        }
+    }
+
+    /// The save folder, if one is set.
+    fn sd_save_folder(&self) -> Option<PathBuf> {
+        let folder = self.sd_save_folder.trim();
+        (!folder.is_empty()).then(|| PathBuf::from(folder))
+    }
+
+    /// Where quick saves go, when they are turned on and a folder is set.
+    fn sd_quick_save_folder(&self) -> Option<PathBuf> {
+        self.sd_save_folder().filter(|_| self.sd_quick_save)
+    }
+
+    /// Put the shown SD image on the system clipboard, for pasting into other apps.
+    pub fn copy_sd_image(&mut self) {
+        let Some(image) = self.selected_sd_image() else {
+            return;
+        };
+
+        let result = image::load_from_memory(&image.generated.bytes)
+            .context("Failed to decode image")
+            .and_then(|decoded| {
+                let rgba = decoded.to_rgba8();
+                let data = arboard::ImageData {
+                    width: rgba.width() as usize,
+                    height: rgba.height() as usize,
+                    bytes: rgba.into_raw().into(),
+                };
+                self.set_clipboard_image(data)
+            });
+
+        match result {
+            Ok(()) => {
+                self.sd_save_status = Some("Copied to the clipboard".to_string());
+                self.sd_error_message = None;
+            }
+            Err(e) => {
+                log::error!("Failed to copy image: {:#}", e);
+                // Some platforms and sessions only take text, saving still works there
+                self.sd_error_message = Some(format!("Failed to copy image, use Save Image instead: {:#}", e));
+            }
+        }
+    }
+
+    fn set_clipboard_image(&mut self, data: arboard::ImageData) -> Result<()> {
+        if self.image_clipboard.is_none() {
+            self.image_clipboard = Some(arboard::Clipboard::new().context("No clipboard available")?);
+        }
+        let clipboard = self.image_clipboard.as_mut().context("No clipboard available")?;
+        clipboard.set_image(data).context("The clipboard doesn't accept images")
+    }
+
+    pub fn save_sd_image(&mut self) {
+        let Some(image) = self.selected_sd_image() else {
+            return;
+        };
+        let image_data = self.sd_image_file_bytes(&image.generated);
+        let name = sd_filename::render_file_name(&self.sd_filename_template, &image.generated, self.sd_selected_image + 1);
+
+        let path = match self.sd_quick_save_folder() {
+            Some(folder) => Some(sd_filename::unique_path(&folder, &name, "png")),
+            None => {
+                let mut dialog = rfd::FileDialog::new()
+                    .set_file_name(format!("{}.png", name))
+                    .add_filter("PNG image", &["png"]);
+                if let Some(folder) = self.sd_save_folder() {
+                    dialog = dialog.set_directory(folder);
+                }
+                dialog.save_file()
+            }
+        };
+
+        // The user cancelled the dialog
+        let Some(path) = path else {
+            return;
+        };
+
+        match std::fs::write(&path, image_data) {
+            Ok(()) => {
+                log::info!("Saved image to {}", path.display());
+                self.sd_save_status = Some(format!("Saved to {}", path.display()));
+                self.sd_error_message = None;
+            }
+            Err(e) => {
+                self.sd_error_message = Some(format!("Failed to save image to {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    pub fn save_all_sd_images(&mut self) {
+        if self.sd_images.is_empty() {
+            return;
+        }
+
+        let folder = match self.sd_quick_save_folder() {
+            Some(folder) => Some(folder),
+            None => {
+                let mut dialog = rfd::FileDialog::new();
+                if let Some(folder) = self.sd_save_folder() {
+                    dialog = dialog.set_directory(folder);
+                }
+                dialog.pick_folder()
+            }
+        };
+
+        // The user cancelled the dialog
+        let Some(folder) = folder else {
+            return;
+        };
+
+        for (i, image) in self.sd_images.iter().enumerate() {
+            let name = sd_filename::render_file_name(&self.sd_filename_template, &image.generated, i + 1);
+            // Templates without {index} give every image the same name, which gets a counter
+            let path = sd_filename::unique_path(&folder, &name, "png");
+            if let Err(e) = std::fs::write(&path, self.sd_image_file_bytes(&image.generated)) {
+                self.sd_error_message = Some(format!("Failed to save image to {}: {}", path.display(), e));
+                return;
+            }
+            log::info!("Saved image to {}", path.display());
+        }
+        self.sd_save_status = Some(format!("Saved {} images to {}", self.sd_images.len(), folder.display()));
+        self.sd_error_message = None;
+    }
     
     pub fn process_sd_generation(&mut self, ctx: &egui::Context) {
+        // Result of "Test SD Connection"
+        match take_ready(&mut self.sd_connection_pending) {
+            Some(Ok(status)) => {
+                self.sd_connection_status = Some(status);
+                self.sd_error_message = None;
+                // The server answers now, so lists that failed to load can be tried again
+                self.sd_options_failed = false;
+            }
+            Some(Err(e)) => self.sd_error_message = Some(format!("{:#}", e)),
+            None => {}
+        }
+
+        // Parameters read from a dropped image
+        match take_ready(&mut self.sd_png_info_pending) {
+            Some(Ok(Some(info))) => self.apply_sd_png_info(&info),
+            Some(Ok(None)) => self.sd_error_message = Some("The image has no embedded generation parameters".to_string()),
+            Some(Err(e)) => self.sd_error_message = Some(format!("Failed to read PNG info: {}", e)),
+            None => {}
+        }
+
+        // Check whether an interrupt request went through
+        match take_ready(&mut self.sd_interrupt_pending) {
+            Some(Ok(())) => {
+                if let Some(stop) = self.sd_stop_flag.take() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                // The checkpoint switch may not have been recorded yet, so the next run redoes it
+                if self.sd_pending_generation.take().is_some_and(|promise| promise.ready().is_none()) {
+                    self.sd_loaded_options = None;
+                }
+                self.sd_progress_receiver = None;
+                self.sd_generating = false;
+                self.sd_progress = 0.0;
+                self.sd_preview_texture = None;
+                self.pause_sd_queue();
+                self.finish_sd_queue_item(ctx, false);
+                ctx.request_repaint();
+            }
+            Some(Err(e)) => {
+                log::error!("Failed to interrupt generation: {}", e);
+                self.sd_error_message = Some(format!("Failed to interrupt generation: {}", e));
+            }
+            None => {}
+        }
+
         // Check for progress updates
-        if let Some(progress) = ctx.memory_mut(|mem| mem.data.remove_temp::<f32>(egui::Id::new("sd_progress"))) {
-            self.sd_progress = progress;
+        if let Some(progress) = self.sd_progress_receiver.as_ref().and_then(|rx| rx.try_iter().last()) {
+            if self.sd_generating {
+                self.sd_progress = progress.percent;
+                self.sd_eta = progress.eta_seconds;
+                if let Some(bytes) = progress.preview {
+                    match (decode_color_image(&bytes), &mut self.sd_preview_texture) {
+                        // Update the preview in place rather than allocating a texture per poll
+                        (Ok(image), Some(texture)) => texture.set(image, egui::TextureOptions::default()),
+                        (Ok(image), None) => {
+                            self.sd_preview_texture = Some(ctx.load_texture("sd-preview", image, egui::TextureOptions::default()));
+                        }
+                        (Err(e), _) => log::warn!("Failed to decode preview: {}", e),
+                    }
+                }
+            }
             ctx.request_repaint();
         }
         
         // Check if generation is complete
-        if let Some(promise) = &self.sd_pending_generation {
+        if let Some(generation) = take_ready(&mut self.sd_pending_generation) {
+            self.sd_generating = false;
+            self.sd_preview_texture = None;
+            self.sd_progress_receiver = None;
+            self.sd_stop_flag = None;
+            // The checkpoint the server switched to, so retries can skip switching again
+            if let Some(options) = generation.loaded_options {
+                self.sd_loaded_options = Some(options);
+            }
+            
+            let succeeded = generation.images.is_ok();
+            match generation.images {
+                Ok(images) => {
+                    // A queue run keeps every item's images, showing the newest
+                    if self.sd_queue_current.is_none() || self.sd_queue_finished == 0 {
+                        self.sd_images.clear();
+                    }
+                    self.sd_selected_image = self.sd_images.len();
+                    // Only a failed generation can be retried
+                    self.sd_last_request = None;
+                    // An upscale still running belongs to the images being replaced
+                    self.sd_pending_upscale = None;
+                    self.sd_generation_time = self.sd_generation_started.map(|started| started.elapsed());
+
+                    // Create textures from image bytes
+                    for generated in images {
+                        let name = format!("generated-image-{}", generated.index);
+                        match load_texture_from_bytes(ctx, &name, &generated.bytes) {
+                            Ok(texture) => self.sd_images.push(SDImage { generated, texture }),
+                            Err(e) => self.sd_error_message = Some(format!("Failed to decode image: {}", e)),
+                        }
+                    }
+                },
+                Err(e) => {
+                    log::error!("Image generation failed: {}", e);
+                    self.sd_error_message = Some(format!("Error: {}", e));
+                }
+            }
+            
+            self.finish_sd_queue_item(ctx, succeeded);
+        }
+
+        // Check if an upscale is complete
+        if let Some((source, promise)) = &self.sd_pending_upscale {
             if let Some(result) = promise.ready() {
-                self.sd_generating = false;
-                
                 match result {
-                    Ok(image_data) => {
-                        self.sd_image_bytes = Some(image_data.clone());
-                        
-                        // Create texture from image bytes
-                        let image = image::load_from_memory(&image_data)
-                            .expect("Failed to create image from data");
-                        let size = [image.width() as _, image.height() as _];
-                        let image_buffer = image.to_rgba8();
-                        let pixels = image_buffer.as_flat_samples();
-                        
-                        self.sd_image_texture = Some(ctx.load_texture(
-                            "generated-image",
-                            egui::ColorImage::from_rgba_unmultiplied(
-                                size,
-                                pixels.as_slice(),
-                            ),
-                            egui::TextureOptions::default(),
-                        ));
-                    },
+                    Ok(bytes) => {
+                        let source = *source;
+                        // Keep the source's info so the seed and parameters still apply
+                        let generated = self.sd_images.get(source).map(|image| GeneratedImage {
+                            bytes: bytes.clone(),
+                            ..image.generated.clone()
+                        });
+                        let name = format!("upscaled-image-{}", self.sd_images.len());
+                        if let Some(generated) = generated {
+                            match load_texture_from_bytes(ctx, &name, bytes) {
+                                Ok(texture) => {
+                                    self.sd_images.insert(source + 1, SDImage { generated, texture });
+                                    self.sd_selected_image = source + 1;
+                                }
+                                Err(e) => self.sd_error_message = Some(format!("Failed to decode image: {}", e)),
+                            }
+                        }
+                    }
                     Err(e) => {
-                        println!("Image generation failed: {}", e);
                         self.sd_error_message = Some(format!("Error: {}", e));
                     }
                 }
-                
-                self.sd_pending_generation = None;
+
+                self.sd_pending_upscale = None;
             }
         }
     }
@@ -1,33 +1,77 @@
+use chrono::{DateTime, Local, Utc};
 use eframe::egui;
-use reqwest;
 use std::time::Duration;
 
-use crate::chatapp::ChatApp;
+use crate::chat_export::ExportFormat;
+use crate::chatapp::{estimate_tokens, split_reasoning, strip_reasoning, ChatApp, ConnectionStatus, MessageSegment, SDQueueState, SD_SIZE_PRESETS};
+use crate::command_palette::Command;
+use crate::config::ThemePreference;
+use crate::llmclient;
+use crate::logging::{self, LogLevel};
+use crate::markdown::{render_markdown, render_markdown_with_cursor};
+use crate::prompt_history::PromptHistory;
+use crate::sampling_preset::SamplingPreset;
+use crate::sd_filename::{DEFAULT_FILENAME_TEMPLATE, FILENAME_TOKENS};
+use crate::sd_mask::{InpaintingFill, OutpaintSide};
+use crate::sdclient::GenerationDetails;
+use crate::tabs::TABS;
 
 impl eframe::App for ChatApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Request a repaint after a short delay
-        ctx.request_repaint_after(Duration::from_millis(16));
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // Keep redrawing only while output is streaming in. Otherwise egui repaints on input,
+        // and background tasks request a repaint when their results arrive.
+        if self.is_streaming()
+            || self.pending_pull.is_some()
+            || self.sd_pending_generation.is_some()
+            || self.sd_pending_upscale.is_some()
+        {
+            ctx.request_repaint_after(Duration::from_millis(16));
+        }
+
+        // Follows OS theme changes when set to "Follow System"
+        self.apply_theme(ctx, frame.info().system_theme);
+
+        self.track_window(ctx);
 
         // Process any incoming response chunks
         self.process_response_chunks(ctx);
+        self.process_comparison(ctx);
         
+        // Pick up finished model and SD option lists
+        self.process_scheduled_model_refresh(ctx);
+        self.process_model_refresh();
+        self.process_endpoint_detection(ctx);
+        self.process_model_pull(ctx);
+        self.process_model_delete(ctx);
+        self.process_model_card();
+        self.process_sd_options();
+        self.process_sd_prompt_refine();
+
         // Process SD generation progress
         self.process_sd_generation(ctx);
 
+        // Look up the context length of the selected model if we don't know it yet
+        self.refresh_context_length(ctx);
+
+        // Periodically ping the LLM endpoint for the status indicator
+        self.check_connection_health(ctx);
+
+        self.handle_dropped_files(ctx);
+
         // Top menu bar
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                if ui.button("☰").clicked() {
-                    self.show_settings = !self.show_settings;
-                    if self.show_settings && self.available_models.is_empty() {
-                        self.refresh_models(ctx);
-                    }
+                if ui.button("☰").on_hover_text("Settings (Ctrl+Shift+P for all commands)").clicked() {
+                    self.toggle_settings(ctx);
                 }
                 ui.label("LLM Chat");
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui.button("New Chat").clicked() {
-                        self.clear_chat();
+                        self.new_conversation();
+                    }
+                    render_connection_status(ui, self.connection_status);
+                    if let Some(status) = self.client.retry_status() {
+                        ui.label(egui::RichText::new(status).small().weak());
                     }
                 });
             });
@@ -36,45 +80,162 @@ impl eframe::App for ChatApp {
         // Add tab bar below the top menu
         egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.selectable_value(&mut self.active_tab, 0, "Chat");
-                ui.selectable_value(&mut self.active_tab, 1, "Stable Diffusion");
-                ui.selectable_value(&mut self.active_tab, 2, "Future Tab");
-                // Add more tabs as needed
+                for (index, tab) in TABS.iter().enumerate() {
+                    ui.selectable_value(&mut self.active_tab, index, tab.label());
+                }
             });
         });
 
+        // Drawn first so it gets Enter and Esc before the chat input does
+        self.show_command_palette(ctx);
+
         // Settings window
         if self.show_settings {
             self.show_settings_window(ctx);
         }
+        self.show_model_card_window(ctx);
+        if self.developer_tools && self.raw_stream {
+            self.show_raw_stream_window(ctx);
+        }
+        self.show_compare_replies_window(ctx);
+
+        let tab = self.current_tab();
+        tab.side_panels(self, ctx);
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            match self.active_tab {
-                0 => self.render_chat_tab(ui, ctx),
-                1 => self.render_stable_diffusion_tab(ui, ctx),
-                2 => self.render_future_tab(ui),
-                _ => self.render_chat_tab(ui, ctx), // Default to chat tab
-            }
+            tab.render(self, ui, ctx);
         });
+
+        render_drop_zone(ctx, tab.drop_hint());
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.to_config().save(storage);
     }
 }
 
 impl ChatApp {
-    fn render_chat_tab(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+    pub fn render_conversation_sidebar(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::left("conversations")
+            .resizable(true)
+            .default_width(180.0)
+            .show(ctx, |ui| {
+                ui.add_space(4.0);
+                if ui.button("➕ New Chat").clicked() {
+                    self.new_conversation();
+                }
+                ui.separator();
+
+                let mut switch_to = None;
+                let mut delete = None;
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (i, conversation) in self.conversations.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.small_button("✖").on_hover_text("Delete conversation").clicked() {
+                                delete = Some(i);
+                            }
+                            let title = conversation.display_title();
+                            if ui.selectable_label(i == self.active_conversation, title)
+                                .on_hover_text(title)
+                                .clicked()
+                            {
+                                switch_to = Some(i);
+                            }
+                        });
+                    }
+                });
+
+                if let Some(i) = switch_to {
+                    self.switch_conversation(i);
+                }
+                if let Some(i) = delete {
+                    self.delete_conversation(i);
+                }
+            });
+    }
+
+    /// Chat keyboard shortcuts. Handled before the input box is drawn so that Enter is
+    /// taken before it can insert a newline.
+    fn handle_chat_shortcuts(&mut self, ctx: &egui::Context) {
+        let focused = ctx.memory(|mem| mem.focus());
+        let input_focused = focused == Some(chat_input_id());
+        // Leave keys alone while typing somewhere else, e.g. in a message being edited
+        if focused.is_some() && !input_focused {
+            return;
+        }
+
+        let streaming = self.is_streaming();
+        let (send, stop, new_chat, focus_input) = ctx.input_mut(|i| {
+            let send = i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter)
+                || (!i.modifiers.shift && i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+            let stop = streaming && i.consume_key(egui::Modifiers::NONE, egui::Key::Escape);
+            let new_chat = i.consume_key(egui::Modifiers::COMMAND, egui::Key::L);
+            let focus_input = i.consume_key(egui::Modifiers::COMMAND, egui::Key::K);
+            (send, stop, new_chat, focus_input)
+        });
+
+        if send {
+            self.send_message(ctx);
+        }
+        if stop {
+            self.cancel_response();
+        }
+        if new_chat {
+            self.new_conversation();
+        }
+        if focus_input {
+            ctx.memory_mut(|mem| mem.request_focus(chat_input_id()));
+        }
+    }
+
+    pub fn render_chat_tab(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        self.handle_chat_shortcuts(ctx);
+
         let available_height = ui.available_height();
-        let input_area_height = 100.0; // Fixed height for input area
+        let mut input_area_height = 142.0; // Fixed height for input area, with the size counter and prefill
+        if self.attached_image.is_some() {
+            input_area_height += 72.0; // Room for the attachment thumbnail
+        }
+        if self.error_message.is_some() {
+            input_area_height += 44.0; // The error and its buttons
+        }
         
         // Use vertical layout to separate chat history and input
         ui.vertical(|ui| {
-            // Chat history area with calculated height
-            egui::ScrollArea::vertical()
-                .auto_shrink([false; 2])
-                .stick_to_bottom(true)
-                .max_height(available_height - input_area_height)
-                .show(ui, |ui| {
-                    self.render_chat_history(ui);
-                    self.render_current_response(ui);
-                });
+            // Chat history area with calculated height, beside the second model's when comparing
+            let history_height = available_height - input_area_height;
+            let output = if self.comparison.is_some() {
+                ui.columns(2, |columns| {
+                    self.render_comparison_side(&mut columns[1], history_height);
+
+                    let ui = &mut columns[0];
+                    let top = ui.cursor().top();
+                    ui.horizontal(|ui| {
+                        ui.set_min_height(ui.spacing().interact_size.y);
+                        ui.label(egui::RichText::new(format!("{} · {}", self.endpoint_type.label(), self.selected_model)).strong());
+                    });
+                    ui.separator();
+                    let height = history_height - (ui.cursor().top() - top);
+                    self.render_chat_scroll_area(ui, height)
+                })
+            } else {
+                self.render_chat_scroll_area(ui, history_height)
+            };
+
+            // Scrolling up stops following new output, scrolling back down resumes it
+            let max_offset = output.content_size.y - output.inner_rect.height();
+            self.follow_chat = output.state.offset.y >= max_offset - 1.0;
+            if !self.follow_chat {
+                egui::Area::new(egui::Id::new("jump_to_latest"))
+                    .pivot(egui::Align2::CENTER_BOTTOM)
+                    .fixed_pos(output.inner_rect.center_bottom() - egui::vec2(0.0, 8.0))
+                    .show(ctx, |ui| {
+                        if ui.button("↓ Jump to latest").clicked() {
+                            self.scroll_to_bottom = true;
+                        }
+                    });
+            }
 
             ui.add_space(8.0);
 
@@ -83,27 +244,246 @@ impl ChatApp {
                 ui.set_min_height(input_area_height);
                 
                 ui.vertical(|ui| {
+                    // Attached image thumbnail
+                    let mut remove_attachment = false;
+                    if let Some(attachment) = &self.attached_image {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Image::from_texture(&attachment.texture).max_height(64.0));
+                            if ui.small_button("✖").on_hover_text("Remove image").clicked() {
+                                remove_attachment = true;
+                            }
+                        });
+                    }
+                    if remove_attachment {
+                        self.attached_image = None;
+                    }
+
+                    if let Some(error) = &self.error_message {
+                        // Only a failed connection has something to retry
+                        let retry_hover = (self.connection_status == ConnectionStatus::Offline).then_some("Fetch the models again");
+                        if render_error(ui, error, retry_hover) {
+                            self.refresh_models(ctx);
+                        }
+                    }
+
                     // Text input
                     ui.add_sized(
                         [ui.available_width(), 70.0],
                         egui::TextEdit::multiline(&mut self.input)
-                            .hint_text("Type your message here... (Press Enter to send, Shift+Enter for new line)")
+                            .id(chat_input_id())
+                            .hint_text("Type your message here... (Enter or Ctrl+Enter to send, Shift+Enter for new line, \
+                                Esc to stop, Ctrl+L for a new chat, Ctrl+K to focus)")
                             .desired_rows(3),
                     );
 
+                    // Size of the message being typed; the gauge below covers the whole conversation
+                    ui.label(egui::RichText::new(format!(
+                        "{} chars · {} words · ~{} tokens",
+                        self.input.chars().count(),
+                        self.input.split_whitespace().count(),
+                        estimate_tokens(&self.input),
+                    )).small().weak());
+
+                    ui.horizontal(|ui| {
+                        ui.label("Reply starts with:");
+                        ui.add(egui::TextEdit::singleline(&mut self.prefill)
+                            .hint_text("optional, e.g. Sure, here's the code:")
+                            .desired_width(260.0))
+                            .on_hover_text("The model continues from this text. Not every OpenAI-compatible server supports it.");
+                        ui.checkbox(&mut self.prefill_sticky, "Sticky")
+                            .on_hover_text("Keep the prefill for every message instead of clearing it after sending");
+                    });
+
                     // Send button
                     ui.horizontal(|ui| {
-                        if ui.button("Send").clicked() || 
-                           (ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift)) {
+                        if ui.button("Send").clicked() {
                             self.send_message(ctx);
                         }
+
+                        if ui.button("Attach Image").on_hover_text("Attach an image for vision models").clicked() {
+                            self.pick_image_attachment(ctx);
+                        }
+
+                        render_prompt_history(ui, &mut self.chat_prompts, &mut self.input);
+
+                        if self.is_streaming()
+                            && ui.button("Stop").on_hover_text("Stop generating").clicked()
+                        {
+                            self.cancel_response();
+                        }
+
+                        if self.can_regenerate()
+                            && ui.button("Regenerate").on_hover_text("Generate a new reply to the last message").clicked()
+                        {
+                            self.regenerate_response(ctx);
+                        }
+
+                        let mut comparing = self.comparison.is_some();
+                        if ui.checkbox(&mut comparing, "Compare")
+                            .on_hover_text("Also send every prompt to a second model and show its replies side by side")
+                            .changed()
+                        {
+                            self.toggle_comparison(ctx);
+                        }
+
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            self.render_context_gauge(ui);
+                        });
                     });
                 });
             });
         });
     }
 
-    fn render_stable_diffusion_tab(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+    fn render_chat_scroll_area(&mut self, ui: &mut egui::Ui, height: f32) -> egui::scroll_area::ScrollAreaOutput<()> {
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .stick_to_bottom(self.follow_chat)
+            .max_height(height)
+            .show(ui, |ui| {
+                self.render_chat_history(ui);
+                self.render_current_response(ui);
+
+                if self.scroll_to_bottom {
+                    ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
+                    self.scroll_to_bottom = false;
+                }
+            })
+    }
+
+    /// The second model's column in comparison mode, with its own endpoint and model pickers.
+    /// Regenerating, editing and continuing only apply to the main column.
+    fn render_comparison_side(&mut self, ui: &mut egui::Ui, height: f32) {
+        let ctx = ui.ctx().clone();
+        let top = ui.cursor().top();
+        let Some(side) = &mut self.comparison else {
+            return;
+        };
+
+        let mut endpoint_type = side.endpoint_type;
+        let mut refresh = false;
+        ui.horizontal(|ui| {
+            ui.set_min_height(ui.spacing().interact_size.y);
+            egui::ComboBox::from_id_source("comparison_endpoint_select")
+                .selected_text(endpoint_type.label())
+                .show_ui(ui, |ui| {
+                    for option in crate::endpoint_type::EndpointType::ALL {
+                        ui.selectable_value(&mut endpoint_type, option, option.label());
+                    }
+                })
+                .response
+                .on_hover_text("Uses the server last set up for this type in the settings");
+            let open = egui::ComboBox::from_id_source("comparison_model_select")
+                .selected_text(&side.model)
+                .show_ui(ui, |ui| {
+                    render_combo_filter(ui, &mut side.model_filter);
+                    for model in side.available_models.iter().filter(|model| matches_filter(&side.model_filter, model)) {
+                        ui.selectable_value(&mut side.model, model.clone(), model);
+                    }
+                })
+                .inner
+                .is_some();
+            if !open {
+                side.model_filter.clear();
+            }
+            refresh = ui.button("⟳").on_hover_text("Refresh model list").clicked();
+            if side.pending_models.is_some() {
+                ui.spinner();
+            }
+        });
+        ui.separator();
+
+        if endpoint_type != side.endpoint_type {
+            self.set_comparison_endpoint_type(&ctx, endpoint_type);
+        } else if refresh {
+            self.refresh_comparison_models(&ctx);
+        }
+
+        let Some(side) = &self.comparison else {
+            return;
+        };
+        let height = height - (ui.cursor().top() - top);
+        egui::ScrollArea::vertical()
+            .id_source("comparison_history")
+            .auto_shrink([false; 2])
+            .stick_to_bottom(true)
+            .max_height(height)
+            .show(ui, |ui| {
+                for entry in &side.history {
+                    let is_error = entry.role == "error";
+                    ui.horizontal(|ui| {
+                        let name = match entry.role.as_str() {
+                            "error" => ui.label(egui::RichText::new("Error: ").strong().color(egui::Color32::RED)),
+                            "assistant" => ui.label(egui::RichText::new(format!("{}: ", side.model)).strong()),
+                            role => self.render_speaker(ui, role),
+                        };
+                        if let Some(timestamp) = entry.timestamp {
+                            self.render_message_time(ui, name, timestamp);
+                        }
+                        render_copy_button(ui, &entry.content);
+                    });
+                    if is_error {
+                        ui.add(egui::Label::new(egui::RichText::new(&entry.content).color(egui::Color32::RED)).wrap(true).selectable(true));
+                    } else {
+                        self.render_message_content(ui, &entry.content);
+                    }
+                    if let Some(error) = &entry.error {
+                        ui.label(egui::RichText::new("⚠️ (response interrupted)").small().color(ui.visuals().warn_fg_color))
+                            .on_hover_text(error);
+                    }
+                    if let Some(stats) = &entry.stats {
+                        ui.label(egui::RichText::new(stats.summary()).small().weak());
+                    }
+                    ui.add_space(8.0);
+                }
+
+                let streaming = side.is_streaming();
+                if streaming || !side.current_response.is_empty() {
+                    let waiting = streaming && self.disable_streaming;
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(format!("{}: ", side.model)).strong());
+                        if waiting {
+                            ui.spinner();
+                        }
+                    });
+                    let cursor = (streaming && !waiting).then(|| self.streaming_cursor_color(ui));
+                    self.render_message_content_with_cursor(ui, &side.current_response, cursor);
+                }
+            });
+    }
+
+    fn render_context_gauge(&self, ui: &mut egui::Ui) {
+        let used = self.estimate_context_tokens();
+
+        match self.context_length() {
+            Some(limit) => {
+                let fill = used as f32 / limit.max(1) as f32;
+                let mut gauge = egui::ProgressBar::new(fill.min(1.0))
+                    .desired_width(160.0)
+                    .text(format!("~{} / {} tokens", used, limit));
+
+                if fill >= 0.9 {
+                    gauge = gauge.fill(egui::Color32::from_rgb(200, 60, 60));
+                } else if fill >= 0.75 {
+                    gauge = gauge.fill(egui::Color32::from_rgb(210, 150, 40));
+                }
+
+                ui.add(gauge)
+                    .on_hover_text("Estimated context usage (~4 characters per token)");
+
+                if fill >= 0.9 {
+                    ui.label(egui::RichText::new("⚠ Near context limit").color(ui.visuals().warn_fg_color))
+                        .on_hover_text("The model may forget the start of the conversation or cut its reply short. Consider starting a new chat.");
+                }
+            }
+            None => {
+                ui.label(format!("~{} tokens", used))
+                    .on_hover_text("Context length unknown for this model. You can set it in Advanced Settings.");
+            }
+        }
+    }
+
+    pub fn render_stable_diffusion_tab(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
         // Only load SD options if not in the settings window
         if !self.show_settings {
             self.load_sd_options(ctx);
@@ -125,27 +505,47 @@ impl ChatApp {
                     egui::Frame::dark_canvas(ui.style())
                         .stroke(egui::Stroke::new(1.0, egui::Color32::WHITE))
                         .show(ui, |ui| {
-                            if let Some(texture) = &self.sd_image_texture {
-                                // Display the generated image with proper scaling
-                                let size = texture.size_vec2();
-                                let max_size = ui.available_size();
-                                let ratio = (max_size.x / size.x).min(max_size.y / size.y);
-                                let display_size = egui::vec2(size.x * ratio, size.y * ratio);
-                                
-                                // Using the texture handle directly
-                                let image = egui::Image::from_texture(texture)
-                                    .fit_to_exact_size(display_size);
-                                ui.add(image);
-                            } else if self.sd_generating {
-                                // Show progress
-                                ui.spinner();
-                                ui.label(format!("Generating image... {}%", self.sd_progress));
+                            if self.sd_generating {
+                                ui.vertical_centered(|ui| {
+                                    // Show the live preview while the sampler is running
+                                    if let Some(texture) = &self.sd_preview_texture {
+                                        let size = texture.size_vec2();
+                                        let max_size = ui.available_size() - egui::vec2(0.0, 24.0);
+                                        let ratio = (max_size.x / size.x).min(max_size.y / size.y);
+                                        ui.add(egui::Image::from_texture(texture).fit_to_exact_size(size * ratio));
+                                    } else {
+                                        ui.spinner();
+                                    }
+
+                                    let mut status = format!("Generating image... {:.0}%", self.sd_progress);
+                                    if self.sd_eta > 0.0 {
+                                        status.push_str(&format!(" (~{:.0}s remaining)", self.sd_eta));
+                                    }
+                                    ui.label(status);
+                                });
+                            } else if self.sd_mask_editing && self.sd_init_image.is_some() {
+                                self.render_sd_mask_editor(ui, ctx);
+                            } else if !self.sd_images.is_empty() {
+                                self.render_sd_gallery(ui);
                             } else if let Some(error) = &self.sd_error_message {
+                                let mut retry = false;
                                 ui.vertical_centered(|ui| {
                                     ui.colored_label(egui::Color32::RED, "Generation failed!");
                                     ui.add_space(10.0);
-                                    ui.label(error);
+                                    ui.add(egui::Label::new(error.as_str()).selectable(true));
+                                    ui.add_space(10.0);
+                                    ui.horizontal(|ui| {
+                                        if ui.button("Copy error").clicked() {
+                                            ui.output_mut(|o| o.copied_text = error.clone());
+                                        }
+                                        if self.sd_last_request.is_some() {
+                                            retry = ui.button("Retry").on_hover_text("Run the failed generation again").clicked();
+                                        }
+                                    });
                                 });
+                                if retry {
+                                    self.retry_sd_generation(ctx);
+                                }
                             } else {
                                 // Initial state
                                 ui.heading("Enter a prompt below to generate an image");
@@ -170,7 +570,20 @@ impl ChatApp {
                             ui.heading("Create an image with Stable Diffusion");
                             
                             // Prompt and negative prompt
-                            ui.label("Prompt:");
+                            ui.horizontal(|ui| {
+                                ui.label("Prompt:");
+                                render_prompt_history(ui, &mut self.sd_prompts, &mut self.sd_prompt);
+                                let refining = self.sd_refine_pending.is_some();
+                                if ui.add_enabled(!refining && !self.sd_prompt.trim().is_empty(), egui::Button::new("✨ Refine"))
+                                    .on_hover_text("Have the chat model rewrite this as a detailed Stable Diffusion prompt")
+                                    .clicked()
+                                {
+                                    self.refine_sd_prompt(ctx);
+                                }
+                                if refining {
+                                    ui.spinner();
+                                }
+                            });
                             
                             ui.add_sized(
                                 [ui.available_width(), 60.0],
@@ -186,20 +599,76 @@ impl ChatApp {
                                     .hint_text("blurry, low quality...")
                                     .desired_rows(1)
                             );
+
+                            self.render_sd_init_image(ui, ctx);
                             
                             ui.add_space(10.0);
                             
                             ui.horizontal(|ui| {
-                                if ui.button("Generate Image").clicked() && !self.sd_prompt.is_empty() && !self.sd_generating {
+                                if ui.add_enabled(!self.sd_prompt.is_empty() && !self.sd_busy(), egui::Button::new("Generate Image")).clicked() {
                                     self.generate_sd_image(ctx);
                                 }
+
+                                if ui.add_enabled(!self.sd_prompt.is_empty(), egui::Button::new("Add to queue"))
+                                    .on_hover_text("Queue this prompt with the current settings")
+                                    .clicked()
+                                {
+                                    self.add_sd_to_queue();
+                                }
+
+                                if self.sd_generating && ui.button("Interrupt").on_hover_text("Stop the running generation").clicked() {
+                                    self.interrupt_sd_generation(ctx);
+                                }
                                 
-                                if let Some(_) = &self.sd_image_bytes {
-                                    if ui.button("Save Image").clicked() {
-                                        self.save_sd_image();
+                                if !self.sd_images.is_empty() && ui.button("Save Image").clicked() {
+                                    self.save_sd_image();
+                                }
+
+                                if self.sd_images.len() > 1 && ui.button("Save All").clicked() {
+                                    self.save_all_sd_images();
+                                }
+
+                                if !self.sd_images.is_empty()
+                                    && ui.button("Copy Image").on_hover_text("Copy the shown image to paste it into other apps").clicked()
+                                {
+                                    self.copy_sd_image();
+                                }
+
+                                if let Some(status) = &self.sd_save_status {
+                                    ui.label(egui::RichText::new(status).small().weak());
+                                }
+
+                                if !self.sd_images.is_empty()
+                                    && ui.button("Use as source").on_hover_text("Use this image for img2img or inpainting").clicked()
+                                {
+                                    self.use_sd_image_as_source(ctx);
+                                }
+
+                                if let Some(seed) = self.sd_last_seed() {
+                                    ui.label(format!("Seed: {}", seed));
+                                    if ui.small_button("Reuse seed").on_hover_text("Use this seed for the next generation").clicked() {
+                                        self.sd_seed = Some(seed);
+                                    }
+                                }
+
+                                // Errors are otherwise hidden behind the displayed image
+                                if !self.sd_images.is_empty() {
+                                    if let Some(error) = &self.sd_error_message {
+                                        let retry_hover = self.sd_last_request.is_some().then_some("Run the failed generation again");
+                                        if render_error(ui, error, retry_hover) {
+                                            self.retry_sd_generation(ctx);
+                                        }
                                     }
                                 }
                             });
+
+                            if !self.sd_images.is_empty() {
+                                self.render_sd_upscale_controls(ui);
+                            }
+
+                            if !self.sd_queue.is_empty() || self.sd_queue_current.is_some() {
+                                self.render_sd_queue(ui, ctx);
+                            }
                         });
                 }
             );
@@ -209,91 +678,566 @@ impl ChatApp {
         });
     }
 
-    fn render_future_tab(&mut self, ui: &mut egui::Ui) {
-        ui.centered_and_justified(|ui| {
-            ui.heading("Future Feature Coming Soon!");
+    fn render_sd_queue(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let title = format!("Queue ({})", self.sd_queue.len() + usize::from(self.sd_queue_current.is_some()));
+        egui::CollapsingHeader::new(title)
+            .id_source("sd_queue")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    match self.sd_queue_state {
+                        SDQueueState::Running => {
+                            if ui.button("⏸ Pause").on_hover_text("Stop after the current image").clicked() {
+                                self.pause_sd_queue();
+                            }
+                        }
+                        state => {
+                            let label = if state == SDQueueState::Paused { "▶ Resume" } else { "▶ Start" };
+                            if ui.add_enabled(!self.sd_queue.is_empty(), egui::Button::new(label)).clicked() {
+                                self.start_sd_queue(ctx);
+                            }
+                        }
+                    }
+                    if ui.add_enabled(!self.sd_queue.is_empty(), egui::Button::new("Clear")).clicked() {
+                        self.clear_sd_queue();
+                    }
+                    if self.sd_queue_finished > 0 {
+                        ui.label(egui::RichText::new(format!("{} done", self.sd_queue_finished)).weak());
+                    }
+                });
+
+                let mut remove = None;
+                egui::ScrollArea::vertical()
+                    .id_source("sd_queue_list")
+                    .max_height(100.0)
+                    .show(ui, |ui| {
+                        if let Some(current) = &self.sd_queue_current {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label(egui::RichText::new(prompt_preview(&current.prompt)).strong())
+                                    .on_hover_text(&current.prompt);
+                            });
+                        }
+                        for (i, item) in self.sd_queue.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{}.", i + 1));
+                                ui.label(prompt_preview(&item.prompt)).on_hover_text(&item.prompt);
+                                if ui.small_button("✖").on_hover_text("Remove from the queue").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        }
+                    });
+                if let Some(i) = remove {
+                    self.sd_queue.remove(i);
+                    if self.sd_queue.is_empty() && self.sd_queue_current.is_none() {
+                        self.sd_queue_state = SDQueueState::Idle;
+                    }
+                }
+            });
+    }
+
+    fn render_sd_upscale_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let upscaling = self.sd_pending_upscale.is_some();
+            if ui.add_enabled(!upscaling && !self.sd_generating, egui::Button::new("Upscale"))
+                .on_hover_text("Upscale the shown image without generating it again")
+                .clicked()
+            {
+                self.upscale_sd_image(ui.ctx());
+            }
+            if upscaling {
+                ui.spinner();
+            }
+
+            egui::ComboBox::from_id_source("sd_upscaler_select")
+                .selected_text(&self.sd_selected_upscaler)
+                .show_ui(ui, |ui| {
+                    for upscaler in &self.sd_upscalers {
+                        ui.selectable_value(&mut self.sd_selected_upscaler, upscaler.clone(), upscaler);
+                    }
+                });
+
+            ui.add(egui::DragValue::new(&mut self.sd_upscale_factor)
+                .clamp_range(1.0..=4.0)
+                .speed(0.05)
+                .suffix("x"));
         });
     }
 
-    fn render_chat_history(&self, ui: &mut egui::Ui) {
-        for (role, content) in &self.chat_history {
-            let is_user = role == "user";
-            let is_error = role == "error";
+    fn render_sd_lora_rows(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+
+        for i in 0..self.sd_selected_loras.len() {
             ui.horizontal(|ui| {
-                if is_user {
-                    ui.label(egui::RichText::new("You: ").strong());
-                } else if is_error {
-                    ui.label(egui::RichText::new("Error: ").strong().color(egui::Color32::RED));
-                } else {
-                    ui.label(egui::RichText::new("LLM: ").strong());
+                let selected = self.sd_selected_loras[i].0.clone();
+                let display_name = self.sd_loras.iter()
+                    .find(|lora| lora.name == selected)
+                    .map(|lora| lora.alias.clone().unwrap_or_else(|| lora.name.clone()))
+                    .unwrap_or_else(|| selected.clone());
+
+                egui::ComboBox::from_id_source(("sd_lora_select", i))
+                    .selected_text(display_name)
+                    .show_ui(ui, |ui| {
+                        for lora in &self.sd_loras {
+                            // Each LoRA can only be added once
+                            let in_other_row = self.sd_selected_loras.iter()
+                                .enumerate()
+                                .any(|(row, (name, _))| row != i && *name == lora.name);
+                            if in_other_row {
+                                continue;
+                            }
+
+                            let name = lora.alias.clone().unwrap_or_else(|| lora.name.clone());
+                            if ui.selectable_label(selected == lora.name, name).clicked() {
+                                self.sd_selected_loras[i].0 = lora.name.clone();
+                            }
+                        }
+                    });
+
+                ui.add(egui::Slider::new(&mut self.sd_selected_loras[i].1, crate::chatapp::LORA_WEIGHT_RANGE).step_by(0.05))
+                    .on_hover_text("LoRA weight");
+
+                if ui.small_button("✖").on_hover_text("Remove LoRA").clicked() {
+                    remove = Some(i);
                 }
             });
-            if is_error {
-                ui.label(egui::RichText::new(content).color(egui::Color32::RED));
-            } else {
-                self.render_message_content(ui, content);
-            }
-            ui.add_space(8.0);
+        }
+
+        if let Some(i) = remove {
+            self.sd_selected_loras.remove(i);
+        }
+
+        let can_add = self.sd_selected_loras.len() < self.sd_loras.len();
+        if ui.add_enabled(can_add, egui::Button::new("➕ Add LoRA")).clicked() {
+            self.add_sd_lora();
         }
     }
 
-    fn render_current_response(&self, ui: &mut egui::Ui) {
-        if !self.current_response.is_empty() {
+    /// The img2img source image, if one was dropped on the tab.
+    fn render_sd_init_image(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut remove = false;
+        let mut read_png_info = false;
+        let mut edit_mask = false;
+
+        if let Some(init) = &self.sd_init_image {
+            let masked = self.sd_mask.as_ref().is_some_and(|mask| !mask.is_empty());
             ui.horizontal(|ui| {
-                ui.label(egui::RichText::new("LLM: ").strong());
+                ui.add(egui::Image::from_texture(&init.texture).max_height(40.0));
+                ui.label(if masked { "inpaint" } else { "img2img" });
+                ui.label("Denoising:");
+                ui.add(egui::Slider::new(&mut self.sd_img2img_denoising, 0.0..=1.0).step_by(0.01))
+                    .on_hover_text("How much to change the source image (0 = keep it, 1 = ignore it)");
+                if ui.button("Use PNG Info").on_hover_text("Fill in the settings this image was generated with").clicked() {
+                    read_png_info = true;
+                }
+                if ui.selectable_label(self.sd_mask_editing, "🖌 Mask")
+                    .on_hover_text("Paint the areas to regenerate, or extend the image to outpaint")
+                    .clicked()
+                {
+                    edit_mask = true;
+                }
+                if ui.small_button("✖").on_hover_text("Remove source image").clicked() {
+                    remove = true;
+                }
             });
-            self.render_message_content(ui, &self.current_response);
+        }
+
+        if read_png_info {
+            if let Some(init) = &self.sd_init_image {
+                let bytes = init.bytes.clone();
+                self.load_sd_png_info(ctx, bytes);
+            }
+        }
+        if edit_mask {
+            if self.sd_mask_editing {
+                self.sd_mask_editing = false;
+            } else {
+                self.start_sd_mask_editing(ctx);
+            }
+        }
+        if remove {
+            self.sd_init_image = None;
+            self.sd_mask = None;
+            self.sd_mask_editing = false;
         }
     }
 
-    fn render_message_content(&self, ui: &mut egui::Ui, content: &str) {
-        let parts: Vec<&str> = content.split("<think>").collect();
-        for (i, part) in parts.iter().enumerate() {
-            if i == 0 && !part.is_empty() {
-                ui.label(*part);
-            } else if !part.is_empty() {
-                let think_parts: Vec<&str> = part.split("</think>").collect();
-                if think_parts.len() > 0 {
-                    if !think_parts[0].is_empty() {
-                        egui::Frame::none()
-                            .fill(egui::Color32::from_rgb(47, 45, 56))
-                            .inner_margin(egui::style::Margin::same(8.0))
-                            .show(ui, |ui| {
-                                ui.horizontal(|ui| {
-                                    ui.label(egui::RichText::new("🤔 Thinking...")
-                                        .color(egui::Color32::from_rgb(167, 139, 250))
-                                        .strong());
-                                });
-                                ui.label(
-                                    egui::RichText::new(think_parts[0])
-                                        .color(egui::Color32::LIGHT_GRAY)
-                                );
-                            });
+    /// Inpainting settings above the source image, which is painted on with the mouse.
+    fn render_sd_mask_editor(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut load_mask = false;
+        let mut extend = None;
+
+        ui.vertical(|ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Brush:");
+                ui.add(egui::Slider::new(&mut self.sd_mask_brush, 2.0..=256.0).logarithmic(true).suffix(" px"));
+                ui.checkbox(&mut self.sd_mask_erase, "Erase");
+                if ui.button("Clear").clicked() {
+                    if let Some(mask) = &mut self.sd_mask {
+                        mask.clear();
                     }
-                    if think_parts.len() > 1 && !think_parts[1].is_empty() {
-                        ui.label(think_parts[1]);
+                }
+                load_mask = ui.button("Load mask…").on_hover_text("Use a black and white image, white marking what to regenerate").clicked();
+                if ui.button("Done").clicked() {
+                    self.sd_mask_editing = false;
+                }
+            });
+
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Masked content:");
+                egui::ComboBox::from_id_source("sd_inpainting_fill")
+                    .selected_text(self.sd_inpainting_fill.label())
+                    .show_ui(ui, |ui| {
+                        for fill in InpaintingFill::ALL {
+                            ui.selectable_value(&mut self.sd_inpainting_fill, fill, fill.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("What the masked area starts from. Latent noise works best for outpainting");
+                ui.label("Mask blur:");
+                ui.add(egui::DragValue::new(&mut self.sd_mask_blur).clamp_range(0..=64).suffix(" px"));
+                ui.checkbox(&mut self.sd_inpaint_full_res, "Only masked")
+                    .on_hover_text("Inpaint just the masked area at full resolution, then paste it back");
+                if self.sd_inpaint_full_res {
+                    ui.label("Padding:");
+                    ui.add(egui::DragValue::new(&mut self.sd_inpaint_padding).clamp_range(0..=256).suffix(" px"));
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Outpaint:");
+                for side in OutpaintSide::ALL {
+                    if ui.button(side.label()).on_hover_text("Extend the image on this side and mask the new part").clicked() {
+                        extend = Some(side);
                     }
                 }
-            }
-        }
-    }
+                ui.add(egui::DragValue::new(&mut self.sd_outpaint_amount).clamp_range(8..=512).speed(8).suffix(" px"));
+            });
 
-    pub fn show_settings_window(&mut self, ctx: &egui::Context) {
-        // Check for model list updates or errors
-        if let Some(error) = ctx.memory_mut(|mem| mem.data.remove_temp::<String>(egui::Id::new("models_error"))) {
-            self.error_message = Some(error);
-            self.models_loading = false;
-        }
-        if let Some(models) = ctx.memory_mut(|mem| mem.data.remove_temp::<Vec<String>>(egui::Id::new("available_models"))) {
-            self.available_models = models;
-            self.models_loading = false;
-            
-            // Select the first model if none selected
-            if self.selected_model == "local-model" && !self.available_models.is_empty() {
-                self.selected_model = self.available_models[0].clone();
-            }
-        }
+            let (Some(init), Some(mask)) = (&self.sd_init_image, &mut self.sd_mask) else {
+                return;
+            };
 
+            ui.vertical_centered(|ui| {
+                let size = init.texture.size_vec2();
+                let max_size = ui.available_size();
+                let ratio = (max_size.x / size.x).min(max_size.y / size.y);
+                let response = ui.add(egui::Image::from_texture(&init.texture)
+                    .fit_to_exact_size(size * ratio)
+                    .sense(egui::Sense::drag()));
+
+                let full = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                ui.painter().image(mask.texture.id(), response.rect, full, egui::Color32::WHITE);
+
+                if response.is_pointer_button_down_on() {
+                    if let Some(pointer) = response.interact_pointer_pos() {
+                        let point = (pointer - response.rect.min) / ratio;
+                        mask.stroke_to(point.to_pos2(), self.sd_mask_brush, self.sd_mask_erase);
+                    }
+                } else {
+                    mask.end_stroke();
+                }
+
+                if let Some(pointer) = response.hover_pos() {
+                    ui.painter().circle_stroke(pointer, self.sd_mask_brush * ratio, egui::Stroke::new(1.0, egui::Color32::WHITE));
+                }
+            });
+        });
+
+        if load_mask {
+            self.load_sd_mask_file(ctx);
+        }
+        if let Some(side) = extend {
+            self.extend_sd_canvas(ctx, side);
+        }
+    }
+
+    /// The selected image scaled to fit, with a strip of thumbnails to pick from when there are several.
+    fn render_sd_gallery(&mut self, ui: &mut egui::Ui) {
+        const THUMBNAIL_SIZE: f32 = 64.0;
+
+        ui.vertical_centered(|ui| {
+            if let Some(details) = self.selected_sd_image().and_then(|image| image.generated.details()) {
+                egui::CollapsingHeader::new("Generation details")
+                    .id_source("sd_generation_details")
+                    .show(ui, |ui| render_generation_details(ui, &details, self.sd_generation_time));
+            }
+
+            let mut max_size = ui.available_size();
+            if self.sd_images.len() > 1 {
+                max_size.y -= THUMBNAIL_SIZE + 16.0;
+            }
+
+            if let Some(selected) = self.selected_sd_image() {
+                let size = selected.texture.size_vec2();
+                let ratio = (max_size.x / size.x).min(max_size.y / size.y);
+                ui.add(egui::Image::from_texture(&selected.texture).fit_to_exact_size(size * ratio));
+            }
+
+            if self.sd_images.len() > 1 {
+                let mut clicked = None;
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for (i, image) in self.sd_images.iter().enumerate() {
+                            let thumbnail = egui::Image::from_texture(&image.texture)
+                                .max_size(egui::vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+                            let response = ui.add(egui::ImageButton::new(thumbnail).selected(i == self.sd_selected_image));
+                            if response.on_hover_text(format!("Image {}", i + 1)).clicked() {
+                                clicked = Some(i);
+                            }
+                        }
+                    });
+                });
+                if let Some(i) = clicked {
+                    self.sd_selected_image = i;
+                }
+            }
+        });
+    }
+
+    fn render_chat_history(&mut self, ui: &mut egui::Ui) {
+        // Editing is only possible while no reply is streaming
+        let can_edit = self.pending_response.is_none();
+        // Drop an edit whose message has gone away, e.g. after regenerating
+        let mut editing = self.editing_message.take()
+            .filter(|(index, _)| matches!(self.chat_history.get(*index), Some(entry) if entry.role == "user"));
+        let mut cancel_edit = false;
+        let mut resend = None;
+        let mut continue_reply = false;
+        let mut select_reply = None;
+        let mut compare = None;
+        let can_continue = self.can_continue();
+        let mut plain_text = self.plain_text_message;
+
+        for (i, entry) in self.chat_history.iter().enumerate() {
+            let content = &entry.content;
+            let is_user = entry.role == "user";
+            let is_error = entry.role == "error";
+            ui.horizontal(|ui| {
+                let name = if is_error {
+                    ui.label(egui::RichText::new("Error: ").strong().color(egui::Color32::RED))
+                } else {
+                    self.render_speaker(ui, &entry.role)
+                };
+                if let Some(timestamp) = entry.timestamp {
+                    self.render_message_time(ui, name, timestamp);
+                }
+                render_copy_button(ui, content);
+                if !entry.images.is_empty() {
+                    ui.label("🖼").on_hover_text(format!("Sent with {} image(s), which are sent again when regenerating or editing", entry.images.len()));
+                }
+                let showing_plain = plain_text == Some(i);
+                if ui.add(egui::SelectableLabel::new(showing_plain, "🔤"))
+                    .on_hover_text("Show as plain text to select across the whole message")
+                    .clicked()
+                {
+                    plain_text = if showing_plain { None } else { Some(i) };
+                }
+                if is_user
+                    && editing.is_none()
+                    && ui.add_enabled(can_edit, egui::Button::new("✏").small())
+                        .on_hover_text("Edit and resend from here")
+                        .clicked()
+                {
+                    editing = Some((i, content.clone()));
+                }
+                let count = entry.reply_count();
+                if count > 1 {
+                    let index = entry.reply_index.min(count - 1);
+                    if ui.add_enabled(can_edit && index > 0, egui::Button::new("◀").small())
+                        .on_hover_text("Previous reply")
+                        .clicked()
+                    {
+                        select_reply = Some((i, index - 1));
+                    }
+                    ui.label(egui::RichText::new(format!("{}/{}", index + 1, count)).small());
+                    if ui.add_enabled(can_edit && index + 1 < count, egui::Button::new("▶").small())
+                        .on_hover_text("Next reply")
+                        .clicked()
+                    {
+                        select_reply = Some((i, index + 1));
+                    }
+                    if ui.small_button("⇔").on_hover_text("Compare the replies side by side").clicked() {
+                        compare = Some(i);
+                    }
+                }
+            });
+
+            if let Some((_, draft)) = editing.as_mut().filter(|(index, _)| *index == i) {
+                ui.add(egui::TextEdit::multiline(draft).desired_width(f32::INFINITY).desired_rows(2));
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(can_edit, egui::Button::new("Save & Resend"))
+                        .on_hover_text("Replaces this message and discards everything after it")
+                        .clicked()
+                    {
+                        resend = Some((i, draft.clone()));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel_edit = true;
+                    }
+                });
+            } else if plain_text == Some(i) {
+                // Read-only, but unlike separate labels it allows one selection over everything
+                ui.add(egui::TextEdit::multiline(&mut content.as_str()).desired_width(f32::INFINITY));
+            } else if is_error {
+                ui.add(egui::Label::new(egui::RichText::new(content).color(egui::Color32::RED)).wrap(true).selectable(true));
+            } else {
+                self.render_message_content(ui, content);
+            }
+            if let Some(error) = &entry.error {
+                ui.label(egui::RichText::new("⚠️ (response interrupted)").small().color(ui.visuals().warn_fg_color))
+                    .on_hover_text(error);
+            }
+            if let Some(stats) = &entry.stats {
+                ui.label(egui::RichText::new(stats.summary()).small().weak());
+            }
+            if can_continue
+                && i == self.chat_history.len() - 1
+                && ui.button("Continue").on_hover_text("Generate the rest of this reply, which hit the token limit").clicked()
+            {
+                continue_reply = true;
+            }
+            ui.add_space(8.0);
+        }
+
+        self.plain_text_message = plain_text;
+
+        if let Some((i, index)) = select_reply {
+            self.select_reply(i, index);
+        }
+        if compare.is_some() {
+            self.compare_replies = compare;
+        }
+
+        if continue_reply {
+            self.continue_response(ui.ctx());
+        }
+
+        if let Some((index, text)) = resend {
+            self.resend_edited_message(ui.ctx(), index, text);
+        } else if !cancel_edit {
+            self.editing_message = editing;
+        }
+    }
+
+    /// The avatar and name above a message, returning the name label.
+    fn render_speaker(&self, ui: &mut egui::Ui, role: &str) -> egui::Response {
+        let (name, avatar) = self.speaker(role);
+        if !avatar.is_empty() {
+            ui.label(egui::RichText::new(avatar).size(18.0));
+        }
+        ui.label(egui::RichText::new(format!("{}: ", name)).strong())
+    }
+
+    /// The time a message was sent, inline if enabled and in full when hovering it or the `name` label.
+    fn render_message_time(&self, ui: &mut egui::Ui, name: egui::Response, timestamp: DateTime<Utc>) {
+        let local = timestamp.with_timezone(&Local);
+        let full = local.format("%A, %B %-d %Y, %H:%M:%S").to_string();
+        if self.show_timestamps {
+            // Just the time for today's messages
+            let short = if local.date_naive() == Local::now().date_naive() {
+                local.format("%H:%M").to_string()
+            } else {
+                local.format("%Y-%m-%d %H:%M").to_string()
+            };
+            ui.label(egui::RichText::new(short).small().weak()).on_hover_text(full);
+        } else {
+            name.on_hover_text(full);
+        }
+    }
+
+    fn render_current_response(&self, ui: &mut egui::Ui) {
+        let streaming = self.pending_response.is_some();
+        if !self.current_response.is_empty() || streaming {
+            // Without streaming the reply arrives all at once, so there is nothing for a cursor to follow
+            let waiting = streaming && self.disable_streaming;
+            ui.horizontal(|ui| {
+                self.render_speaker(ui, "assistant");
+                if !self.current_response.is_empty() {
+                    render_copy_button(ui, &self.current_response);
+                }
+                if waiting {
+                    ui.spinner();
+                }
+                let alternatives = self.current_alternatives.iter().filter(|content| !content.is_empty()).count();
+                if alternatives > 0 {
+                    ui.label(egui::RichText::new(format!("+{} more", alternatives)).small().weak())
+                        .on_hover_text("Further replies to the same request, kept as variants of this one");
+                }
+            });
+            let cursor = (streaming && !waiting).then(|| self.streaming_cursor_color(ui));
+            self.render_message_content_with_cursor(ui, &self.current_response, cursor);
+        }
+    }
+
+    /// The cursor stays solid while text is arriving and blinks while waiting for more.
+    fn streaming_cursor_color(&self, ui: &egui::Ui) -> egui::Color32 {
+        const BLINK_SECONDS: f64 = 0.5;
+        let receiving = self.response_updated
+            .is_some_and(|updated| updated.elapsed().as_secs_f64() < BLINK_SECONDS);
+        // Streaming repaints every frame anyway, so the phase can come from the clock
+        let visible = receiving || (ui.input(|i| i.time) / BLINK_SECONDS) as i64 % 2 == 0;
+        if visible {
+            ui.visuals().text_color()
+        } else {
+            egui::Color32::TRANSPARENT
+        }
+    }
+
+    fn render_message_content(&self, ui: &mut egui::Ui, content: &str) {
+        self.render_message_content_with_cursor(ui, content, None);
+    }
+
+    /// `cursor` is drawn after the answer text while the reply is streaming.
+    fn render_message_content_with_cursor(&self, ui: &mut egui::Ui, content: &str, cursor: Option<egui::Color32>) {
+        let segments = split_reasoning(content);
+        let last_answer = segments.iter().rposition(|segment| matches!(segment, MessageSegment::Answer(text) if !text.trim().is_empty()));
+        // Hidden reasoning already shows it is busy, so the cursor only follows an answer or open reasoning
+        let thinking = matches!(segments.last(), Some(MessageSegment::Reasoning { finished: false, .. }));
+        for (i, segment) in segments.into_iter().enumerate() {
+            match segment {
+                MessageSegment::Answer(text) => {
+                    if !text.trim().is_empty() {
+                        match cursor.filter(|_| !thinking && Some(i) == last_answer) {
+                            Some(color) => render_markdown_with_cursor(ui, text, color),
+                            None => render_markdown(ui, text),
+                        }
+                    }
+                }
+                MessageSegment::Reasoning { text, finished } => {
+                    if !self.show_reasoning {
+                        // Still show that the model is busy while hidden reasoning streams in
+                        if !finished {
+                            ui.label(egui::RichText::new("🤔 Thinking...").weak());
+                        }
+                    } else if !text.trim().is_empty() {
+                        egui::Frame::none()
+                            .fill(egui::Color32::from_rgb(47, 45, 56))
+                            .inner_margin(egui::style::Margin::same(8.0))
+                            .show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label(egui::RichText::new(if finished { "🤔 Reasoning" } else { "🤔 Thinking..." })
+                                        .color(egui::Color32::from_rgb(167, 139, 250))
+                                        .strong());
+                                });
+                                ui.add(egui::Label::new(
+                                    egui::RichText::new(text.trim())
+                                        .color(egui::Color32::LIGHT_GRAY)
+                                ).wrap(true).selectable(true));
+                            });
+                    }
+                }
+            }
+        }
+
+        // Nothing to follow yet, e.g. before the first token or right after the reasoning
+        if let Some(color) = cursor.filter(|_| !thinking && last_answer.is_none()) {
+            render_markdown_with_cursor(ui, "", color);
+        }
+    }
+
+    pub fn show_settings_window(&mut self, ctx: &egui::Context) {
         let mut show_settings = self.show_settings;
         egui::Window::new("Settings")
             .open(&mut show_settings)
@@ -322,6 +1266,305 @@ impl ChatApp {
         self.show_settings = show_settings;
     }
 
+    /// Ctrl+Shift+P opens a filtered list of the commands that apply right now, run with Enter or a click.
+    fn show_command_palette(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::P)) {
+            self.command_palette_open = !self.command_palette_open;
+            self.command_palette_query.clear();
+            self.command_palette_selected = 0;
+        }
+        if !self.command_palette_open {
+            return;
+        }
+
+        let commands: Vec<Command> = Command::ALL.into_iter()
+            .filter(|command| command.is_available(self) && matches_filter(&self.command_palette_query, command.label()))
+            .collect();
+        let (up, down, enter, escape) = ctx.input_mut(|i| (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+        ));
+        let last = commands.len().saturating_sub(1);
+        if up {
+            self.command_palette_selected = self.command_palette_selected.saturating_sub(1);
+        }
+        if down {
+            self.command_palette_selected += 1;
+        }
+        self.command_palette_selected = self.command_palette_selected.min(last);
+
+        let mut run = if enter { commands.get(self.command_palette_selected).copied() } else { None };
+        let window = egui::Window::new("Command palette")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 48.0])
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                let filter = ui.add(egui::TextEdit::singleline(&mut self.command_palette_query)
+                    .hint_text("Type a command...")
+                    .desired_width(f32::INFINITY));
+                filter.request_focus();
+                if filter.changed() {
+                    self.command_palette_selected = 0;
+                }
+                ui.separator();
+
+                if commands.is_empty() {
+                    ui.label(egui::RichText::new("No matching commands").weak());
+                }
+                for (index, command) in commands.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(index == self.command_palette_selected, command.label()).clicked() {
+                            run = Some(*command);
+                        }
+                        if let Some(shortcut) = command.shortcut() {
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                ui.label(egui::RichText::new(shortcut).small().weak());
+                            });
+                        }
+                    });
+                }
+            });
+
+        let clicked_outside = window.is_some_and(|window| window.response.clicked_elsewhere());
+        if run.is_some() || escape || clicked_outside {
+            self.command_palette_open = false;
+        }
+        if let Some(command) = run {
+            command.run(self, ctx);
+        }
+    }
+
+    /// Every reply of one turn next to each other, with a button to show one of them in the chat.
+    fn show_compare_replies_window(&mut self, ctx: &egui::Context) {
+        let Some(i) = self.compare_replies else {
+            return;
+        };
+        let Some(entry) = self.chat_history.get(i).filter(|entry| entry.reply_count() > 1) else {
+            self.compare_replies = None;
+            return;
+        };
+
+        let replies = entry.replies();
+        let shown = entry.reply_index;
+        let can_select = self.pending_response.is_none();
+        let mut open = true;
+        let mut select = None;
+        egui::Window::new("Compare replies")
+            .open(&mut open)
+            .default_width(320.0 * replies.len().min(3) as f32)
+            .default_height(420.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    ui.columns(replies.len(), |columns| {
+                        for (index, (column, reply)) in columns.iter_mut().zip(&replies).enumerate() {
+                            column.horizontal(|ui| {
+                                ui.label(egui::RichText::new(format!("Reply {}", index + 1)).strong());
+                                if index == shown {
+                                    ui.label(egui::RichText::new("shown").small().weak());
+                                } else if ui.add_enabled(can_select, egui::Button::new("Use this").small()).clicked() {
+                                    select = Some(index);
+                                }
+                            });
+                            if let Some(stats) = &reply.stats {
+                                column.label(egui::RichText::new(stats.summary()).small().weak());
+                            }
+                            column.separator();
+                            egui::ScrollArea::vertical()
+                                .id_source(("compare_reply", index))
+                                .show(column, |ui| self.render_message_content(ui, &reply.content));
+                        }
+                    });
+                });
+            });
+
+        if let Some(index) = select {
+            self.select_reply(i, index);
+        }
+        if !open {
+            self.compare_replies = None;
+        }
+    }
+
+    /// The last reply's response body as it arrived, to see why an endpoint's output doesn't parse.
+    fn show_raw_stream_window(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Raw stream")
+            .open(&mut open)
+            .default_width(520.0)
+            .default_height(320.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|o| o.copied_text = self.raw_stream_log.clone());
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.raw_stream_log.clear();
+                    }
+                    ui.label(egui::RichText::new(format!("{} bytes", self.raw_stream_log.len())).small().weak());
+                });
+                ui.separator();
+
+                egui::ScrollArea::both()
+                    .auto_shrink([false; 2])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        if self.raw_stream_log.is_empty() {
+                            ui.label(egui::RichText::new("Send a message to see the raw response").weak());
+                        } else {
+                            ui.add(egui::Label::new(egui::RichText::new(&self.raw_stream_log).monospace()).wrap(false).selectable(true));
+                        }
+                    });
+            });
+        self.raw_stream = open;
+    }
+
+    /// Ollama's description of a model, opened from the model info button in the settings.
+    pub fn show_model_card_window(&mut self, ctx: &egui::Context) {
+        let Some((model, card)) = &self.model_card else {
+            return;
+        };
+
+        let mut open = true;
+        egui::Window::new(format!("Model info: {}", model))
+            .id(egui::Id::new("model_card_window"))
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                let Some(card) = card else {
+                    ui.spinner();
+                    return;
+                };
+
+                if !card.details.is_empty() {
+                    egui::Grid::new("model_card_details").num_columns(2).show(ui, |ui| {
+                        for (key, value) in &card.details {
+                            // Lists such as "families" are too long to be useful here
+                            let value = match value {
+                                serde_json::Value::String(text) => text.clone(),
+                                serde_json::Value::Array(_) | serde_json::Value::Object(_) | serde_json::Value::Null => continue,
+                                other => other.to_string(),
+                            };
+                            if value.is_empty() {
+                                continue;
+                            }
+                            ui.label(key.replace('_', " "));
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                    ui.add_space(4.0);
+                }
+
+                for (title, text) in [("Parameters", &card.parameters), ("Template", &card.template), ("Modelfile", &card.modelfile)] {
+                    egui::CollapsingHeader::new(title)
+                        .default_open(title == "Parameters")
+                        .show(ui, |ui| {
+                            if text.trim().is_empty() {
+                                ui.label(egui::RichText::new("None").weak());
+                                return;
+                            }
+                            if ui.small_button("📋").on_hover_text(format!("Copy {}", title.to_lowercase())).clicked() {
+                                ui.output_mut(|o| o.copied_text = text.clone());
+                            }
+                            egui::ScrollArea::vertical()
+                                .id_source(title)
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    ui.add(egui::Label::new(egui::RichText::new(text.trim()).monospace()).selectable(true));
+                                });
+                        });
+                }
+            });
+
+        if !open {
+            self.model_card = None;
+            self.pending_model_card = None;
+        }
+    }
+
+    /// Download a model onto the Ollama server, with the progress of the running pull.
+    fn render_model_pull(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.vertical(|ui| {
+            let pulling = self.pending_pull.is_some();
+            ui.horizontal(|ui| {
+                let response = ui.add_enabled(!pulling, egui::TextEdit::singleline(&mut self.pull_model_input)
+                    .hint_text("llama3.2:3b")
+                    .desired_width(160.0));
+                let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if pulling {
+                    if ui.button("Cancel").clicked() {
+                        self.cancel_pull();
+                    }
+                } else {
+                    let clicked = ui.add_enabled(!self.pull_model_input.trim().is_empty(), egui::Button::new("Pull"))
+                        .on_hover_text("Download the model from the Ollama library")
+                        .clicked();
+                    if clicked || submitted {
+                        self.pull_model(ctx);
+                    }
+                }
+            });
+
+            if pulling {
+                let progress = self.pull_progress.clone().unwrap_or_default();
+                let status = if progress.status.is_empty() { "Starting..." } else { progress.status.as_str() };
+                match progress.fraction() {
+                    Some(fraction) => {
+                        let text = format!("{} ({:.0}%)", status, fraction * 100.0);
+                        ui.add(egui::ProgressBar::new(fraction).text(text).desired_width(240.0));
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(status);
+                        });
+                    }
+                }
+            } else if let Some(result) = &self.pull_result {
+                match result {
+                    Ok(message) => ui.label(egui::RichText::new(message).weak()),
+                    Err(error) => ui.colored_label(egui::Color32::RED, error),
+                };
+            }
+        });
+    }
+
+    /// Editable name/value rows for the custom request headers, returning whether any changed.
+    fn render_custom_headers(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let mut remove = None;
+
+        ui.vertical(|ui| {
+            for (i, (name, value)) in self.custom_headers.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    changed |= ui.add(egui::TextEdit::singleline(name).desired_width(120.0).hint_text("X-Api-Key")).changed();
+                    changed |= ui.add(egui::TextEdit::singleline(value).desired_width(160.0).hint_text("value")).changed();
+                    if ui.small_button("✖").on_hover_text("Remove header").clicked() {
+                        remove = Some(i);
+                    }
+                });
+                if !name.trim().is_empty() {
+                    if let Err(e) = llmclient::validate_header(name, value) {
+                        ui.colored_label(egui::Color32::RED, format!("{} (not sent)", e));
+                    }
+                }
+            }
+
+            if ui.small_button("➕ Add header").clicked() {
+                self.custom_headers.push((String::new(), String::new()));
+            }
+        });
+
+        if let Some(i) = remove {
+            self.custom_headers.remove(i);
+            changed = true;
+        }
+        changed
+    }
+
     fn render_api_settings_tab(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
         ui.heading("API Configuration");
         ui.add_space(8.0);
@@ -330,11 +1573,10 @@ impl ChatApp {
         ui.horizontal(|ui| {
             ui.label("Endpoint Type:");
             let mut new_endpoint = self.endpoint_type;  
-            if ui.radio_value(&mut new_endpoint, crate::endpoint_type::EndpointType::LMStudio, "OpenAI-Compatible").clicked() {
-                self.update_endpoint_type(new_endpoint);
-            }
-            if ui.radio_value(&mut new_endpoint, crate::endpoint_type::EndpointType::Ollama, "Ollama").clicked() {
-                self.update_endpoint_type(new_endpoint);
+            for endpoint_type in crate::endpoint_type::EndpointType::ALL {
+                if ui.radio_value(&mut new_endpoint, endpoint_type, endpoint_type.label()).clicked() {
+                    self.update_endpoint_type(new_endpoint);
+                }
             }
         });
         
@@ -344,13 +1586,19 @@ impl ChatApp {
         ui.horizontal(|ui| {
             ui.label("Model:");
             let mut new_model = self.selected_model.clone();
-            egui::ComboBox::from_id_source("model_select")
+            let open = egui::ComboBox::from_id_source("model_select")
                 .selected_text(&new_model)
                 .show_ui(ui, |ui| {
-                    for model in &self.available_models {
+                    render_combo_filter(ui, &mut self.model_filter);
+                    for model in self.available_models.iter().filter(|model| matches_filter(&self.model_filter, model)) {
                         ui.selectable_value(&mut new_model, model.clone(), model);
                     }
-                });
+                })
+                .inner
+                .is_some();
+            if !open {
+                self.model_filter.clear();
+            }
             if new_model != self.selected_model {
                 self.selected_model = new_model;
             }
@@ -358,9 +1606,42 @@ impl ChatApp {
             if ui.button("⟳").on_hover_text("Refresh model list").clicked() {
                 self.refresh_models(ctx);
             }
+
+            let has_model = self.available_models.contains(&self.selected_model);
+            if self.endpoint_type == crate::endpoint_type::EndpointType::Ollama && has_model {
+                if ui.button("ℹ").on_hover_text("Show the model's parameters, template and Modelfile").clicked() {
+                    self.show_model_card(ctx);
+                }
+                let deleting = self.pending_delete.is_some();
+                if ui.add_enabled(!deleting, egui::Button::new("🗑"))
+                    .on_hover_text("Delete this model from the Ollama server")
+                    .clicked()
+                {
+                    self.confirm_delete_model = Some(self.selected_model.clone());
+                }
+                if deleting {
+                    ui.spinner();
+                }
+            }
         });
+
+        if let Some(model) = self.confirm_delete_model.clone() {
+            ui.horizontal(|ui| {
+                ui.colored_label(ui.visuals().warn_fg_color, format!("Delete {} from the server?", model));
+                if ui.button("Delete").clicked() {
+                    self.delete_model(ctx);
+                }
+                if ui.button("Cancel").clicked() {
+                    self.confirm_delete_model = None;
+                }
+            });
+        }
+
+        if let Some(limit) = self.context_length() {
+            ui.label(egui::RichText::new(format!("Context window: {} tokens", limit)).weak());
+        }
         
-        if self.models_loading {
+        if self.pending_models.is_some() || self.model_refresh_due.is_some() {
             ui.spinner();
         }
         
@@ -373,110 +1654,490 @@ impl ChatApp {
             .show(ui, |ui| {
                 // Protocol dropdown
                 ui.label("Protocol:");
+                let mut url_changed = false;
                 egui::ComboBox::from_id_source("protocol_select")
                     .selected_text(&self.protocol)
                     .show_ui(ui, |ui| {
-                        ui.selectable_value(&mut self.protocol, "http".to_string(), "http");
-                        ui.selectable_value(&mut self.protocol, "https".to_string(), "https");
+                        url_changed |= ui.selectable_value(&mut self.protocol, "http".to_string(), "http").changed();
+                        url_changed |= ui.selectable_value(&mut self.protocol, "https".to_string(), "https").changed();
                     });
                 ui.end_row();
 
                 // Server
                 ui.label("Server:");
-                ui.text_edit_singleline(&mut self.server);
+                url_changed |= ui.text_edit_singleline(&mut self.server).changed();
                 ui.end_row();
 
                 // Port
                 ui.label("Port:");
-                ui.add(egui::TextEdit::singleline(&mut self.port)
+                url_changed |= ui.add(egui::TextEdit::singleline(&mut self.port)
                     .desired_width(60.0))
-                    .on_hover_text("Port number");
+                    .on_hover_text("Port number")
+                    .changed();
                 ui.end_row();
 
                 // Endpoint
                 ui.label("Endpoint:");
-                ui.text_edit_singleline(&mut self.endpoint);
+                url_changed |= ui.text_edit_singleline(&mut self.endpoint)
+                    .on_hover_text("The API path, optionally behind a reverse proxy prefix, e.g. proxy/ollama/api/chat. \
+                        Requests go to the prefix plus each API's own path.")
+                    .changed();
                 ui.end_row();
+
+                if url_changed {
+                    self.update_client_url();
+                }
+
+                // API key
+                ui.label("API Key:");
+                let key_changed = ui.add(egui::TextEdit::singleline(&mut self.api_key)
+                    .password(true)
+                    .hint_text("optional"))
+                    .on_hover_text("Sent as a Bearer token with every request")
+                    .changed();
+                if key_changed {
+                    self.update_client_url();
+                }
+                ui.end_row();
+
+                ui.label("Headers:").on_hover_text("Extra headers sent with every request, e.g. for gateways and proxies");
+                if self.render_custom_headers(ui) {
+                    self.update_client_url();
+                }
+                ui.end_row();
+
+                ui.label("TLS:");
+                ui.vertical(|ui| {
+                    if ui.checkbox(&mut self.accept_invalid_certs, "Accept invalid certificates")
+                        .on_hover_text("For self-hosted HTTPS servers with self-signed certificates")
+                        .changed()
+                    {
+                        self.update_client_url();
+                    }
+                    if self.accept_invalid_certs {
+                        ui.colored_label(ui.visuals().warn_fg_color,
+                            "⚠ Certificates are not checked: anyone on the network could intercept your requests and API key.");
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Streaming:");
+                if ui.checkbox(&mut self.disable_streaming, "Disable streaming")
+                    .on_hover_text("Wait for the whole reply in one response, for servers and proxies that don't stream well")
+                    .changed()
+                {
+                    self.update_client_url();
+                }
+                ui.end_row();
+
+                if self.endpoint_type == crate::endpoint_type::EndpointType::Ollama {
+                    ui.label("Mode:");
+                    ui.horizontal(|ui| {
+                        let mut mode_changed = ui.radio_value(&mut self.ollama_generate, false, "Chat").changed();
+                        mode_changed |= ui.radio_value(&mut self.ollama_generate, true, "Generate (raw)")
+                            .on_hover_text("Send a plain User/Assistant transcript to /api/generate without the model's chat template")
+                            .changed();
+                        if mode_changed {
+                            self.update_client_url();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Pull model:");
+                    self.render_model_pull(ui, ctx);
+                    ui.end_row();
+                }
             });
         
         ui.add_space(8.0);
         
         // Test Connection button
-        if ui.button("Test Connection").clicked() {
-            println!("Testing connection to: {}://{}:{}/{}", self.protocol, self.server, self.port, self.endpoint);
-            match reqwest::Url::parse(&format!("{}://{}:{}/{}", self.protocol, self.server, self.port, self.endpoint)) {
-                Ok(_) => {
-                    self.selected_model = "local-model".to_string();
-                    self.available_models.clear();
-                    self.update_client_url();
-                    self.refresh_models(ctx);
-                }
-                Err(e) => {
-                    self.error_message = Some(format!("Invalid URL: {}", e));
+        ui.horizontal(|ui| {
+            if ui.button("Test Connection").clicked() {
+                log::info!("Testing connection to: {}://{}:{}/{}", self.protocol, self.server, self.port, self.endpoint);
+                match reqwest::Url::parse(&format!("{}://{}:{}/{}", self.protocol, self.server, self.port, self.endpoint)) {
+                    Ok(_) => {
+                        self.selected_model = "local-model".to_string();
+                        self.available_models.clear();
+                        self.update_client_url();
+                        self.refresh_models(ctx);
+                    }
+                    Err(e) => {
+                        self.error_message = Some(format!("Invalid URL: {}", e));
+                    }
                 }
             }
-        }
+
+            let detecting = self.pending_detection.is_some();
+            if ui.add_enabled(!detecting, egui::Button::new("Detect"))
+                .on_hover_text("Find out which kind of server this is and switch the endpoint type to match")
+                .clicked()
+            {
+                self.detect_endpoint_type(ctx);
+            }
+            if detecting {
+                ui.spinner();
+            }
+
+            if self.developer_tools {
+                render_copy_curl_button(ui, self.last_chat_curl.as_deref(), "chat");
+                ui.checkbox(&mut self.raw_stream, "Show raw stream")
+                    .on_hover_text("Show the response body of each reply as it arrives, before parsing");
+            }
+        });
         
+        if let Some(message) = &self.detection_message {
+            ui.label(message);
+        }
+
         ui.add_space(4.0);
         ui.label("Current: ").on_hover_text("The URL currently in use");
-        ui.label(&format!("{}://{}:{}/{}", self.protocol, self.server, self.port, self.endpoint));
+        ui.label(format!("{}://{}:{}/{}", self.protocol, self.server, self.port, self.endpoint));
         
         // Display error message if present
         if let Some(error) = &self.error_message {
-            ui.colored_label(egui::Color32::RED, error);
+            if render_error(ui, error, Some("Fetch the models again")) {
+                self.refresh_models(ctx);
+            }
+        }
+        
+        ui.separator();
+        
+        if ui.button("Reset to Default").clicked() {
+            self.reset_to_defaults();
+            self.refresh_models(ctx);
+        }
+    }
+    
+    /// One-click sampling presets, plus saving the current parameters as a custom one.
+    fn render_sampling_presets(&mut self, ui: &mut egui::Ui) {
+        let options = self.chat_options();
+        let mut apply = None;
+        let mut remove = None;
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Presets:");
+            for preset in SamplingPreset::builtin().iter().chain(&self.custom_presets) {
+                let active = preset.matches(&options);
+                let hover = format!(
+                    "Temperature {:.2}, top P {:.2}, presence penalty {:.2}, frequency penalty {:.2}",
+                    preset.temperature, preset.top_p, preset.presence_penalty, preset.frequency_penalty,
+                );
+                if ui.selectable_label(active, &preset.name).on_hover_text(hover).clicked() {
+                    apply = Some(preset.clone());
+                }
+            }
+        });
+
+        if !self.custom_presets.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Custom:");
+                for (i, preset) in self.custom_presets.iter().enumerate() {
+                    if ui.small_button(format!("✖ {}", preset.name)).on_hover_text("Delete this preset").clicked() {
+                        remove = Some(i);
+                    }
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            let response = ui.add(egui::TextEdit::singleline(&mut self.preset_name_input)
+                .desired_width(150.0)
+                .hint_text("Preset name"));
+            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let can_save = !self.preset_name_input.trim().is_empty();
+            let save = ui.add_enabled(can_save, egui::Button::new("Save as preset"))
+                .on_hover_text("Save the temperature, top P and penalties below; a custom preset with the same name is replaced");
+            if save.clicked() || (enter && can_save) {
+                self.save_custom_preset();
+            }
+        });
+
+        if let Some(preset) = apply {
+            self.apply_preset(&preset);
         }
-        
-        ui.separator();
-        
-        if ui.button("Reset to Default").clicked() {
-            self.reset_to_defaults();
-            self.refresh_models(ctx);
+        if let Some(i) = remove {
+            self.custom_presets.remove(i);
         }
     }
-    
+
     fn render_advanced_settings_tab(&mut self, ui: &mut egui::Ui) {
         ui.heading("Advanced Settings");
         ui.add_space(8.0);
         
-        // This is a placeholder - add your advanced settings here
-        ui.label("These settings will be implemented in a future update.");
-        
-        // Example settings that could be added here:
+        ui.group(|ui| {
+            ui.label("Appearance");
+            ui.add_space(4.0);
+
+            ui.horizontal(|ui| {
+                ui.label("Theme:");
+                for theme in [ThemePreference::Dark, ThemePreference::Light, ThemePreference::System] {
+                    ui.radio_value(&mut self.theme, theme, theme.label());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("UI Scale:");
+                if ui.add(egui::Slider::new(&mut self.ui_scale, 0.5..=2.0).step_by(0.05)).changed() {
+                    ui.ctx().set_zoom_factor(self.ui_scale);
+                }
+            });
+
+            egui::Grid::new("speaker_names_grid")
+                .num_columns(3)
+                .show(ui, |ui| {
+                    ui.label("Your name:");
+                    ui.add(egui::TextEdit::singleline(&mut self.user_name).hint_text("You").desired_width(160.0));
+                    ui.add(egui::TextEdit::singleline(&mut self.user_avatar).hint_text("avatar, e.g. 🙂").desired_width(100.0));
+                    ui.end_row();
+
+                    ui.label("Assistant name:");
+                    ui.add(egui::TextEdit::singleline(&mut self.assistant_name).hint_text(self.selected_model.as_str()).desired_width(160.0))
+                        .on_hover_text("Shown on replies. Empty = the selected model");
+                    ui.add(egui::TextEdit::singleline(&mut self.assistant_avatar).hint_text("avatar, e.g. 🤖").desired_width(100.0));
+                    ui.end_row();
+                });
+
+            ui.checkbox(&mut self.show_timestamps, "Show message times")
+                .on_hover_text("Show when each message was sent next to its name. The full date is shown on hover either way.");
+            ui.checkbox(&mut self.show_reasoning, "Show model reasoning")
+                .on_hover_text("Show the <think> sections reasoning models write before their answer");
+            ui.checkbox(&mut self.developer_tools, "Developer tools")
+                .on_hover_text("Offer to copy the last chat and SD requests as curl commands and to show the raw chat stream, next to the connection tests");
+        });
+
+        ui.add_space(8.0);
+
         ui.group(|ui| {
             ui.label("Model Parameters");
             ui.add_space(4.0);
+
+            self.render_sampling_presets(ui);
+            ui.add_space(4.0);
             
-            let mut placeholder_temp = 0.7;
             ui.horizontal(|ui| {
                 ui.label("Temperature:");
-                ui.add(egui::Slider::new(&mut placeholder_temp, 0.0..=2.0).text(""));
+                ui.add(egui::Slider::new(&mut self.temperature, 0.0..=2.0).text(""));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Top P:");
+                ui.add(egui::Slider::new(&mut self.top_p, 0.0..=1.0).step_by(0.01).text(""))
+                    .on_hover_text("Only sample from the most likely tokens making up this much probability. 1 = server default");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Top K:");
+                ui.add(egui::Slider::new(&mut self.top_k, 0..=200).text(""))
+                    .on_hover_text("Only sample from this many of the most likely tokens. Not supported by OpenAI. 0 = server default");
             });
             
-            let mut placeholder_tokens = 2048;
             ui.horizontal(|ui| {
                 ui.label("Max Tokens:");
-                ui.add(egui::Slider::new(&mut placeholder_tokens, 256..=4096).text(""));
+                ui.add(egui::Slider::new(&mut self.max_tokens, 0..=8192).text(""))
+                    .on_hover_text("0 = unlimited");
             });
             
-            let mut placeholder_presence = 0.0;
             ui.horizontal(|ui| {
                 ui.label("Presence Penalty:");
-                ui.add(egui::Slider::new(&mut placeholder_presence, -2.0..=2.0).text(""));
+                ui.add(egui::Slider::new(&mut self.presence_penalty, -2.0..=2.0).step_by(0.05).text(""))
+                    .on_hover_text("Positive values push the model toward new topics. 0 = server default");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Frequency Penalty:");
+                ui.add(egui::Slider::new(&mut self.frequency_penalty, -2.0..=2.0).step_by(0.05).text(""))
+                    .on_hover_text("Positive values discourage repeating the same words. 0 = server default");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Max History Messages:");
+                ui.add(egui::Slider::new(&mut self.max_history_messages, 0..=100).text(""))
+                    .on_hover_text("Earlier messages sent with each prompt; older ones are left out. 0 = unlimited");
             });
+
+            if self.endpoint_type.supports_completions() {
+                ui.horizontal(|ui| {
+                    ui.label("Completions:");
+                    ui.add(egui::DragValue::new(&mut self.completions).clamp_range(1..=8))
+                        .on_hover_text("Replies generated for each prompt, shown as variants to flip through. \
+                            Each one costs its own tokens, and some servers ignore this.");
+                });
+            }
+
+            ui.checkbox(&mut self.json_mode, "JSON mode")
+                .on_hover_text("Make the server only produce valid JSON. The prompt still has to ask for JSON, \
+                    otherwise some models produce whitespace until the token limit. Not supported by Anthropic.");
+
+            ui.add_space(4.0);
+            ui.label("Stop Sequences:")
+                .on_hover_text("Generation stops when the model produces one of these. Use \\n for a newline.");
+
+            let mut remove = None;
+            for (i, sequence) in self.stop_sequences.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(sequence).desired_width(200.0).hint_text("\\nUser:"));
+                    if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                self.stop_sequences.remove(i);
+            }
+            if ui.button("Add Stop Sequence").clicked() {
+                self.stop_sequences.push(String::new());
+            }
         });
         
+        ui.add_space(8.0);
+
+        ui.group(|ui| {
+            ui.label("Connection");
+            ui.add_space(4.0);
+
+            let mut timeouts_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Connect timeout:");
+                timeouts_changed |= ui.add(egui::DragValue::new(&mut self.connect_timeout)
+                    .clamp_range(0..=600)
+                    .suffix(" s"))
+                    .on_hover_text("How long to wait for the server to accept a connection (0 = no timeout)")
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Request timeout:");
+                timeouts_changed |= ui.add(egui::DragValue::new(&mut self.request_timeout)
+                    .clamp_range(0..=3600)
+                    .suffix(" s"))
+                    .on_hover_text("Maximum duration of a request, including the whole streamed reply (0 = no timeout)")
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Retry attempts:");
+                timeouts_changed |= ui.add(egui::DragValue::new(&mut self.retry_attempts)
+                    .clamp_range(1..=10))
+                    .on_hover_text("How many times to try reaching the server before giving up, waiting longer after each failure")
+                    .changed();
+            });
+            if timeouts_changed {
+                self.update_client_url();
+            }
+        });
+
+        ui.add_space(8.0);
+
+        ui.group(|ui| {
+            ui.label("Context Length");
+            ui.add_space(4.0);
+
+            ui.horizontal(|ui| {
+                ui.label(format!("{}:", self.selected_model));
+
+                let mut length = self.model_context_length().unwrap_or(0);
+                let response = ui.add(egui::DragValue::new(&mut length)
+                    .speed(256)
+                    .clamp_range(0..=2_000_000)
+                    .suffix(" tokens"))
+                    .on_hover_text("Overrides the context length reported by the server (0 = unknown)");
+
+                if response.changed() {
+                    if length == 0 {
+                        self.model_context_lengths.remove(&self.selected_model);
+                    } else {
+                        self.model_context_lengths.insert(self.selected_model.clone(), length);
+                    }
+                }
+            });
+
+            if self.endpoint_type == crate::endpoint_type::EndpointType::Ollama {
+                ui.horizontal(|ui| {
+                    ui.label("Ollama num_ctx:");
+                    ui.add(egui::DragValue::new(&mut self.num_ctx)
+                        .speed(256)
+                        .clamp_range(0..=1_048_576)
+                        .suffix(" tokens"))
+                        .on_hover_text("The context window Ollama loads the model with. Longer prompts are cut off \
+                            without warning. 0 = server default");
+                });
+                if self.num_ctx > 0 {
+                    ui.colored_label(ui.visuals().warn_fg_color,
+                        "⚠ Larger values use more memory and may not fit on the GPU.");
+                }
+            }
+        });
+
         ui.add_space(8.0);
         
+        ui.group(|ui| {
+            ui.label("Logging");
+            ui.add_space(4.0);
+
+            ui.horizontal(|ui| {
+                ui.label("Verbosity:");
+                let previous = self.log_level;
+                egui::ComboBox::from_id_source("log_level_select")
+                    .selected_text(self.log_level.label())
+                    .show_ui(ui, |ui| {
+                        for level in LogLevel::ALL {
+                            ui.selectable_value(&mut self.log_level, level, level.label());
+                        }
+                    });
+                if self.log_level != previous {
+                    logging::set_level(self.log_level);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Log file:");
+                let response = ui.add(egui::TextEdit::singleline(&mut self.log_file)
+                    .hint_text("none, stderr only")
+                    .desired_width(220.0));
+                if response.lost_focus() {
+                    self.apply_log_file();
+                }
+                if ui.button("Browse…").clicked() {
+                    if let Some(path) = rfd::FileDialog::new().set_file_name("llm-client.log").save_file() {
+                        self.log_file = path.display().to_string();
+                        self.apply_log_file();
+                    }
+                }
+            });
+        });
+
+        ui.add_space(8.0);
+
         ui.group(|ui| {
             ui.label("Chat History");
             ui.add_space(4.0);
+
+            ui.checkbox(&mut self.restore_chat_history, "Restore conversations on launch");
+            ui.checkbox(&mut self.interrupt_on_send, "Interrupt on new message")
+                .on_hover_text("Sending while a reply is streaming stops it, keeps what arrived so far, and answers the new message");
+            ui.checkbox(&mut self.fence_dropped_text, "Wrap dropped files in code fences");
             
-            if ui.button("Export Chat History").clicked() {
-                // Placeholder for export functionality
-            }
+            ui.horizontal(|ui| {
+                if ui.button("Export Chat History").clicked() {
+                    self.export_chat_history();
+                }
+
+                egui::ComboBox::from_id_source("export_format_select")
+                    .selected_text(self.export_format.label())
+                    .show_ui(ui, |ui| {
+                        for format in [ExportFormat::Markdown, ExportFormat::Json] {
+                            ui.selectable_value(&mut self.export_format, format, format.label());
+                        }
+                    });
+
+                ui.checkbox(&mut self.export_include_errors, "Include errors");
+            });
             
             if ui.button("Import Chat History").clicked() {
-                // Placeholder for import functionality
+                self.import_chat_history(ui.ctx());
+            }
+
+            if let Some(error) = &self.error_message {
+                render_error(ui, error, None);
             }
         });
     }
@@ -493,17 +2154,35 @@ impl ChatApp {
             
             ui.horizontal(|ui| {
                 ui.label("API URL:");
-                let mut api_url = self.sd_client.base_url.clone();
-                let url_changed = ui.text_edit_singleline(&mut api_url).changed();
-                if url_changed {
-                    self.sd_client = crate::sdclient::SDClient::new(api_url);
+                // Applied once editing finishes, so a half-typed URL never reaches the client
+                let response = ui.text_edit_singleline(&mut self.sd_url_input);
+                if response.lost_focus() {
+                    self.apply_sd_url();
                 }
                 
                 // Add refresh button next to URL field
                 if ui.button("⟳").on_hover_text("Load models and options from API").clicked() {
-                    self.load_sd_options(ctx);
+                    self.apply_sd_url();
+                    self.reload_sd_options(ctx);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Test SD Connection").clicked() {
+                    self.test_sd_connection(ctx);
+                }
+
+                if self.developer_tools {
+                    render_copy_curl_button(ui, self.sd_last_curl.as_deref(), "generation");
                 }
             });
+            if let Some(error) = &self.sd_error_message {
+                if render_error(ui, error, Some("Test the connection again")) {
+                    self.test_sd_connection(ctx);
+                }
+            } else if let Some(status) = &self.sd_connection_status {
+                ui.colored_label(egui::Color32::GREEN, status);
+            }
             
             ui.add_space(4.0);
             ui.label("⚠️ Note: Make sure Automatic1111 WebUI is running with the --api flag enabled");
@@ -525,11 +2204,11 @@ impl ChatApp {
                     // Model selection
                     ui.label("Model:");
                     
-                    if self.sd_models_loading {
+                    if self.sd_models_pending.is_some() {
                         ui.spinner();
                         ui.end_row();
                     } else {
-                        egui::ComboBox::from_id_source("sd_model_select")
+                        let open = egui::ComboBox::from_id_source("sd_model_select")
                             .selected_text(
                                 self.sd_models.iter()
                                     .find(|m| m.model_name == self.sd_selected_model)
@@ -537,63 +2216,59 @@ impl ChatApp {
                                     .unwrap_or_else(|| "Select model...".to_string())
                             )
                             .show_ui(ui, |ui| {
-                                for model in &self.sd_models {
+                                render_combo_filter(ui, &mut self.sd_model_filter);
+                                for model in self.sd_models.iter().filter(|m| matches_filter(&self.sd_model_filter, &m.title)) {
                                     ui.selectable_value(
                                         &mut self.sd_selected_model,
                                         model.model_name.clone(),
                                         &model.title
                                     );
                                 }
-                            });
+                            })
+                            .inner
+                            .is_some();
+                        if !open {
+                            self.sd_model_filter.clear();
+                        }
                         ui.end_row();
                     }
-                    
-                    // LoRA selection
-                    ui.label("LoRA:");
-                    
-                    if self.sd_loras_loading {
+
+                    // VAE selection
+                    ui.label("VAE:");
+
+                    if self.sd_vaes_pending.is_some() {
                         ui.spinner();
                         ui.end_row();
                     } else {
-                        let mut display_name = "None".to_string();
-                        
-                        if let Some(lora_name) = &self.sd_selected_lora {
-                            if let Some(lora) = self.sd_loras.iter().find(|l| l.name == *lora_name) {
-                                display_name = lora.alias.clone().unwrap_or_else(|| lora.name.clone());
-                            }
-                        }
-                        
-                        egui::ComboBox::from_id_source("sd_lora_select")
-                            .selected_text(display_name)
+                        egui::ComboBox::from_id_source("sd_vae_select")
+                            .selected_text(&self.sd_selected_vae)
                             .show_ui(ui, |ui| {
-                                if ui.selectable_label(self.sd_selected_lora.is_none(), "None").clicked() {
-                                    self.sd_selected_lora = None;
-                                }
-                                
-                                for lora in &self.sd_loras {
-                                    let name = lora.alias.clone().unwrap_or_else(|| lora.name.clone());
-                                    if ui.selectable_label(
-                                        self.sd_selected_lora.as_ref() == Some(&lora.name),
-                                        name
-                                    ).clicked() {
-                                        self.sd_selected_lora = Some(lora.name.clone());
-                                    }
+                                for vae in &self.sd_vaes {
+                                    ui.selectable_value(&mut self.sd_selected_vae, vae.clone(), vae);
                                 }
-                            });
+                            })
+                            .response
+                            .on_hover_text("Automatic uses the VAE matching the checkpoint, None the one baked into it");
                         ui.end_row();
                     }
                     
-                    // LoRA weight slider (only show if LoRA is selected)
-                    if self.sd_selected_lora.is_some() {
-                        ui.label("LoRA weight:");
-                        ui.add(egui::Slider::new(&mut self.sd_lora_weight, 0.1..=1.0).text(""));
+                    // LoRA selection, one row per LoRA with its own weight
+                    ui.label("LoRAs:");
+                    
+                    if self.sd_loras_pending.is_some() {
+                        ui.spinner();
+                        ui.end_row();
+                    } else {
+                        ui.vertical(|ui| {
+                            self.render_sd_lora_rows(ui);
+                        });
                         ui.end_row();
                     }
                     
                     // Sampler selection
                     ui.label("Sampler:");
                     
-                    if self.sd_samplers_loading {
+                    if self.sd_samplers_pending.is_some() {
                         ui.spinner();
                         ui.end_row();
                     } else {
@@ -621,7 +2296,7 @@ impl ChatApp {
                     // Schedule Type selection (new)
                     ui.label("Schedule Type:");
                     
-                    if self.sd_schedulers_loading {
+                    if self.sd_schedulers_pending.is_some() {
                         ui.spinner();
                         ui.end_row();
                     } else {
@@ -640,6 +2315,65 @@ impl ChatApp {
                         ui.end_row();
                     }
                     
+                    // Seed: random or fixed
+                    ui.label("Seed:");
+                    ui.horizontal(|ui| {
+                        let mut random = self.sd_seed.is_none();
+                        if ui.checkbox(&mut random, "Random").changed() {
+                            self.sd_seed = if random {
+                                None
+                            } else {
+                                Some(self.sd_last_seed().unwrap_or(0))
+                            };
+                        }
+                        if let Some(seed) = &mut self.sd_seed {
+                            ui.add(egui::DragValue::new(seed).clamp_range(0..=u32::MAX as i64));
+                        }
+                    });
+                    ui.end_row();
+
+                    // Batches
+                    ui.label("Batch size:");
+                    ui.add(egui::DragValue::new(&mut self.sd_batch_size).clamp_range(1..=8))
+                        .on_hover_text("Images generated together in one batch (uses more VRAM)");
+                    ui.end_row();
+
+                    ui.label("Batch count:");
+                    ui.add(egui::DragValue::new(&mut self.sd_batch_count).clamp_range(1..=16))
+                        .on_hover_text("Batches run one after another");
+                    ui.end_row();
+
+                    ui.label("Save metadata:");
+                    ui.checkbox(&mut self.sd_embed_metadata, "Embed parameters in PNG")
+                        .on_hover_text("Saved images can be loaded back with A1111's PNG Info tab");
+                    ui.end_row();
+
+                    ui.label("Save folder:");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.sd_save_folder)
+                            .hint_text("ask every time")
+                            .desired_width(180.0));
+                        if ui.button("Browse…").clicked() {
+                            if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                                self.sd_save_folder = folder.display().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("File name:");
+                    ui.add(egui::TextEdit::singleline(&mut self.sd_filename_template)
+                        .hint_text(DEFAULT_FILENAME_TEMPLATE)
+                        .desired_width(180.0))
+                        .on_hover_text(format!("Without the extension. Tokens: {}", FILENAME_TOKENS));
+                    ui.end_row();
+
+                    ui.label("Quick save:");
+                    ui.add_enabled(!self.sd_save_folder.trim().is_empty(), egui::Checkbox::new(&mut self.sd_quick_save, "Save without asking"))
+                        .on_hover_text("Save straight into the save folder, adding a number instead of overwriting")
+                        .on_disabled_hover_text("Set a save folder first");
+                    ui.end_row();
+
                     // Steps slider
                     ui.label("Steps:");
                     ui.add(egui::Slider::new(&mut self.sd_steps, 10..=50).text(""));
@@ -653,12 +2387,260 @@ impl ChatApp {
                     // Width and Height
                     ui.label("Size:");
                     ui.horizontal(|ui| {
-                        ui.add(egui::DragValue::new(&mut self.sd_width).speed(32).clamp_range(256..=1024));
+                        ui.add(egui::DragValue::new(&mut self.sd_width).speed(32).clamp_range(64..=2048));
                         ui.label("×");
-                        ui.add(egui::DragValue::new(&mut self.sd_height).speed(32).clamp_range(256..=1024));
+                        ui.add(egui::DragValue::new(&mut self.sd_height).speed(32).clamp_range(64..=2048));
+
+                        if ui.small_button("⇄").on_hover_text("Swap width and height").clicked() {
+                            std::mem::swap(&mut self.sd_width, &mut self.sd_height);
+                        }
+
+                        ui.menu_button("Presets", |ui| {
+                            for (label, width, height) in SD_SIZE_PRESETS {
+                                if ui.button(*label).clicked() {
+                                    self.sd_width = *width;
+                                    self.sd_height = *height;
+                                    ui.close_menu();
+                                }
+                            }
+                        });
                     });
                     ui.end_row();
+
+                    // A1111 rounds other sizes down, so the image comes out smaller than asked
+                    if !self.sd_width.is_multiple_of(8) || !self.sd_height.is_multiple_of(8) {
+                        ui.label("");
+                        ui.label(egui::RichText::new("⚠ Width and height should be multiples of 8").color(ui.visuals().warn_fg_color));
+                        ui.end_row();
+                    }
+
+                    // Hires.fix
+                    ui.label("Hires.fix:");
+                    ui.checkbox(&mut self.sd_hires_enabled, "Enabled")
+                        .on_hover_text("Upscale with a second pass. Roughly doubles generation time and VRAM use.");
+                    ui.end_row();
+
+                    if self.sd_hires_enabled {
+                        ui.label("Upscale by:");
+                        ui.add(egui::Slider::new(&mut self.sd_hr_scale, 1.0..=4.0).step_by(0.05).text(""));
+                        ui.end_row();
+
+                        ui.label("Upscaler:");
+                        egui::ComboBox::from_id_source("sd_hr_upscaler_select")
+                            .selected_text(&self.sd_hr_upscaler)
+                            .show_ui(ui, |ui| {
+                                for upscaler in crate::sdclient::HIRES_UPSCALERS {
+                                    ui.selectable_value(&mut self.sd_hr_upscaler, upscaler.to_string(), *upscaler);
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Denoising:");
+                        ui.add(egui::Slider::new(&mut self.sd_denoising_strength, 0.0..=1.0).text(""));
+                        ui.end_row();
+                    }
+
+                    ui.label("Tiling:");
+                    ui.checkbox(&mut self.sd_tiling, "Seamless")
+                        .on_hover_text("Make the image repeat without visible seams when placed side by side, for textures");
+                    ui.end_row();
+
+                    // Face restoration
+                    ui.label("Restore faces:");
+                    ui.checkbox(&mut self.sd_restore_faces, "Enabled")
+                        .on_hover_text("Fix faces in the finished images with a face restoration model");
+                    ui.end_row();
+
+                    if self.sd_restore_faces {
+                        ui.label("Restorer:");
+                        let selected = if self.sd_selected_face_restorer.is_empty() { "Server default" } else { &self.sd_selected_face_restorer };
+                        egui::ComboBox::from_id_source("sd_face_restorer_select")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for restorer in &self.sd_face_restorers {
+                                    ui.selectable_value(&mut self.sd_selected_face_restorer, restorer.clone(), restorer);
+                                }
+                            });
+                        ui.end_row();
+
+                        if self.sd_selected_face_restorer == "CodeFormer" {
+                            ui.label("CodeFormer weight:");
+                            ui.add(egui::Slider::new(&mut self.sd_codeformer_weight, 0.0..=1.0).step_by(0.01).text(""))
+                                .on_hover_text("0 = strongest restoration, 1 = closest to the generated face");
+                            ui.end_row();
+                        }
+                    }
                 });
         });
     }
-} 
\ No newline at end of file
+} 
+
+/// A selectable error with a button to copy it, and a Retry button when `retry_hover` says
+/// what retrying does. Returns whether Retry was clicked.
+fn render_error(ui: &mut egui::Ui, error: &str, retry_hover: Option<&str>) -> bool {
+    ui.add(egui::Label::new(egui::RichText::new(error).color(egui::Color32::RED)).wrap(true).selectable(true));
+
+    let mut retry = false;
+    ui.horizontal(|ui| {
+        if ui.small_button("Copy error").clicked() {
+            ui.output_mut(|o| o.copied_text = error.to_string());
+        }
+        if let Some(hover) = retry_hover {
+            retry = ui.small_button("Retry").on_hover_text(hover).clicked();
+        }
+    });
+    retry
+}
+
+/// Copies the last request of `kind` as a `curl` command; disabled until one was sent.
+fn render_copy_curl_button(ui: &mut egui::Ui, curl: Option<&str>, kind: &str) {
+    if ui.add_enabled(curl.is_some(), egui::Button::new("Copy as curl"))
+        .on_hover_text(format!("Copy the last {} request as a curl command, with the API key redacted", kind))
+        .on_disabled_hover_text(format!("No {} request sent since developer tools were turned on", kind))
+        .clicked()
+    {
+        if let Some(curl) = curl {
+            ui.output_mut(|o| o.copied_text = curl.to_string());
+        }
+    }
+}
+
+fn render_copy_button(ui: &mut egui::Ui, content: &str) {
+    if ui.small_button("📋").on_hover_text("Copy message").clicked() {
+        // Copy what the user sees, without the model's reasoning
+        ui.output_mut(|o| o.copied_text = strip_reasoning(content));
+    }
+}
+
+/// Star button for `input` and a menu of favorite and recent prompts that fills it.
+fn render_prompt_history(ui: &mut egui::Ui, history: &mut PromptHistory, input: &mut String) {
+    let starred = history.is_favorite(input);
+    let star = if starred { "★" } else { "☆" };
+    let hover = if starred { "Remove from favorites" } else { "Save as favorite" };
+    if ui.add_enabled(!input.trim().is_empty(), egui::Button::new(star).small())
+        .on_hover_text(hover)
+        .clicked()
+    {
+        history.toggle_favorite(input);
+    }
+
+    ui.add_enabled_ui(!history.recent.is_empty() || !history.favorites.is_empty(), |ui| {
+        ui.menu_button("🕘", |ui| {
+            let mut selected = None;
+            if !history.favorites.is_empty() {
+                ui.label(egui::RichText::new("Favorites").strong());
+                for prompt in &history.favorites {
+                    if ui.button(prompt_preview(prompt)).on_hover_text(prompt).clicked() {
+                        selected = Some(prompt.clone());
+                    }
+                }
+                ui.separator();
+            }
+            if !history.recent.is_empty() {
+                ui.label(egui::RichText::new("Recent").strong());
+                for prompt in &history.recent {
+                    if ui.button(prompt_preview(prompt)).on_hover_text(prompt).clicked() {
+                        selected = Some(prompt.clone());
+                    }
+                }
+            }
+            if let Some(prompt) = selected {
+                *input = prompt;
+                ui.close_menu();
+            }
+        })
+        .response
+        .on_hover_text("Recent and favorite prompts");
+    });
+}
+
+/// First line of `prompt`, shortened to fit in a menu.
+fn prompt_preview(prompt: &str) -> String {
+    const MAX_CHARS: usize = 60;
+    let line = prompt.lines().next().unwrap_or("");
+    if line.chars().count() > MAX_CHARS || prompt.lines().nth(1).is_some() {
+        format!("{}…", line.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+fn chat_input_id() -> egui::Id {
+    egui::Id::new("chat_input")
+}
+
+/// Search box at the top of a combo box popup. It takes focus as soon as the popup
+/// opens, since clicking inside the popup would close it.
+fn render_combo_filter(ui: &mut egui::Ui, filter: &mut String) {
+    ui.add(egui::TextEdit::singleline(filter)
+        .hint_text("Type to filter...")
+        .desired_width(ui.available_width()))
+        .request_focus();
+    ui.separator();
+}
+
+/// The settings an image was generated with, selectable so they can be copied elsewhere.
+fn render_generation_details(ui: &mut egui::Ui, details: &GenerationDetails, time: Option<Duration>) {
+    let model = match (&details.model, &details.model_hash) {
+        (Some(model), Some(hash)) => Some(format!("{} [{}]", model, hash)),
+        (model, hash) => model.clone().or_else(|| hash.clone()),
+    };
+    let size = details.width.zip(details.height).map(|(width, height)| format!("{}×{}", width, height));
+    let rows = [
+        ("Seed", details.seed.map(|seed| seed.to_string())),
+        ("Subseed", details.subseed.map(|subseed| subseed.to_string())),
+        ("Model", model),
+        ("Steps", details.steps.map(|steps| steps.to_string())),
+        ("Sampler", details.sampler.clone()),
+        ("CFG scale", details.cfg_scale.map(|cfg| format!("{:.1}", cfg))),
+        ("Size", size),
+        ("Time", time.map(|time| format!("{:.1}s", time.as_secs_f32()))),
+    ];
+
+    egui::Grid::new("sd_generation_details_grid").num_columns(2).show(ui, |ui| {
+        for (label, value) in rows {
+            if let Some(value) = value {
+                ui.label(format!("{}:", label));
+                ui.add(egui::Label::new(value).selectable(true));
+                ui.end_row();
+            }
+        }
+    });
+}
+
+/// Case-insensitive substring match for the combo box filters.
+fn matches_filter(filter: &str, text: &str) -> bool {
+    text.to_lowercase().contains(&filter.trim().to_lowercase())
+}
+
+/// Dim the window and show what a drop will do while files are dragged over it.
+fn render_drop_zone(ctx: &egui::Context, text: &str) {
+    if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+        return;
+    }
+
+    let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_zone")));
+    let rect = ctx.screen_rect();
+    let accent = ctx.style().visuals.selection.bg_fill;
+    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+    painter.rect_stroke(rect.shrink(8.0), 8.0, egui::Stroke::new(3.0, accent));
+    painter.text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        text,
+        egui::FontId::proportional(24.0),
+        egui::Color32::WHITE,
+    );
+}
+
+fn render_connection_status(ui: &mut egui::Ui, status: ConnectionStatus) {
+    let (color, text) = match status {
+        ConnectionStatus::Online => (egui::Color32::GREEN, "LLM server reachable"),
+        ConnectionStatus::Offline => (egui::Color32::RED, "LLM server unreachable"),
+        ConnectionStatus::Unknown => (egui::Color32::GRAY, "Checking LLM server..."),
+    };
+
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+    ui.painter().circle_filled(rect.center(), 5.0, color);
+    response.on_hover_text(text);
+}
@@ -0,0 +1,109 @@
+use eframe::egui;
+
+use crate::chatapp::ChatApp;
+use crate::tabs::TABS;
+
+/// An action offered in the command palette (Ctrl+Shift+P).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    NewChat,
+    ClearChat,
+    Regenerate,
+    ContinueReply,
+    StopGenerating,
+    ToggleComparison,
+    ExportChat,
+    ImportChat,
+    ToggleSettings,
+    NextTab,
+    RefreshModels,
+    GenerateImage,
+    StopImage,
+    CopyImage,
+    SaveImage,
+}
+
+impl Command {
+    pub const ALL: [Command; 15] = [
+        Command::NewChat,
+        Command::ClearChat,
+        Command::Regenerate,
+        Command::ContinueReply,
+        Command::StopGenerating,
+        Command::ToggleComparison,
+        Command::ExportChat,
+        Command::ImportChat,
+        Command::ToggleSettings,
+        Command::NextTab,
+        Command::RefreshModels,
+        Command::GenerateImage,
+        Command::StopImage,
+        Command::CopyImage,
+        Command::SaveImage,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Command::NewChat => "New Chat",
+            Command::ClearChat => "Clear Chat",
+            Command::Regenerate => "Regenerate Reply",
+            Command::ContinueReply => "Continue Reply",
+            Command::StopGenerating => "Stop Generating",
+            Command::ToggleComparison => "Toggle Comparison Mode",
+            Command::ExportChat => "Export Chat…",
+            Command::ImportChat => "Import Chat…",
+            Command::ToggleSettings => "Toggle Settings",
+            Command::NextTab => "Switch Tab",
+            Command::RefreshModels => "Refresh Models",
+            Command::GenerateImage => "Generate Image",
+            Command::StopImage => "Interrupt Image Generation",
+            Command::CopyImage => "Copy Image",
+            Command::SaveImage => "Save Image…",
+        }
+    }
+
+    /// The key combination that does the same outside the palette, if any.
+    pub fn shortcut(&self) -> Option<&'static str> {
+        match self {
+            Command::NewChat => Some("Ctrl+L"),
+            Command::StopGenerating => Some("Esc"),
+            _ => None,
+        }
+    }
+
+    /// Whether the command would do anything right now; others are left out of the list.
+    pub fn is_available(&self, app: &ChatApp) -> bool {
+        match self {
+            Command::NewChat | Command::ImportChat | Command::ToggleSettings | Command::RefreshModels
+                | Command::ToggleComparison => true,
+            Command::ClearChat | Command::ExportChat => !app.chat_history.is_empty(),
+            Command::Regenerate => app.can_regenerate(),
+            Command::ContinueReply => app.can_continue(),
+            Command::StopGenerating => app.is_streaming(),
+            Command::NextTab => TABS.len() > 1,
+            Command::GenerateImage => !app.sd_prompt.is_empty() && !app.sd_busy(),
+            Command::StopImage => app.sd_generating,
+            Command::CopyImage | Command::SaveImage => app.selected_sd_image().is_some(),
+        }
+    }
+
+    pub fn run(&self, app: &mut ChatApp, ctx: &egui::Context) {
+        match self {
+            Command::NewChat => app.new_conversation(),
+            Command::ClearChat => app.clear_chat(),
+            Command::Regenerate => app.regenerate_response(ctx),
+            Command::ContinueReply => app.continue_response(ctx),
+            Command::StopGenerating => app.cancel_response(),
+            Command::ToggleComparison => app.toggle_comparison(ctx),
+            Command::ExportChat => app.export_chat_history(),
+            Command::ImportChat => app.import_chat_history(ctx),
+            Command::ToggleSettings => app.toggle_settings(ctx),
+            Command::NextTab => app.active_tab = (app.active_tab + 1) % TABS.len(),
+            Command::RefreshModels => app.refresh_models(ctx),
+            Command::GenerateImage => app.generate_sd_image(ctx),
+            Command::StopImage => app.interrupt_sd_generation(ctx),
+            Command::CopyImage => app.copy_sd_image(),
+            Command::SaveImage => app.save_sd_image(),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use poll_promise::Promise;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use crate::endpoint_type::EndpointType;
+use crate::llmclient::{ChatEntry, GenerationStats, StreamMessage};
+
+/// The right-hand column of comparison mode: another endpoint and model answering the
+/// same prompts, with a conversation of its own.
+pub struct ComparisonSide {
+    pub endpoint_type: EndpointType,
+    pub model: String,
+    pub available_models: Vec<String>,
+    pub pending_models: Option<Promise<Result<Vec<String>>>>,
+    pub model_filter: String,
+    /// The prompts sent while comparing and this side's replies to them, after whatever
+    /// the conversation held when comparing started.
+    pub history: Vec<ChatEntry>,
+    pub current_response: String,
+    pub pending_response: Option<Promise<Result<GenerationStats>>>,
+    pub response_receiver: Option<Receiver<StreamMessage>>,
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+}
+
+impl ComparisonSide {
+    pub fn new(endpoint_type: EndpointType, model: String, history: Vec<ChatEntry>) -> Self {
+        Self {
+            endpoint_type,
+            model,
+            available_models: Vec::new(),
+            pending_models: None,
+            model_filter: String::new(),
+            history,
+            current_response: String::new(),
+            pending_response: None,
+            response_receiver: None,
+            cancel_flag: None,
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.pending_response.is_some()
+    }
+
+    /// Append the reply text received so far to `current_response`, returning whether there was any.
+    fn drain_response_channel(&mut self) -> bool {
+        let Some(rx) = &self.response_receiver else {
+            return false;
+        };
+
+        let mut received = false;
+        while let Ok(message) = rx.try_recv() {
+            if let StreamMessage::Text(new_content) = message {
+                self.current_response.push_str(&new_content);
+                received = true;
+            }
+        }
+        received
+    }
+
+    /// Pick up streamed text, and the reply or error once the stream has ended.
+    /// Returns whether anything changed.
+    pub fn process_response(&mut self) -> bool {
+        let received = self.drain_response_channel();
+        let Some(promise) = self.pending_response.take() else {
+            return received;
+        };
+
+        match promise.try_take() {
+            Err(promise) => {
+                self.pending_response = Some(promise);
+                received
+            }
+            Ok(result) => {
+                // The sender is gone, so this picks up everything sent after the drain above
+                self.drain_response_channel();
+                let entry = match result {
+                    Err(e) if self.current_response.is_empty() => Some(ChatEntry::new("error", format!("Error: {}", e))),
+                    Err(e) => {
+                        let mut entry = ChatEntry::new("assistant", std::mem::take(&mut self.current_response));
+                        entry.error = Some(e.to_string());
+                        Some(entry)
+                    }
+                    Ok(stats) => (!self.current_response.is_empty()).then(|| {
+                        let mut entry = ChatEntry::new("assistant", std::mem::take(&mut self.current_response));
+                        entry.stats = (!stats.is_empty()).then_some(stats);
+                        entry
+                    }),
+                };
+                self.history.extend(entry);
+                self.current_response.clear();
+                self.response_receiver = None;
+                self.cancel_flag = None;
+                true
+            }
+        }
+    }
+
+    /// Stop streaming, keeping whatever part of the reply already arrived.
+    pub fn cancel(&mut self) {
+        if self.pending_response.is_none() {
+            return;
+        }
+
+        if let Some(cancel) = self.cancel_flag.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        self.drain_response_channel();
+        if !self.current_response.is_empty() {
+            self.history.push(ChatEntry::new("assistant", std::mem::take(&mut self.current_response)));
+        }
+
+        self.current_response.clear();
+        self.pending_response = None;
+        self.response_receiver = None;
+    }
+}
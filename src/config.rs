@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::conversation::Conversation;
+use crate::endpoint_type::EndpointType;
+use crate::llmclient::{ChatEntry, ChatOptions, ClientSettings};
+use crate::logging::LogLevel;
+use crate::prompt_history::PromptHistory;
+use crate::sampling_preset::SamplingPreset;
+use crate::sd_filename::DEFAULT_FILENAME_TEMPLATE;
+
+/// Smallest window the chat and SD layouts still fit in.
+pub const MIN_WINDOW_SIZE: [f32; 2] = [640.0, 480.0];
+
+/// Where the main window was when the app closed, in the OS's logical pixels
+/// so that changing the UI scale doesn't resize it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub size: [f32; 2],
+    /// Not every platform reports it, e.g. Wayland.
+    pub position: Option<[f32; 2]>,
+    pub maximized: bool,
+}
+
+/// Which visuals the UI uses.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ThemePreference {
+    Dark,
+    Light,
+    System,
+}
+
+impl ThemePreference {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThemePreference::Dark => "Dark",
+            ThemePreference::Light => "Light",
+            ThemePreference::System => "Follow System",
+        }
+    }
+}
+
+/// Connection settings remembered separately for each endpoint type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointConfig {
+    pub protocol: String,
+    pub server: String,
+    pub port: String,
+    pub endpoint: String,
+    pub selected_model: String,
+}
+
+impl EndpointConfig {
+    pub fn defaults(endpoint_type: EndpointType) -> Self {
+        Self {
+            protocol: endpoint_type.default_protocol().to_string(),
+            server: endpoint_type.default_server().to_string(),
+            port: endpoint_type.default_port().to_string(),
+            endpoint: endpoint_type.default_endpoint().to_string(),
+            selected_model: "local-model".to_string(),
+        }
+    }
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self::defaults(EndpointType::Ollama)
+    }
+}
+
+/// Settings persisted between runs through eframe's storage.
+/// Missing fields fall back to their defaults so older saves keep loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub endpoint_type: EndpointType,
+    pub api_key: String,
+    pub custom_headers: Vec<(String, String)>,
+    pub connect_timeout: u64,
+    pub request_timeout: u64,
+    pub retry_attempts: u32,
+    pub ollama_generate: bool,
+    pub accept_invalid_certs: bool,
+    pub disable_streaming: bool,
+    pub protocol: String,
+    pub server: String,
+    pub port: String,
+    pub endpoint: String,
+    pub selected_model: String,
+    /// Settings of the endpoint types not currently selected; the fields above are the active one's.
+    pub endpoint_configs: HashMap<EndpointType, EndpointConfig>,
+    pub model_context_lengths: HashMap<String, u32>,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub top_k: u32,
+    pub max_tokens: u32,
+    pub presence_penalty: f32,
+    pub frequency_penalty: f32,
+    pub stop_sequences: Vec<String>,
+    pub max_history_messages: u32,
+    pub num_ctx: u32,
+    pub json_mode: bool,
+    pub completions: u32,
+    pub prefill: String,
+    pub prefill_sticky: bool,
+    pub custom_presets: Vec<SamplingPreset>,
+    pub sd_base_url: String,
+    pub sd_save_folder: String,
+    pub sd_filename_template: String,
+    pub sd_quick_save: bool,
+    pub theme: ThemePreference,
+    pub ui_scale: f32,
+    pub window: Option<WindowGeometry>,
+    pub restore_chat_history: bool,
+    pub interrupt_on_send: bool,
+    pub fence_dropped_text: bool,
+    pub show_reasoning: bool,
+    pub show_timestamps: bool,
+    pub user_name: String,
+    /// Name shown on replies, empty for the selected model.
+    pub assistant_name: String,
+    /// Emoji or short text shown before each name, empty for none.
+    pub user_avatar: String,
+    pub assistant_avatar: String,
+    pub developer_tools: bool,
+    pub log_level: LogLevel,
+    /// Where to copy the log, empty for stderr only.
+    pub log_file: String,
+    pub chat_prompts: PromptHistory,
+    pub sd_prompts: PromptHistory,
+    pub conversations: Vec<Conversation>,
+    pub active_conversation: usize,
+    /// Single history written before conversations existed, only read to migrate old saves.
+    #[serde(skip_serializing)]
+    pub chat_history: Vec<ChatEntry>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            endpoint_type: EndpointType::Ollama,
+            api_key: String::new(),
+            custom_headers: Vec::new(),
+            connect_timeout: ClientSettings::default().connect_timeout,
+            request_timeout: ClientSettings::default().request_timeout,
+            retry_attempts: ClientSettings::default().retry_attempts,
+            ollama_generate: ClientSettings::default().ollama_generate,
+            accept_invalid_certs: ClientSettings::default().accept_invalid_certs,
+            disable_streaming: ClientSettings::default().disable_streaming,
+            protocol: "http".to_string(),
+            server: "localhost".to_string(),
+            port: "11434".to_string(),
+            endpoint: "v1/chat/completions".to_string(),
+            selected_model: "local-model".to_string(),
+            endpoint_configs: HashMap::new(),
+            model_context_lengths: HashMap::new(),
+            temperature: ChatOptions::default().temperature,
+            top_p: ChatOptions::default().top_p,
+            top_k: ChatOptions::default().top_k,
+            max_tokens: ChatOptions::default().max_tokens,
+            presence_penalty: ChatOptions::default().presence_penalty,
+            frequency_penalty: ChatOptions::default().frequency_penalty,
+            stop_sequences: Vec::new(),
+            max_history_messages: ChatOptions::default().max_history_messages,
+            num_ctx: ChatOptions::default().num_ctx,
+            json_mode: ChatOptions::default().json_mode,
+            completions: ChatOptions::default().completions,
+            prefill: String::new(),
+            prefill_sticky: false,
+            custom_presets: Vec::new(),
+            sd_base_url: "http://localhost:7860".to_string(),
+            sd_save_folder: String::new(),
+            sd_filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            sd_quick_save: false,
+            theme: ThemePreference::System,
+            ui_scale: 1.0,
+            window: None,
+            restore_chat_history: false,
+            interrupt_on_send: false,
+            fence_dropped_text: true,
+            show_reasoning: true,
+            show_timestamps: false,
+            user_name: "You".to_string(),
+            assistant_name: String::new(),
+            user_avatar: String::new(),
+            assistant_avatar: String::new(),
+            developer_tools: false,
+            log_level: LogLevel::Info,
+            log_file: String::new(),
+            chat_prompts: PromptHistory::default(),
+            sd_prompts: PromptHistory::default(),
+            conversations: Vec::new(),
+            active_conversation: 0,
+            chat_history: Vec::new(),
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+}
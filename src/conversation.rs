@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use crate::llmclient::ChatEntry;
+
+/// Longest auto-generated title, in characters.
+const MAX_TITLE_CHARS: usize = 40;
+
+/// A saved chat shown in the sidebar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: u64,
+    /// Empty until the first user message gives the conversation a title.
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub history: Vec<ChatEntry>,
+}
+
+impl Conversation {
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            title: String::new(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn display_title(&self) -> &str {
+        if self.title.is_empty() {
+            "New Chat"
+        } else {
+            &self.title
+        }
+    }
+
+    /// Title the conversation after its first user message, if it doesn't have one yet.
+    pub fn auto_title(&mut self) {
+        if !self.title.is_empty() {
+            return;
+        }
+
+        let Some(first) = self.history.iter().find(|entry| entry.role == "user") else {
+            return;
+        };
+
+        let line = first.content.lines().find(|line| !line.trim().is_empty()).unwrap_or("").trim();
+        let mut title: String = line.chars().take(MAX_TITLE_CHARS).collect();
+        if line.chars().count() > MAX_TITLE_CHARS {
+            title.push('…');
+        }
+        self.title = title;
+    }
+}
+
+/// The id to give a new conversation.
+pub fn next_conversation_id(conversations: &[Conversation]) -> u64 {
+    conversations.iter().map(|c| c.id).max().map_or(0, |id| id + 1)
+}
@@ -0,0 +1,17 @@
+/// A shell command that sends `body` as JSON to `url` with `curl`, for reproducing requests by hand.
+pub fn curl_command(url: &str, headers: &[(&str, String)], body: &impl serde::Serialize) -> String {
+    let mut command = format!("curl -X POST {}", shell_quote(url));
+    command.push_str(" \\\n  -H 'Content-Type: application/json'");
+    for (name, value) in headers {
+        command.push_str(&format!(" \\\n  -H {}", shell_quote(&format!("{}: {}", name, value))));
+    }
+
+    let body = serde_json::to_string_pretty(body).unwrap_or_default();
+    command.push_str(&format!(" \\\n  -d {}", shell_quote(&body)));
+    command
+}
+
+/// Quote `text` for a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
@@ -1,21 +1,61 @@
-#[derive(Debug, Clone, PartialEq, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy, Serialize, Deserialize)]
 pub enum EndpointType {
     LMStudio,
     Ollama,
+    OpenAI,
+    LlamaCpp,
+    Anthropic,
 }
 
+/// Offered when Anthropic's model list can't be fetched, e.g. before an API key is entered.
+const ANTHROPIC_MODELS: &[&str] = &[
+    "claude-sonnet-4-5",
+    "claude-opus-4-1",
+    "claude-haiku-4-5",
+];
+
 impl EndpointType {
-    pub fn default_url(&self) -> &'static str {
+    pub const ALL: [EndpointType; 5] = [
+        EndpointType::LMStudio,
+        EndpointType::Ollama,
+        EndpointType::OpenAI,
+        EndpointType::LlamaCpp,
+        EndpointType::Anthropic,
+    ];
+
+    pub fn label(&self) -> &'static str {
         match self {
-            EndpointType::LMStudio => "http://localhost:1234/v1/chat/completions",
-            EndpointType::Ollama => "http://localhost:11434/v1/chat/completions",
+            EndpointType::LMStudio => "LM Studio",
+            EndpointType::Ollama => "Ollama",
+            EndpointType::OpenAI => "OpenAI-Compatible",
+            EndpointType::LlamaCpp => "llama.cpp",
+            EndpointType::Anthropic => "Anthropic",
         }
     }
-    
+
+    pub fn default_protocol(&self) -> &'static str {
+        match self {
+            EndpointType::LMStudio | EndpointType::Ollama | EndpointType::LlamaCpp => "http",
+            EndpointType::OpenAI | EndpointType::Anthropic => "https",
+        }
+    }
+
+    pub fn default_server(&self) -> &'static str {
+        match self {
+            EndpointType::LMStudio | EndpointType::Ollama | EndpointType::LlamaCpp => "localhost",
+            EndpointType::OpenAI => "api.openai.com",
+            EndpointType::Anthropic => "api.anthropic.com",
+        }
+    }
+
     pub fn default_port(&self) -> &'static str {
         match self {
             EndpointType::LMStudio => "1234",
             EndpointType::Ollama => "11434",
+            EndpointType::OpenAI | EndpointType::Anthropic => "443",
+            EndpointType::LlamaCpp => "8080",
         }
     }
 
@@ -23,48 +63,187 @@ impl EndpointType {
         match self {
             EndpointType::LMStudio => "v1/chat/completions",
             EndpointType::Ollama => "v1/chat/completions",
+            EndpointType::OpenAI => "v1/chat/completions",
+            EndpointType::LlamaCpp => "completion",
+            EndpointType::Anthropic => "v1/messages",
+        }
+    }
+
+    /// Models to choose from when the server can't list them.
+    pub fn known_models(&self) -> &'static [&'static str] {
+        match self {
+            EndpointType::Anthropic => ANTHROPIC_MODELS,
+            _ => &[],
         }
     }
     
     pub fn models_endpoint(&self, endpoint: &str) -> String {
-        match self {
+        let path = match self {
+            EndpointType::LMStudio | EndpointType::OpenAI | EndpointType::LlamaCpp | EndpointType::Anthropic => {
+                // LM Studio, OpenAI, llama.cpp (OpenAI-compatible surface) and Anthropic all use /v1/models
+                "v1/models"
+            }
+            EndpointType::Ollama => "api/tags",
+        };
+        join_base_path(endpoint, path)
+    }
+
+    /// A path to probe for this kind of server, with a field its reply has. The native APIs are
+    /// only answered by their own servers, the OpenAI-compatible one by most of them. Anthropic
+    /// isn't probed, it only runs at its own host.
+    pub fn probe_endpoint(&self, endpoint: &str) -> Option<(String, &'static str)> {
+        let (path, key) = match self {
+            EndpointType::Ollama => ("api/tags", "models"),
+            // LM Studio's native REST API
+            EndpointType::LMStudio => ("api/v0/models", "data"),
+            // The llama.cpp server's settings
+            EndpointType::LlamaCpp => ("props", "default_generation_settings"),
+            EndpointType::OpenAI => ("v1/models", "data"),
+            EndpointType::Anthropic => return None,
+        };
+        Some((join_base_path(endpoint, path), key))
+    }
+
+    /// The default endpoint under the base path of `endpoint`, keeping a reverse proxy's prefix.
+    pub fn default_endpoint_under(&self, endpoint: &str) -> String {
+        join_base_path(endpoint, self.default_endpoint())
+    }
+
+    pub fn model_info_endpoint(&self, endpoint: &str, model: &str) -> String {
+        let path = match self {
             EndpointType::LMStudio => {
-                // For LM Studio, always use /v1/models
-                "v1/models".to_string()
+                // LM Studio exposes per-model metadata on its native REST API
+                format!("api/v0/models/{}", model)
             }
-            EndpointType::Ollama => {
-                // For Ollama, use /api/tags but respect any custom base path
-                if endpoint.is_empty() {
-                    "api/tags".to_string()
-                } else {
-                    // Strip the chat completions part if present and add api/tags
-                    let base = endpoint.trim_end_matches("v1/chat/completions");
-                    format!("{}api/tags", base.trim_end_matches('/'))
-                        .trim_start_matches('/')
-                        .to_string()
-                }
+            EndpointType::OpenAI | EndpointType::Anthropic => {
+                format!("v1/models/{}", model)
             }
+            EndpointType::LlamaCpp => {
+                // llama.cpp serves a single model and reports its settings on /props
+                "props".to_string()
+            }
+            // Ollama takes the model in the request body
+            EndpointType::Ollama => "api/show".to_string(),
+        };
+        join_base_path(endpoint, &path)
+    }
+
+    /// Ollama's model download endpoint; the other servers can't pull models.
+    pub fn pull_endpoint(&self, endpoint: &str) -> Option<String> {
+        self.ollama_management_endpoint(endpoint, "api/pull")
+    }
+
+    /// Ollama's endpoint for removing a model from the server.
+    pub fn delete_endpoint(&self, endpoint: &str) -> Option<String> {
+        self.ollama_management_endpoint(endpoint, "api/delete")
+    }
+
+    fn ollama_management_endpoint(&self, endpoint: &str, path: &str) -> Option<String> {
+        match self {
+            EndpointType::Ollama => Some(join_base_path(endpoint, path)),
+            EndpointType::LMStudio | EndpointType::OpenAI | EndpointType::LlamaCpp | EndpointType::Anthropic => None,
+        }
+    }
+
+    /// Whether the server can generate several replies to one request (`n`).
+    pub fn supports_completions(&self) -> bool {
+        match self {
+            EndpointType::LMStudio | EndpointType::OpenAI => true,
+            EndpointType::Ollama | EndpointType::LlamaCpp | EndpointType::Anthropic => false,
         }
     }
 
     pub fn chat_endpoint(&self, endpoint: &str) -> String {
+        let path = match self {
+            EndpointType::LMStudio | EndpointType::OpenAI => "v1/chat/completions",
+            // llama.cpp's native streaming completion endpoint
+            EndpointType::LlamaCpp => "completion",
+            // Anthropic's Messages API
+            EndpointType::Anthropic => "v1/messages",
+            EndpointType::Ollama => "api/chat",
+        };
+        join_base_path(endpoint, path)
+    }
+
+    /// Raw completion endpoint, only offered by Ollama; other backends keep their chat endpoint.
+    pub fn generate_endpoint(&self, endpoint: &str) -> String {
         match self {
-            EndpointType::LMStudio => {
-                // For LM Studio, always use /v1/chat/completions
-                "v1/chat/completions".to_string()
-            }
-            EndpointType::Ollama => {
-                // For Ollama, use /api/chat but respect any custom base path
-                if endpoint.is_empty() {
-                    "api/chat".to_string()
-                } else {
-                    // Strip the chat completions part if present and add api/chat
-                    let base = endpoint.trim_end_matches("v1/chat/completions");
-                    format!("{}api/chat", base.trim_end_matches('/'))
-                        .trim_start_matches('/')
-                        .to_string()
-                }
-            }
+            EndpointType::Ollama => join_base_path(endpoint, "api/generate"),
+            _ => self.chat_endpoint(endpoint),
         }
     }
 }
+
+/// API paths the endpoint setting may end with, longest first. What comes before them is
+/// the base path a reverse proxy serves the API under, e.g. `proxy/ollama`.
+const API_PATHS: &[&[&str]] = &[
+    &["v1", "chat", "completions"],
+    &["api", "v0", "models"],
+    &["v1", "completions"],
+    &["v1", "messages"],
+    &["v1", "models"],
+    &["api", "chat"],
+    &["api", "generate"],
+    &["api", "tags"],
+    &["api", "show"],
+    &["api", "pull"],
+    &["api", "delete"],
+    &["completion"],
+    &["props"],
+    &["v1"],
+];
+
+/// `path` under the base path of the endpoint setting. Both are compared and joined by
+/// segment, so extra, missing or doubled slashes don't matter.
+fn join_base_path(endpoint: &str, path: &str) -> String {
+    let mut segments: Vec<&str> = endpoint.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if let Some(api_path) = API_PATHS.iter().find(|api_path| segments.ends_with(api_path)) {
+        segments.truncate(segments.len() - api_path.len());
+    }
+    segments.extend(path.split('/').filter(|segment| !segment.is_empty()));
+    segments.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_base_path_keeps_proxy_prefix() {
+        assert_eq!(join_base_path("proxy/ollama", "api/tags"), "proxy/ollama/api/tags");
+        assert_eq!(join_base_path("proxy/ollama/api/chat", "api/tags"), "proxy/ollama/api/tags");
+        assert_eq!(join_base_path("/proxy/ollama/", "/api/tags"), "proxy/ollama/api/tags");
+    }
+
+    #[test]
+    fn join_base_path_without_base() {
+        assert_eq!(join_base_path("", "v1/models"), "v1/models");
+        assert_eq!(join_base_path("/", "v1/models"), "v1/models");
+        assert_eq!(join_base_path("v1/chat/completions", "v1/models"), "v1/models");
+    }
+
+    #[test]
+    fn ollama_endpoints_under_base() {
+        let ollama = EndpointType::Ollama;
+        assert_eq!(ollama.chat_endpoint(""), "api/chat");
+        assert_eq!(ollama.models_endpoint("proxy/ollama"), "proxy/ollama/api/tags");
+        assert_eq!(ollama.models_endpoint("proxy/ollama/"), "proxy/ollama/api/tags");
+        assert_eq!(ollama.pull_endpoint("proxy/ollama/api/chat/").as_deref(), Some("proxy/ollama/api/pull"));
+        assert_eq!(ollama.chat_endpoint("proxy/ollama/api/pull"), "proxy/ollama/api/chat");
+        assert_eq!(ollama.chat_endpoint("proxy/ollama/api/delete"), "proxy/ollama/api/chat");
+        assert_eq!(ollama.chat_endpoint("proxy/ollama/api/show"), "proxy/ollama/api/chat");
+    }
+
+    #[test]
+    fn openai_endpoints_under_base() {
+        let openai = EndpointType::OpenAI;
+        assert_eq!(openai.chat_endpoint(""), "v1/chat/completions");
+        assert_eq!(openai.models_endpoint("proxy/openai"), "proxy/openai/v1/models");
+        assert_eq!(openai.models_endpoint("proxy/openai/v1/"), "proxy/openai/v1/models");
+        assert_eq!(openai.chat_endpoint("proxy/openai/v1/chat/completions/"), "proxy/openai/v1/chat/completions");
+        assert_eq!(EndpointType::LMStudio.chat_endpoint("proxy/lms/api/v0/models"), "proxy/lms/v1/chat/completions");
+        assert_eq!(EndpointType::LlamaCpp.chat_endpoint("proxy/llama/props"), "proxy/llama/completion");
+    }
+}
@@ -1,11 +1,37 @@
 use anyhow::{Result, Context};
-use reqwest::Client;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
-use std::sync::mpsc::SyncSender;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use futures_util::StreamExt;
+use crate::curl;
 use crate::endpoint_type::EndpointType;
 
+/// Delay before the first retry, doubled for every further attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Tags used to mark reasoning in the streamed text.
+const REASONING_OPEN: &str = "<think>";
+const REASONING_CLOSE: &str = "</think>";
+
+/// API version sent in the `anthropic-version` header.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Anthropic requires `max_tokens`, so this is sent when no limit is set.
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Pulls download gigabytes, so they get far longer than the configured request timeout.
+const PULL_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+/// Health checks give up quickly so an offline server is reported without delay.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sent after a cut-off reply to chat APIs that can't resume an assistant message themselves.
+const CONTINUE_PROMPT: &str = "Continue your previous response exactly where it stopped, \
+    without repeating anything or adding an introduction.";
+
 #[derive(Debug, Deserialize)]
 pub struct ModelData {
     pub id: String,
@@ -22,28 +48,623 @@ pub struct ChatMessage {
     pub content: String,
 }
 
+/// Wrap reasoning that a server streams in its own field in think tags, so it renders
+/// the same way as models that write the tags inline.
+fn tag_reasoning(reasoning: Option<&str>, content: Option<&str>, in_reasoning: &mut bool) -> String {
+    let mut text = String::new();
+    if let Some(reasoning) = reasoning.filter(|reasoning| !reasoning.is_empty()) {
+        if !*in_reasoning {
+            text.push_str(REASONING_OPEN);
+            *in_reasoning = true;
+        }
+        text.push_str(reasoning);
+    }
+    if let Some(content) = content.filter(|content| !content.is_empty()) {
+        if *in_reasoning {
+            text.push_str(REASONING_CLOSE);
+            *in_reasoning = false;
+        }
+        text.push_str(content);
+    }
+    text
+}
+
+/// Ollama explains a failed request, e.g. an unknown model, in an `error` field of the body.
+fn ollama_error_message(body: String) -> String {
+    serde_json::from_str::<PullProgress>(&body).ok()
+        .and_then(|progress| progress.error)
+        .unwrap_or(body)
+}
+
+/// Ollama's `thinking` text, or the OpenAI-style fields some builds and proxies use instead.
+fn ollama_reasoning(message: &serde_json::Value) -> Option<&str> {
+    ["thinking", "reasoning_content", "reasoning"].iter()
+        .filter_map(|key| message.get(*key).and_then(|v| v.as_str()))
+        .find(|reasoning| !reasoning.is_empty())
+}
+
+/// The token counts and timings (in nanoseconds) on Ollama's final object.
+fn ollama_stats(response: &serde_json::Value) -> GenerationStats {
+    let count = |key: &str| response.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+    GenerationStats {
+        prompt_tokens: count("prompt_eval_count"),
+        completion_tokens: count("eval_count"),
+        generation_secs: response.get("eval_duration")
+            .and_then(|v| v.as_u64())
+            .map(|nanos| nanos as f64 / 1e9),
+        finish_reason: response.get("done_reason")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+    }
+}
+
+/// Decode `chunk` after the bytes left over from the previous one. A multibyte character
+/// cut off at the end is kept in `pending` for the next chunk instead of becoming U+FFFD.
+fn decode_utf8_chunk(pending: &mut Vec<u8>, chunk: &[u8]) -> String {
+    pending.extend_from_slice(chunk);
+
+    let mut text = String::new();
+    let mut start = 0;
+    while start < pending.len() {
+        match std::str::from_utf8(&pending[start..]) {
+            Ok(valid) => {
+                text.push_str(valid);
+                start = pending.len();
+            }
+            Err(e) => {
+                let valid_end = start + e.valid_up_to();
+                text.push_str(&String::from_utf8_lossy(&pending[start..valid_end]));
+                match e.error_len() {
+                    // Really invalid bytes, not just an incomplete character
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        start = valid_end + len;
+                    }
+                    None => {
+                        start = valid_end;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pending.drain(..start);
+    text
+}
+
+/// A message in the conversation shown in the UI, with any metadata collected for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ChatEntryRepr")]
+pub struct ChatEntry {
+    pub role: String,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<GenerationStats>,
+    /// Why a reply ended early; the content is then only the part received before the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// When the message was sent or the reply finished; unknown for histories saved before this was kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Replies generated for the same turn before or after this one, kept when regenerating.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub other_replies: Vec<ReplyVariant>,
+    /// Position of this reply among all of the turn's replies, in the order they were generated.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub reply_index: usize,
+    /// Images sent with a user message, so regenerating or editing it sends them again.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageInput>,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+/// One of several replies generated for the same turn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyVariant {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<GenerationStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl ReplyVariant {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            stats: None,
+            error: None,
+            timestamp: Some(Utc::now()),
+        }
+    }
+}
+
+impl ChatEntry {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            stats: None,
+            error: None,
+            timestamp: Some(Utc::now()),
+            other_replies: Vec::new(),
+            reply_index: 0,
+            images: Vec::new(),
+        }
+    }
+
+    /// An assistant message showing `replies[index]`, with the others kept as variants.
+    pub fn from_replies(mut replies: Vec<ReplyVariant>, index: usize) -> Option<Self> {
+        if replies.is_empty() {
+            return None;
+        }
+        let index = index.min(replies.len() - 1);
+        let shown = replies.remove(index);
+        Some(Self {
+            role: "assistant".to_string(),
+            content: shown.content,
+            stats: shown.stats,
+            error: shown.error,
+            timestamp: shown.timestamp,
+            other_replies: replies,
+            reply_index: index,
+            images: Vec::new(),
+        })
+    }
+
+    /// Every reply generated for this turn, in the order they were generated.
+    pub fn replies(&self) -> Vec<ReplyVariant> {
+        let mut replies = self.other_replies.clone();
+        let shown = ReplyVariant {
+            content: self.content.clone(),
+            stats: self.stats.clone(),
+            error: self.error.clone(),
+            timestamp: self.timestamp,
+        };
+        replies.insert(self.reply_index.min(replies.len()), shown);
+        replies
+    }
+
+    pub fn reply_count(&self) -> usize {
+        self.other_replies.len() + 1
+    }
+
+    /// Show the reply at `index` instead of the current one.
+    pub fn select_reply(&mut self, index: usize) {
+        if index == self.reply_index || index >= self.reply_count() {
+            return;
+        }
+        if let Some(entry) = Self::from_replies(self.replies(), index) {
+            *self = entry;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChatEntryRepr {
+    Entry {
+        role: String,
+        content: String,
+        #[serde(default)]
+        stats: Option<GenerationStats>,
+        #[serde(default)]
+        error: Option<String>,
+        #[serde(default)]
+        timestamp: Option<DateTime<Utc>>,
+        #[serde(default)]
+        other_replies: Vec<ReplyVariant>,
+        #[serde(default)]
+        reply_index: usize,
+        #[serde(default)]
+        images: Vec<ImageInput>,
+    },
+    // Histories saved before messages carried metadata were plain (role, content) pairs
+    Pair(String, String),
+}
+
+impl From<ChatEntryRepr> for ChatEntry {
+    fn from(repr: ChatEntryRepr) -> Self {
+        match repr {
+            ChatEntryRepr::Entry { role, content, stats, error, timestamp, other_replies, reply_index, images } => {
+                Self { role, content, stats, error, timestamp, other_replies, reply_index, images }
+            }
+            ChatEntryRepr::Pair(role, content) => Self {
+                timestamp: None,
+                ..Self::new(&role, content)
+            },
+        }
+    }
+}
+
+/// Token counts and timing reported at the end of a streamed reply.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationStats {
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    /// Time spent generating the completion, in seconds.
+    pub generation_secs: Option<f64>,
+    /// Why the server ended the reply, in its own words ("stop", "length", "max_tokens", ...).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+}
+
+impl GenerationStats {
+    pub fn is_empty(&self) -> bool {
+        self.prompt_tokens.is_none() && self.completion_tokens.is_none() && self.stop_note().is_none()
+    }
+
+    /// Why the reply ended, when it wasn't simply finished.
+    pub fn stop_note(&self) -> Option<&str> {
+        match self.finish_reason.as_deref()? {
+            // Finished normally or at one of the stop sequences
+            "stop" | "end_turn" | "eos" | "stop_sequence" | "word" => None,
+            "length" | "max_tokens" | "limit" => Some("length"),
+            "content_filter" => Some("content filter"),
+            other => Some(other),
+        }
+    }
+
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let tokens = self.completion_tokens?;
+        let secs = self.generation_secs.filter(|secs| *secs > 0.0)?;
+        Some(tokens as f64 / secs)
+    }
+
+    /// One-line summary such as "128 tokens (42 prompt) · 35.2 tok/s".
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+
+        match (self.completion_tokens, self.prompt_tokens) {
+            (Some(completion), Some(prompt)) => parts.push(format!("{} tokens ({} prompt)", completion, prompt)),
+            (Some(completion), None) => parts.push(format!("{} tokens", completion)),
+            (None, Some(prompt)) => parts.push(format!("{} prompt tokens", prompt)),
+            (None, None) => {}
+        }
+        if let Some(rate) = self.tokens_per_second() {
+            parts.push(format!("{:.1} tok/s", rate));
+        }
+        if let Some(note) = self.stop_note() {
+            parts.push(format!("stopped: {}", note));
+        }
+
+        parts.join(" · ")
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+/// OpenAI's `response_format`, e.g. `{"type": "json_object"}`.
+#[derive(Debug, Serialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<ChatMessage>,
     pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
     pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+/// What `chat_stream` sends to the UI while a reply streams in.
+#[derive(Debug)]
+pub enum StreamMessage {
+    /// Reply text, with reasoning wrapped in think tags.
+    Text(String),
+    /// Text of another reply when several were asked for, by its choice index.
+    Alternative(usize, String),
+    /// Part of the response body exactly as it arrived, when `ChatOptions::raw_stream` is set.
+    Raw(String),
+}
+
+/// Sampling parameters applied to every chat request.
+#[derive(Debug, Clone)]
+pub struct ChatOptions {
+    pub temperature: f32,
+    /// Nucleus sampling: only tokens within this cumulative probability, 1.0 to consider all.
+    pub top_p: f32,
+    /// Only sample from this many of the most likely tokens, 0 for no limit.
+    pub top_k: u32,
+    /// Maximum number of tokens to generate, 0 for no limit.
+    pub max_tokens: u32,
+    /// Penalty for tokens that have appeared at all, -2.0..=2.0 with 0.0 as neutral.
+    pub presence_penalty: f32,
+    /// Penalty scaled by how often a token has appeared, -2.0..=2.0 with 0.0 as neutral.
+    pub frequency_penalty: f32,
+    /// Strings that end the generation when produced.
+    pub stop: Vec<String>,
+    /// Earlier messages sent along with the prompt, 0 for the whole conversation.
+    pub max_history_messages: u32,
+    /// Context window Ollama loads the model with, 0 for the server default.
+    pub num_ctx: u32,
+    /// Ask the server to only produce valid JSON. The prompt still has to ask for JSON.
+    pub json_mode: bool,
+    /// Replies to generate for the prompt, where the server supports it.
+    pub completions: u32,
+    /// Extend the assistant reply at the end of the history instead of answering the prompt.
+    pub continue_reply: bool,
+    /// Start of the reply for the model to carry on from, empty for none.
+    pub prefill: String,
+    /// Also send the response body as it arrives, before parsing.
+    pub raw_stream: bool,
+}
+
+impl Default for ChatOptions {
+    fn default() -> Self {
+        Self {
+            temperature: 0.7,
+            top_p: 1.0,
+            top_k: 0,
+            max_tokens: 0,
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            stop: Vec::new(),
+            max_history_messages: 0,
+            num_ctx: 0,
+            json_mode: false,
+            completions: 1,
+            continue_reply: false,
+            prefill: String::new(),
+            raw_stream: false,
+        }
+    }
+}
+
+impl ChatOptions {
+    pub fn max_tokens(&self) -> Option<u32> {
+        (self.max_tokens > 0).then_some(self.max_tokens)
+    }
+
+    /// Left out of requests when neutral, so servers apply their own defaults.
+    pub fn top_p(&self) -> Option<f32> {
+        (self.top_p < 1.0).then_some(self.top_p)
+    }
+
+    pub fn top_k(&self) -> Option<u32> {
+        (self.top_k > 0).then_some(self.top_k)
+    }
+
+    pub fn max_history_messages(&self) -> Option<usize> {
+        (self.max_history_messages > 0).then_some(self.max_history_messages as usize)
+    }
+
+    pub fn num_ctx(&self) -> Option<u32> {
+        (self.num_ctx > 0).then_some(self.num_ctx)
+    }
+
+    /// Continuing only ever extends the one reply being shown.
+    pub fn completions(&self) -> Option<u32> {
+        (self.completions > 1 && !self.continue_reply).then_some(self.completions)
+    }
+
+    /// Penalties are left out of requests at their neutral value so servers use their own defaults.
+    pub fn presence_penalty(&self) -> Option<f32> {
+        (self.presence_penalty != 0.0).then_some(self.presence_penalty)
+    }
+
+    pub fn frequency_penalty(&self) -> Option<f32> {
+        (self.frequency_penalty != 0.0).then_some(self.frequency_penalty)
+    }
+
+    /// llama.cpp's `/completion` takes `n_predict`, with -1 meaning no limit.
+    pub fn n_predict(&self) -> i64 {
+        self.max_tokens().map_or(-1, i64::from)
+    }
+
+    /// Ollama takes sampling parameters in a nested `options` object.
+    pub fn to_ollama_options(&self) -> serde_json::Value {
+        let mut options = serde_json::Map::new();
+        options.insert("temperature".to_string(), serde_json::json!(self.temperature));
+        if let Some(top_p) = self.top_p() {
+            options.insert("top_p".to_string(), serde_json::json!(top_p));
+        }
+        if let Some(top_k) = self.top_k() {
+            options.insert("top_k".to_string(), serde_json::json!(top_k));
+        }
+        if let Some(max_tokens) = self.max_tokens() {
+            options.insert("num_predict".to_string(), serde_json::json!(max_tokens));
+        }
+        // Without it Ollama uses a small default window and silently cuts off long prompts
+        if let Some(num_ctx) = self.num_ctx() {
+            options.insert("num_ctx".to_string(), serde_json::json!(num_ctx));
+        }
+        // Ollama accepts the OpenAI-style penalties alongside its own multiplicative
+        // `repeat_penalty`, which is left at the model's default
+        if let Some(presence_penalty) = self.presence_penalty() {
+            options.insert("presence_penalty".to_string(), serde_json::json!(presence_penalty));
+        }
+        if let Some(frequency_penalty) = self.frequency_penalty() {
+            options.insert("frequency_penalty".to_string(), serde_json::json!(frequency_penalty));
+        }
+        if !self.stop.is_empty() {
+            options.insert("stop".to_string(), serde_json::json!(self.stop));
+        }
+        serde_json::Value::Object(options)
+    }
+
+    /// Ollama's `format` field, only set in JSON mode.
+    pub fn ollama_format(&self) -> Option<&'static str> {
+        self.json_mode.then_some("json")
+    }
+}
+
+/// An image attached to a prompt, for vision-capable models.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInput {
+    pub mime_type: String,
+    pub base64: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DeltaContent {
     pub content: Option<String>,
+    /// Reasoning streamed separately from the answer (DeepSeek, vLLM, LM Studio).
+    #[serde(default)]
+    pub reasoning_content: Option<String>,
+    /// The same under the name OpenRouter and newer vLLM use. Some servers send both.
+    #[serde(default)]
+    pub reasoning: Option<String>,
+}
+
+impl DeltaContent {
+    /// The reasoning under either name, taken once when a server sends both.
+    pub fn reasoning_text(&self) -> Option<&str> {
+        self.reasoning_content.as_deref()
+            .filter(|reasoning| !reasoning.is_empty())
+            .or(self.reasoning.as_deref())
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Choice {
+    #[serde(default)]
+    pub index: usize,
     pub delta: DeltaContent,
     pub finish_reason: Option<String>,
 }
 
+/// The whole reply at once, when streaming is off.
+#[derive(Debug, Deserialize)]
+pub struct CompleteChoice {
+    #[serde(default)]
+    pub index: usize,
+    pub message: DeltaContent,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteChatResponse {
+    #[serde(default)]
+    pub choices: Vec<CompleteChoice>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ChatResponse {
+    #[serde(default)]
     pub choices: Vec<Choice>,
+    // Only present on the final chunk when usage reporting is requested
+    pub usage: Option<Usage>,
+}
+
+/// One streamed chunk from llama.cpp's native `/completion` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct LlamaCppChunk {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub stop: bool,
+    // Only present on the final chunk
+    pub timings: Option<LlamaCppTimings>,
+    /// "eos", "limit" or "word" on the final chunk.
+    pub stop_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LlamaCppTimings {
+    pub prompt_n: Option<u32>,
+    pub predicted_n: Option<u32>,
+    pub predicted_ms: Option<f64>,
+}
+
+/// One server-sent event from Anthropic's streaming Messages API.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicEvent {
+    MessageStart { message: AnthropicMessage },
+    ContentBlockDelta { delta: AnthropicDelta },
+    MessageDelta {
+        #[serde(default)]
+        delta: AnthropicMessageDelta,
+        usage: AnthropicUsage,
+    },
+    MessageStop,
+    Error { error: AnthropicError },
+    // Block starts and stops, pings
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicMessage {
+    pub usage: AnthropicUsage,
+}
+
+/// A whole reply from Anthropic's Messages API, when streaming is off.
+#[derive(Debug, Deserialize)]
+pub struct AnthropicResponse {
+    #[serde(default)]
+    pub content: Vec<AnthropicBlock>,
+    pub stop_reason: Option<String>,
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicBlock {
+    Text { text: String },
+    Thinking { thinking: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicDelta {
+    TextDelta { text: String },
+    ThinkingDelta { thinking: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AnthropicMessageDelta {
+    pub stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicUsage {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicError {
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,152 +677,960 @@ pub struct OllamaModelsResponse {
     pub models: Vec<ModelDetails>,
 }
 
-#[derive(Clone)]
-pub struct LLMClient {
-    client: Client,
-    protocol: String,
-    server: String,
-    port: String,
-    endpoint: String,
-    endpoint_type: EndpointType,
-}
+#[derive(Debug, Deserialize)]
+pub struct OllamaShowResponse {
+    #[serde(default)]
+    pub parameters: Option<String>,
+    #[serde(default)]
+    pub model_info: serde_json::Map<String, serde_json::Value>,
+}
+
+/// What Ollama's `/api/show` reports about a model, for the model info window.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OllamaModelCard {
+    #[serde(default)]
+    pub parameters: String,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default)]
+    pub modelfile: String,
+    /// Format, family, parameter size and quantization level.
+    #[serde(default)]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+/// What probing a server found: the API to use, and any others it answers as well.
+#[derive(Debug, Clone)]
+pub struct EndpointDetection {
+    pub endpoint_type: EndpointType,
+    pub also: Vec<EndpointType>,
+}
+
+impl EndpointDetection {
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Detected {}", self.endpoint_type.label());
+        if !self.also.is_empty() {
+            let also: Vec<&str> = self.also.iter().map(|endpoint_type| endpoint_type.label()).collect();
+            summary.push_str(&format!(" (also answers as {})", also.join(", ")));
+        }
+        summary
+    }
+}
+
+/// One line of the progress Ollama streams while pulling a model.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    /// Bytes of the current layer downloaded so far, and its size.
+    pub completed: Option<u64>,
+    pub total: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl PullProgress {
+    /// How far the current layer has downloaded, if it reports sizes.
+    pub fn fraction(&self) -> Option<f32> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => Some((completed as f64 / total as f64) as f32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LMStudioModelInfo {
+    // Hosted OpenAI-compatible providers that report it use one of the other names
+    #[serde(alias = "context_length", alias = "context_window")]
+    pub max_context_length: Option<u32>,
+}
+
+/// What the server reports about a model; fields are None when it doesn't say.
+#[derive(Debug, Clone, Default)]
+pub struct ModelInfo {
+    /// Context window in tokens.
+    pub context_length: Option<u32>,
+}
+
+/// Connection options shared by every request an `LLMClient` makes.
+#[derive(Debug, Clone)]
+pub struct ClientSettings {
+    pub api_key: Option<String>,
+    /// Seconds to wait while connecting, 0 to wait indefinitely.
+    pub connect_timeout: u64,
+    /// Seconds allowed for a whole request including streaming, 0 for no limit.
+    pub request_timeout: u64,
+    /// Attempts made when the server can't be reached, including the first one.
+    pub retry_attempts: u32,
+    /// Send Ollama prompts to `/api/generate` as raw text instead of `/api/chat`.
+    pub ollama_generate: bool,
+    /// Extra headers sent with every request, e.g. for gateways and proxies.
+    pub custom_headers: HeaderMap,
+    /// Skip TLS certificate checks, for self-hosted servers with self-signed certificates.
+    pub accept_invalid_certs: bool,
+    /// Ask for the whole reply in one response, for servers and proxies that mangle streams.
+    pub disable_streaming: bool,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            connect_timeout: 5,
+            request_timeout: 300,
+            retry_attempts: 3,
+            ollama_generate: false,
+            custom_headers: HeaderMap::new(),
+            accept_invalid_certs: false,
+            disable_streaming: false,
+        }
+    }
+}
+
+/// Check that a custom header can be sent as typed.
+pub fn validate_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("Invalid header name: {:?}", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("Invalid value for header {}", name))?;
+    Ok((name, value))
+}
+
+/// The custom headers that are valid, skipping rows without a name.
+pub fn header_map(headers: &[(String, String)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers.iter().filter(|(name, _)| !name.trim().is_empty()) {
+        match validate_header(name, value) {
+            Ok((name, value)) => {
+                map.append(name, value);
+            }
+            Err(e) => log::warn!("Skipping custom header: {}", e),
+        }
+    }
+    map
+}
+
+/// Take the first complete line out of `buffer`, without its line ending. A line that hasn't
+/// fully arrived yet stays in the buffer until the chunk with its end is added.
+fn next_line(buffer: &mut String) -> Option<String> {
+    let end = buffer.find('\n')?;
+    let line: String = buffer.drain(..=end).collect();
+    Some(line.trim_end_matches(['\n', '\r']).to_string())
+}
+
+#[derive(Clone)]
+pub struct LLMClient {
+    client: Client,
+    protocol: String,
+    server: String,
+    port: String,
+    endpoint: String,
+    endpoint_type: EndpointType,
+    api_key: Option<String>,
+    retry_attempts: u32,
+    ollama_generate: bool,
+    disable_streaming: bool,
+    custom_headers: HeaderMap,
+    // Shared between clones so the UI can show retries made on background threads
+    retry_status: Arc<Mutex<Option<String>>>,
+}
+
+impl LLMClient {
+    pub fn new(protocol: String, server: String, port: String, endpoint: String, endpoint_type: EndpointType, settings: ClientSettings) -> Self {
+        let mut builder = Client::builder();
+        if settings.connect_timeout > 0 {
+            builder = builder.connect_timeout(Duration::from_secs(settings.connect_timeout));
+        }
+        if settings.request_timeout > 0 {
+            builder = builder.timeout(Duration::from_secs(settings.request_timeout));
+        }
+        if settings.accept_invalid_certs {
+            log::warn!("TLS certificate verification is disabled for {}", server);
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        let client = builder
+            .build()
+            .unwrap_or_else(|_| Client::new());
+            
+        Self {
+            client,
+            protocol,
+            server,
+            port,
+            endpoint,
+            endpoint_type,
+            api_key: settings.api_key,
+            retry_attempts: settings.retry_attempts.max(1),
+            ollama_generate: settings.ollama_generate,
+            disable_streaming: settings.disable_streaming,
+            custom_headers: settings.custom_headers,
+            retry_status: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Describes the connection retry in progress, if any.
+    pub fn retry_status(&self) -> Option<String> {
+        self.retry_status.lock().ok()?.clone()
+    }
+
+    fn set_retry_status(&self, status: Option<String>) {
+        if let Ok(mut current) = self.retry_status.lock() {
+            *current = status;
+        }
+    }
+
+    /// Send a request, retrying with exponential backoff while the server can't be reached.
+    /// Only failures to connect or to get a response are retried, never HTTP error statuses.
+    async fn send_with_retry(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let mut attempt = 1;
+
+        loop {
+            // Bodies that can't be cloned (streams) can only be sent once
+            let Some(this_attempt) = request.try_clone() else {
+                return request.send().await;
+            };
+
+            match this_attempt.send().await {
+                Err(e) if (e.is_connect() || e.is_timeout()) && attempt < self.retry_attempts => {
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+                    log::warn!("Request failed ({}), retrying in {:?}", e, delay);
+                    self.set_retry_status(Some(format!(
+                        "Server not reachable, retrying ({}/{})...",
+                        attempt + 1,
+                        self.retry_attempts
+                    )));
+
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => {
+                    self.set_retry_status(None);
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Headers carrying the API key, if any, as a bearer token or in Anthropic's own headers.
+    fn auth_headers(&self, redact_key: bool) -> Vec<(&'static str, String)> {
+        let key = self.api_key.as_ref()
+            .map(|key| if redact_key { "<redacted>".to_string() } else { key.clone() });
+
+        let mut headers = Vec::new();
+        if self.endpoint_type == EndpointType::Anthropic {
+            headers.push(("anthropic-version", ANTHROPIC_VERSION.to_string()));
+            if let Some(key) = key {
+                headers.push(("x-api-key", key));
+            }
+        } else if let Some(key) = key {
+            headers.push(("Authorization", format!("Bearer {}", key)));
+        }
+        headers
+    }
+
+    /// Add the auth headers and then the custom ones, which replace auth headers of the same name.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        self.auth_headers(false).into_iter()
+            .fold(request, |request, (name, value)| request.header(name, value))
+            .headers(self.custom_headers.clone())
+    }
+
+    /// Find out which API the server speaks by probing each kind's own path. Servers with an
+    /// OpenAI-compatible surface next to their native API are set up for the native one.
+    pub async fn detect_endpoint_type(&self) -> Result<EndpointDetection> {
+        let base_url = format!("{}://{}:{}", self.protocol, self.server, self.port);
+        // Anthropic rejects requests without its own headers, but can be told by its host
+        if self.server.ends_with("anthropic.com") {
+            return Ok(EndpointDetection { endpoint_type: EndpointType::Anthropic, also: Vec::new() });
+        }
+
+        let mut found = Vec::new();
+        for endpoint_type in [EndpointType::Ollama, EndpointType::LMStudio, EndpointType::LlamaCpp, EndpointType::OpenAI] {
+            let Some((path, key)) = endpoint_type.probe_endpoint(&self.endpoint) else {
+                continue;
+            };
+            if self.probe(&format!("{}/{}", base_url, path), key).await {
+                found.push(endpoint_type);
+            }
+        }
+
+        let mut found = found.into_iter();
+        let endpoint_type = found.next().ok_or_else(|| anyhow::anyhow!(
+            "{} didn't answer like Ollama, LM Studio, llama.cpp or an OpenAI-compatible server", base_url
+        ))?;
+        Ok(EndpointDetection { endpoint_type, also: found.collect() })
+    }
+
+    /// Whether `url` answers a GET with a JSON object holding `key`.
+    async fn probe(&self, url: &str, key: &str) -> bool {
+        log::info!("Probing {}", url);
+        let response = match self.authorize(self.client.get(url)).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                log::debug!("{} answered {}", url, response.status());
+                return false;
+            }
+            Err(e) => {
+                log::debug!("{} didn't answer: {}", url, e);
+                return false;
+            }
+        };
+        response.json::<serde_json::Value>().await
+            .is_ok_and(|body| body.get(key).is_some())
+    }
+
+    /// Whether the server answers at all. Sent once with a short timeout and never retried,
+    /// so background health checks don't show up as connection retries.
+    pub async fn ping(&self) -> bool {
+        let models_url = format!("{}://{}:{}/{}",
+            self.protocol,
+            self.server,
+            self.port,
+            self.endpoint_type.models_endpoint(&self.endpoint)
+        ).trim_end_matches('/').to_string();
+
+        match self.authorize(self.client.get(&models_url)).timeout(PING_TIMEOUT).send().await {
+            Ok(response) => response.status().is_success(),
+            Err(e) => {
+                log::debug!("Health check of {} failed: {}", models_url, e);
+                false
+            }
+        }
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let models_url = format!("{}://{}:{}/{}",
+            self.protocol,
+            self.server,
+            self.port,
+            self.endpoint_type.models_endpoint(&self.endpoint)
+        ).trim_end_matches('/').to_string();
+        
+        log::info!("Fetching models from: {}", models_url);
+        
+        let response = self.send_with_retry(self.authorize(self.client.get(&models_url)))
+            .await
+            .context(format!("Failed to fetch models from {}. Please check if the server is running and accessible", &models_url))?;
+            
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Server returned error {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_else(|_| "No error message".to_string())
+            ));
+        }
+            
+        match self.endpoint_type {
+            EndpointType::LMStudio | EndpointType::OpenAI | EndpointType::LlamaCpp | EndpointType::Anthropic => {
+                let models: ModelsResponse = response
+                    .json()
+                    .await
+                    .context("Failed to parse models response")?;
+                    
+                Ok(models.data.into_iter().map(|m| m.id).collect())
+            }
+            EndpointType::Ollama => {
+                // First print the raw response for debugging
+                let text = response.text().await?;
+                log::debug!("Raw Ollama response: {}", text);
+                
+                // Parse the response from the text
+                let models: OllamaModelsResponse = serde_json::from_str(&text)
+                    .context("Failed to parse Ollama response")?;
+                    
+                Ok(models.models.into_iter().map(|m| m.name).collect())
+            }
+        }
+    }
+
+    /// Have Ollama download `model`, sending its progress to `tx` until it finishes.
+    pub async fn pull_model(&self, model: &str, tx: Sender<PullProgress>, cancel: Arc<AtomicBool>) -> Result<()> {
+        let Some(endpoint) = self.endpoint_type.pull_endpoint(&self.endpoint) else {
+            return Err(anyhow::anyhow!("Only Ollama servers can pull models"));
+        };
+        let pull_url = format!("{}://{}:{}/{}", self.protocol, self.server, self.port, endpoint);
+        log::info!("Pulling {} from {}", model, pull_url);
+
+        let request = self.client.post(&pull_url)
+            .timeout(PULL_TIMEOUT)
+            .json(&serde_json::json!({ "model": model, "stream": true }));
+        let response = self.authorize(request)
+            .send()
+            .await
+            .context(format!("Failed to send pull request to {}", &pull_url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to pull {} ({}): {}", model, status, ollama_error_message(text)));
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut pending_bytes = Vec::new();
+        let mut line = String::new();
+        let mut finished = false;
+
+        while let Some(chunk) = stream.next().await {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(anyhow::anyhow!("Pull of {} cancelled", model));
+            }
+
+            let chunk = chunk.map_err(|e| anyhow::anyhow!("Error reading pull progress: {}", e))?;
+            line.push_str(&decode_utf8_chunk(&mut pending_bytes, &chunk));
+
+            // Progress lines can be split across chunks, so only complete ones are parsed
+            while let Some(end) = line.find('\n') {
+                let json_str: String = line.drain(..=end).collect();
+                if json_str.trim().is_empty() {
+                    continue;
+                }
+
+                let progress: PullProgress = match serde_json::from_str(json_str.trim()) {
+                    Ok(progress) => progress,
+                    Err(e) => {
+                        log::warn!("Failed to parse pull progress: {}. Raw JSON: {}", e, json_str);
+                        continue;
+                    }
+                };
+                if let Some(error) = progress.error {
+                    return Err(anyhow::anyhow!("Failed to pull {}: {}", model, error));
+                }
+                finished |= progress.status == "success";
+                // The receiver is gone when the app stopped listening
+                if tx.send(progress).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+
+        if finished {
+            log::info!("Pulled {}", model);
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Pull of {} ended before it finished", model))
+        }
+    }
+
+    /// Remove `model` from the Ollama server.
+    pub async fn delete_model(&self, model: &str) -> Result<()> {
+        let Some(endpoint) = self.endpoint_type.delete_endpoint(&self.endpoint) else {
+            return Err(anyhow::anyhow!("Only Ollama servers can delete models"));
+        };
+        let delete_url = format!("{}://{}:{}/{}", self.protocol, self.server, self.port, endpoint);
+        log::info!("Deleting {} at {}", model, delete_url);
+
+        let request = self.client.delete(&delete_url)
+            .json(&serde_json::json!({ "model": model }));
+        let response = self.authorize(request)
+            .send()
+            .await
+            .context(format!("Failed to send delete request to {}", &delete_url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to delete {} ({}): {}", model, status, ollama_error_message(text)));
+        }
+        Ok(())
+    }
+
+    /// Ollama's parameters, prompt template and Modelfile for `model`.
+    pub async fn show_model(&self, model: &str) -> Result<OllamaModelCard> {
+        if self.endpoint_type != EndpointType::Ollama {
+            return Err(anyhow::anyhow!("Only Ollama servers describe their models"));
+        }
+        let show_url = format!("{}://{}:{}/{}",
+            self.protocol,
+            self.server,
+            self.port,
+            self.endpoint_type.model_info_endpoint(&self.endpoint, model)
+        );
+
+        let request = self.client.post(&show_url)
+            .json(&serde_json::json!({ "model": model }));
+        let response = self.authorize(request)
+            .send()
+            .await
+            .context(format!("Failed to fetch model info from {}", &show_url))?;
 
-impl LLMClient {
-    pub fn new(protocol: String, server: String, port: String, endpoint: String, endpoint_type: EndpointType) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(5))  // 5 second timeout
-            .build()
-            .unwrap_or_else(|_| Client::new());
-            
-        Self {
-            client,
-            protocol,
-            server,
-            port,
-            endpoint,
-            endpoint_type,
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to fetch info for {} ({}): {}", model, status, ollama_error_message(text)));
         }
+
+        response.json().await.context("Failed to parse Ollama model info")
     }
 
-    pub async fn list_models(&self) -> Result<Vec<String>> {
-        let models_url = format!("{}://{}:{}/{}",
+    pub async fn get_model_info(&self, model: &str) -> Result<ModelInfo> {
+        let info_url = format!("{}://{}:{}/{}",
             self.protocol,
             self.server,
             self.port,
-            self.endpoint_type.models_endpoint(&self.endpoint)
+            self.endpoint_type.model_info_endpoint(&self.endpoint, model)
         ).trim_end_matches('/').to_string();
-        
-        println!("Fetching models from: {}", models_url);
-        
-        let response = self.client
-            .get(&models_url)
+
+        log::info!("Fetching model info from: {}", info_url);
+
+        let request = match self.endpoint_type {
+            EndpointType::LMStudio | EndpointType::OpenAI | EndpointType::LlamaCpp | EndpointType::Anthropic => {
+                self.client.get(&info_url)
+            }
+            EndpointType::Ollama => self.client
+                .post(&info_url)
+                .json(&serde_json::json!({ "model": model })),
+        };
+
+        let response = self.authorize(request)
             .send()
             .await
-            .context(format!("Failed to fetch models from {}. Please check if the server is running and accessible", &models_url))?;
-            
+            .context(format!("Failed to fetch model info from {}", &info_url))?;
+
         if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Server returned error {}: {}",
-                response.status(),
-                response.text().await.unwrap_or_else(|_| "No error message".to_string())
-            ));
+            return Err(anyhow::anyhow!("Server returned error {}", response.status()));
         }
-            
-        match self.endpoint_type {
-            EndpointType::LMStudio => {
-                let models: ModelsResponse = response
+
+        let context_length = match self.endpoint_type {
+            EndpointType::LMStudio | EndpointType::OpenAI | EndpointType::Anthropic => {
+                // OpenAI's and Anthropic's model objects have no context length, so this yields None there
+                let info: LMStudioModelInfo = response
                     .json()
                     .await
-                    .context("Failed to parse models response")?;
-                    
-                Ok(models.data.into_iter().map(|m| m.id).collect())
+                    .context("Failed to parse LM Studio model info")?;
+
+                info.max_context_length
+            }
+            EndpointType::LlamaCpp => {
+                let props: serde_json::Value = response
+                    .json()
+                    .await
+                    .context("Failed to parse llama.cpp server properties")?;
+
+                // n_ctx is the context the server was started with (--ctx-size)
+                props["default_generation_settings"]["n_ctx"]
+                    .as_u64()
+                    .map(|value| value as u32)
             }
             EndpointType::Ollama => {
-                // First print the raw response for debugging
-                let text = response.text().await?;
-                println!("Raw Ollama response: {}", text);
-                
-                // Parse the response from the text
-                let models: OllamaModelsResponse = serde_json::from_str(&text)
-                    .context("Failed to parse Ollama response")?;
-                    
-                Ok(models.models.into_iter().map(|m| m.name).collect())
+                let info: OllamaShowResponse = response
+                    .json()
+                    .await
+                    .context("Failed to parse Ollama model info")?;
+
+                // A num_ctx in the Modelfile is what the server actually runs with,
+                // so it takes precedence over the model's trained context length
+                let num_ctx = info.parameters.as_deref().and_then(|params| {
+                    params.lines()
+                        .filter_map(|line| line.trim().strip_prefix("num_ctx"))
+                        .find_map(|value| value.trim().parse::<u32>().ok())
+                });
+
+                let trained = info.model_info.iter()
+                    .find(|(key, _)| key.ends_with(".context_length"))
+                    .and_then(|(_, value)| value.as_u64())
+                    .map(|value| value as u32);
+
+                num_ctx.or(trained)
+            }
+        };
+
+        Ok(ModelInfo { context_length })
+    }
+
+    /// Attach images to the last (current user) message of a chat request body.
+    fn attach_images(&self, request_body: &mut serde_json::Value, images: &[ImageInput]) {
+        if images.is_empty() {
+            return;
+        }
+
+        // Completion-style bodies carry a single prompt instead of messages
+        if request_body.get("prompt").is_some() {
+            // Ollama's generate endpoint takes images next to the prompt, llama.cpp's has no place for them
+            if self.endpoint_type == EndpointType::Ollama {
+                let encoded: Vec<&str> = images.iter().map(|image| image.base64.as_str()).collect();
+                request_body["images"] = serde_json::json!(encoded);
+            }
+            return;
+        }
+
+        let Some(message) = request_body["messages"].as_array_mut().and_then(|m| m.last_mut()) else {
+            return;
+        };
+
+        match self.endpoint_type {
+            EndpointType::Ollama => {
+                // Ollama takes raw base64 strings next to the text content
+                let encoded: Vec<&str> = images.iter().map(|image| image.base64.as_str()).collect();
+                message["images"] = serde_json::json!(encoded);
+            }
+            EndpointType::LMStudio | EndpointType::OpenAI => {
+                // OpenAI-style APIs want the content split into typed parts
+                let text = message["content"].take();
+                let mut parts = vec![serde_json::json!({ "type": "text", "text": text })];
+                parts.extend(images.iter().map(|image| serde_json::json!({
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:{};base64,{}", image.mime_type, image.base64) }
+                })));
+                message["content"] = serde_json::Value::Array(parts);
+            }
+            EndpointType::Anthropic => {
+                // Anthropic wants typed parts too, with the image as a base64 source
+                let text = message["content"].take();
+                let mut parts: Vec<serde_json::Value> = images.iter().map(|image| serde_json::json!({
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": image.mime_type, "data": image.base64 }
+                })).collect();
+                parts.push(serde_json::json!({ "type": "text", "text": text }));
+                message["content"] = serde_json::Value::Array(parts);
+            }
+            // The plain completion prompt has no messages to attach images to
+            EndpointType::LlamaCpp => {}
+        }
+    }
+
+    /// Flatten chat messages into a plain transcript for completion-style endpoints,
+    /// ending with an open assistant turn for the model to complete.
+    /// With `open_reply` the prompt ends inside the last assistant message, so the model carries on from there.
+    fn format_completion_prompt(messages: &[serde_json::Value], open_reply: bool) -> String {
+        let mut prompt = String::new();
+
+        for (i, message) in messages.iter().enumerate() {
+            let speaker = match message["role"].as_str() {
+                Some("user") => "User",
+                Some("assistant") => "Assistant",
+                Some("system") => "System",
+                _ => continue,
+            };
+            let content = message["content"].as_str().unwrap_or_default();
+            if open_reply && i == messages.len() - 1 {
+                prompt.push_str(&format!("{}: {}", speaker, content));
+                return prompt;
             }
+            prompt.push_str(&format!("{}: {}\n", speaker, content));
+        }
+
+        prompt.push_str("Assistant:");
+        prompt
+    }
+
+    /// The entries of `history` sent along with a new prompt: system messages, then the last
+    /// `max_history` turns. The context gauge counts the same ones.
+    pub fn sent_history(history: &[ChatEntry], max_history: Option<usize>) -> Vec<&ChatEntry> {
+        // Errors are only shown locally; "error" isn't a role any backend accepts
+        let (system, turns): (Vec<&ChatEntry>, Vec<&ChatEntry>) = history.iter()
+            .filter(|entry| entry.role != "error")
+            .partition(|entry| entry.role == "system");
+
+        let mut skip = max_history.map_or(0, |max| turns.len().saturating_sub(max));
+        // Some backends reject a conversation that starts with the assistant
+        while skip > 0 && turns.get(skip).is_some_and(|entry| entry.role == "assistant") {
+            skip += 1;
+        }
+
+        system.into_iter().chain(turns.into_iter().skip(skip)).collect()
+    }
+
+    /// Convert the chat history to the messages format, ending with the current prompt, or
+    /// with the last entry of the history when there is no prompt because a reply is continued.
+    /// Only the last `max_history` earlier messages are kept, plus any system messages.
+    fn history_messages(chat_history: &[ChatEntry], prompt: Option<&str>, max_history: Option<usize>) -> Vec<serde_json::Value> {
+        // The last entry is the current prompt, added separately below
+        let earlier = match prompt {
+            Some(_) => &chat_history[..chat_history.len().saturating_sub(1)],
+            None => chat_history,
+        };
+
+        let mut messages: Vec<serde_json::Value> = Self::sent_history(earlier, max_history).into_iter()
+            .map(|entry| serde_json::json!({
+                "role": entry.role,
+                "content": entry.content
+            }))
+            .collect();
+        if let Some(prompt) = prompt {
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": prompt
+            }));
         }
+        messages
+    }
+
+    fn continue_message() -> serde_json::Value {
+        serde_json::json!({
+            "role": "user",
+            "content": CONTINUE_PROMPT
+        })
+    }
+
+    /// Typed messages for `ChatRequest`, failing on any without a text role and content.
+    fn chat_messages(messages: &[serde_json::Value]) -> Result<Vec<ChatMessage>> {
+        messages.iter()
+            .map(|m| match (m["role"].as_str(), m["content"].as_str()) {
+                (Some(role), Some(content)) => Ok(ChatMessage {
+                    role: role.to_string(),
+                    content: content.to_string(),
+                }),
+                _ => Err(anyhow::anyhow!("Message without a text role and content: {}", m)),
+            })
+            .collect()
     }
 
-    pub async fn chat_stream(&self, chat_history: &[(String, String)], prompt: &str, model: &str, tx: SyncSender<String>) -> Result<()> {
+    /// Ollama's generate mode skips the chat template and takes a single raw prompt.
+    fn uses_ollama_generate(&self) -> bool {
+        self.endpoint_type == EndpointType::Ollama && self.ollama_generate
+    }
+
+    /// The URL and JSON body of a chat request for `prompt`.
+    fn build_chat_request(&self, chat_history: &[ChatEntry], prompt: &str, images: &[ImageInput], model: &str, options: &ChatOptions) -> Result<(String, serde_json::Value)> {
+        let ollama_generate = self.uses_ollama_generate();
+        let stream = !self.disable_streaming;
+        let chat_endpoint = if ollama_generate {
+            self.endpoint_type.generate_endpoint(&self.endpoint)
+        } else {
+            self.endpoint_type.chat_endpoint(&self.endpoint)
+        };
         let chat_url = format!("{}://{}:{}/{}",
             self.protocol,
             self.server,
             self.port,
-            self.endpoint_type.chat_endpoint(&self.endpoint)
+            chat_endpoint
         ).trim_end_matches('/').to_string();
         
-        // Convert chat history to messages format
-        let mut messages = Vec::new();
-        // Add all messages except the last one (which is the current prompt)
-        for (role, content) in chat_history.iter().take(chat_history.len().saturating_sub(1)) {
+        let continue_reply = options.continue_reply
+            && chat_history.last().is_some_and(|entry| entry.role == "assistant");
+        let mut messages = Self::history_messages(chat_history, (!continue_reply).then_some(prompt), options.max_history_messages());
+        let prefilled = !continue_reply && !options.prefill.is_empty();
+        if continue_reply {
+            match self.endpoint_type {
+                // Chat templates close the assistant turn, so these are asked to go on in a new one
+                EndpointType::LMStudio | EndpointType::OpenAI => messages.push(Self::continue_message()),
+                EndpointType::Ollama if !ollama_generate => messages.push(Self::continue_message()),
+                // Anthropic and completion prompts carry on from the trailing assistant message
+                EndpointType::Anthropic | EndpointType::Ollama | EndpointType::LlamaCpp => {}
+            }
+        } else if prefilled {
+            // Ollama, Anthropic and most OpenAI-compatible servers continue a trailing assistant
+            // message; OpenAI itself answers after it instead
             messages.push(serde_json::json!({
-                "role": role,
-                "content": content
+                "role": "assistant",
+                "content": options.prefill
             }));
         }
-        // Add current prompt
-        messages.push(serde_json::json!({
-            "role": "user",
-            "content": prompt
-        }));
+        // Completion prompts end inside this reply rather than opening a new one
+        let open_reply = continue_reply || prefilled;
+        // Anthropic rejects a trailing assistant message that ends in whitespace
+        if open_reply && self.endpoint_type == EndpointType::Anthropic {
+            if let Some(last) = messages.last_mut() {
+                let partial = last["content"].as_str().unwrap_or_default().trim_end().to_string();
+                last["content"] = serde_json::json!(partial);
+            }
+        }
 
         // Different request format for different endpoints
-        let request_body = match self.endpoint_type {
-            EndpointType::LMStudio => {
+        let mut request_body = match self.endpoint_type {
+            EndpointType::LMStudio | EndpointType::OpenAI => {
                 let request = ChatRequest {
                     model: model.to_string(),
-                    messages: messages.iter().map(|m| ChatMessage {
-                        role: m["role"].as_str().unwrap().to_string(),
-                        content: m["content"].as_str().unwrap().to_string(),
-                    }).collect(),
-                    temperature: 0.7,
-                    stream: true,
+                    messages: Self::chat_messages(&messages)?,
+                    temperature: options.temperature,
+                    top_p: options.top_p(),
+                    max_tokens: options.max_tokens(),
+                    presence_penalty: options.presence_penalty(),
+                    frequency_penalty: options.frequency_penalty(),
+                    stop: options.stop.clone(),
+                    response_format: options.json_mode.then(|| ResponseFormat { kind: "json_object".to_string() }),
+                    n: options.completions(),
+                    stream,
+                    // Ask for a final chunk with token counts
+                    stream_options: stream.then_some(StreamOptions { include_usage: true }),
                 };
-                serde_json::to_value(request).unwrap()
+                serde_json::to_value(request).context("Failed to serialize chat request")?
+            }
+            EndpointType::Ollama if ollama_generate => {
+                // Keep the model from writing the user's next turn itself
+                let mut ollama_options = options.to_ollama_options();
+                let mut stop = options.stop.clone();
+                stop.push("\nUser:".to_string());
+                ollama_options["stop"] = serde_json::json!(stop);
+
+                let mut body = serde_json::json!({
+                    "model": model,
+                    "prompt": Self::format_completion_prompt(&messages, open_reply),
+                    "raw": true,
+                    "options": ollama_options,
+                    "stream": stream
+                });
+                if let Some(format) = options.ollama_format() {
+                    body["format"] = serde_json::json!(format);
+                }
+                body
             }
             EndpointType::Ollama => {
-                serde_json::json!({
+                let mut body = serde_json::json!({
+                    "model": model,
+                    "messages": messages,
+                    "options": options.to_ollama_options(),
+                    "stream": stream
+                });
+                if let Some(format) = options.ollama_format() {
+                    body["format"] = serde_json::json!(format);
+                }
+                body
+            }
+            EndpointType::Anthropic => {
+                // The system prompt is a top-level field rather than a message
+                let (system, messages): (Vec<_>, Vec<_>) = messages.into_iter()
+                    .partition(|message| message["role"] == "system");
+                let system: Vec<&str> = system.iter()
+                    .filter_map(|message| message["content"].as_str())
+                    .collect();
+
+                let mut body = serde_json::json!({
                     "model": model,
                     "messages": messages,
-                    "stream": true
-                })
+                    "max_tokens": options.max_tokens().unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+                    // Anthropic only accepts 0.0..=1.0
+                    "temperature": options.temperature.min(1.0),
+                    "stream": stream
+                });
+                if !system.is_empty() {
+                    body["system"] = serde_json::json!(system.join("\n\n"));
+                }
+                if !options.stop.is_empty() {
+                    body["stop_sequences"] = serde_json::json!(options.stop);
+                }
+                if let Some(top_p) = options.top_p() {
+                    body["top_p"] = serde_json::json!(top_p);
+                }
+                if let Some(top_k) = options.top_k() {
+                    body["top_k"] = serde_json::json!(top_k);
+                }
+                body
+            }
+            EndpointType::LlamaCpp => {
+                // Keep the model from writing the user's next turn itself
+                let mut stop = options.stop.clone();
+                stop.push("\nUser:".to_string());
+
+                // llama.cpp treats 0.0 as disabled, so the penalties can always be sent
+                let mut body = serde_json::json!({
+                    "prompt": Self::format_completion_prompt(&messages, open_reply),
+                    "temperature": options.temperature,
+                    "n_predict": options.n_predict(),
+                    "presence_penalty": options.presence_penalty,
+                    "frequency_penalty": options.frequency_penalty,
+                    "stop": stop,
+                    "stream": stream,
+                    "cache_prompt": true
+                });
+                // Unlike the penalties, 1.0 and 0 would override llama.cpp's own defaults
+                if let Some(top_p) = options.top_p() {
+                    body["top_p"] = serde_json::json!(top_p);
+                }
+                if let Some(top_k) = options.top_k() {
+                    body["top_k"] = serde_json::json!(top_k);
+                }
+                // Constrains sampling to a JSON object through a schema-derived grammar
+                if options.json_mode {
+                    body["json_schema"] = serde_json::json!({ "type": "object" });
+                }
+                body
             }
         };
+        self.attach_images(&mut request_body, images);
 
-        let response = self.client
-            .post(&chat_url)
-            .json(&request_body)
-            .timeout(Duration::from_secs(300))  // 5 minute timeout for the entire stream
-            .send()
+        Ok((chat_url, request_body))
+    }
+
+    /// A `curl` command sending the same chat request, with the API key and custom header values left out.
+    pub fn curl_command(&self, chat_history: &[ChatEntry], prompt: &str, images: &[ImageInput], model: &str, options: &ChatOptions) -> Result<String> {
+        let (chat_url, request_body) = self.build_chat_request(chat_history, prompt, images, model, options)?;
+        // Custom headers may carry credentials too, so their values are left out like the key
+        let mut headers: Vec<(&str, String)> = self.auth_headers(true).into_iter()
+            .filter(|(name, _)| !self.custom_headers.contains_key(*name))
+            .collect();
+        headers.extend(self.custom_headers.keys().map(|name| (name.as_str(), "<redacted>".to_string())));
+        Ok(curl::curl_command(&chat_url, &headers, &request_body))
+    }
+
+    /// Send a single prompt without any history and return the whole reply, reasoning included.
+    pub async fn complete(&self, prompt: &str, model: &str, options: &ChatOptions) -> Result<String> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.chat_stream(&[], prompt, &[], model, options, tx, Arc::new(AtomicBool::new(false))).await?;
+        Ok(rx.try_iter()
+            .filter_map(|message| match message {
+                StreamMessage::Text(text) => Some(text),
+                StreamMessage::Alternative(..) | StreamMessage::Raw(_) => None,
+            })
+            .collect())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn chat_stream(&self, chat_history: &[ChatEntry], prompt: &str, images: &[ImageInput], model: &str, options: &ChatOptions, tx: Sender<StreamMessage>, cancel: Arc<AtomicBool>) -> Result<GenerationStats> {
+        let ollama_generate = self.uses_ollama_generate();
+        let (chat_url, request_body) = self.build_chat_request(chat_history, prompt, images, model, options)?;
+        log::info!("Sending chat request to {}", chat_url);
+        log::debug!("Chat request body: {}", request_body);
+
+        let sent_at = Instant::now();
+        // Once streaming has started nothing is retried, that would duplicate tokens
+        let response = self.send_with_retry(self.authorize(self.client.post(&chat_url)).json(&request_body))
             .await
             .map_err(|e| anyhow::anyhow!("Failed to send request: {}", e))?;
 
         let status = response.status();
         if !status.is_success() {
             let error_text = response.text().await.unwrap_or_default();
+            log::error!("Chat request failed with status {}: {}", status, error_text);
+            if options.raw_stream {
+                let _ = tx.send(StreamMessage::Raw(error_text.clone()));
+            }
             return Err(anyhow::anyhow!("Request failed with status {}: {}", status, error_text));
         }
 
+        if self.disable_streaming {
+            return self.complete_reply(response, options, &tx, &cancel, sent_at).await;
+        }
+
         let mut stream = response.bytes_stream();
         let mut buffer = String::new();
+        let mut stats = GenerationStats::default();
+        let mut first_token_at: Option<Instant> = None;
+        let mut in_reasoning = false;
+        // Whether each further choice is inside reasoning, by choice index - 1
+        let mut alternatives_reasoning: Vec<bool> = Vec::new();
+        let mut pending_bytes = Vec::new();
+        // Events can be split across chunks, so only complete lines are parsed
+        let mut lines = String::new();
         
         while let Some(chunk) = stream.next().await {
+            // Stop reading as soon as the user cancels the request
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(stats);
+            }
+
             let chunk = chunk.map_err(|e| anyhow::anyhow!("Error reading stream: {}", e))?;
-            let text = String::from_utf8_lossy(&chunk);
+            let text = decode_utf8_chunk(&mut pending_bytes, &chunk);
+            log::trace!("Received: {}", text);
+            if options.raw_stream && tx.send(StreamMessage::Raw(text.clone())).is_err() {
+                return Ok(stats);
+            }
+            lines.push_str(&text);
             
             match self.endpoint_type {
-                EndpointType::LMStudio => {
-                    // Split the text by lines and process each line
-                    for line in text.lines() {
-                        if line.is_empty() || line == "data: [DONE]" {
+                EndpointType::LMStudio | EndpointType::OpenAI => {
+                    while let Some(line) = next_line(&mut lines) {
+                        if line == "data: [DONE]" {
+                            if in_reasoning {
+                                let _ = tx.send(StreamMessage::Text(REASONING_CLOSE.to_string()));
+                            }
+                            for (i, _) in alternatives_reasoning.iter().enumerate().filter(|(_, open)| **open) {
+                                let _ = tx.send(StreamMessage::Alternative(i + 1, REASONING_CLOSE.to_string()));
+                            }
+                            return Ok(stats);
+                        }
+
+                        if line.is_empty() {
                             continue;
                         }
                         
@@ -213,62 +1642,424 @@ impl LLMClient {
                         
                         match serde_json::from_str::<ChatResponse>(json_str) {
                             Ok(response) => {
-                                if let Some(choice) = response.choices.first() {
-                                    if let Some(content) = &choice.delta.content {
-                                        buffer.push_str(content);
+                                // With several choices their chunks arrive interleaved
+                                for choice in response.choices.iter().filter(|choice| choice.index > 0) {
+                                    let slot = choice.index - 1;
+                                    if alternatives_reasoning.len() <= slot {
+                                        alternatives_reasoning.resize(slot + 1, false);
+                                    }
+                                    let content = tag_reasoning(
+                                        choice.delta.reasoning_text(),
+                                        choice.delta.content.as_deref(),
+                                        &mut alternatives_reasoning[slot],
+                                    );
+                                    if !content.is_empty() && tx.send(StreamMessage::Alternative(choice.index, content)).is_err() {
+                                        return Ok(stats);
+                                    }
+                                }
+
+                                if let Some(choice) = response.choices.iter().find(|choice| choice.index == 0) {
+                                    let content = tag_reasoning(
+                                        choice.delta.reasoning_text(),
+                                        choice.delta.content.as_deref(),
+                                        &mut in_reasoning,
+                                    );
+                                    if !content.is_empty() {
+                                        buffer.push_str(&content);
+                                        first_token_at.get_or_insert_with(Instant::now);
                                         
                                         // Try to send the content through the channel
-                                        if tx.send(content.clone()).is_err() {
+                                        if tx.send(StreamMessage::Text(content)).is_err() {
                                             // If sending fails, the receiver has been dropped
-                                            return Ok(());
+                                            return Ok(stats);
                                         }
                                     }
                                     
+                                    // The usage chunk follows the one carrying finish_reason,
+                                    // so keep reading until [DONE]
                                     if choice.finish_reason.is_some() {
-                                        return Ok(());
+                                        stats.generation_secs = first_token_at.map(|start| start.elapsed().as_secs_f64());
+                                        stats.finish_reason = choice.finish_reason.clone();
                                     }
                                 }
+
+                                if let Some(usage) = response.usage {
+                                    stats.prompt_tokens = usage.prompt_tokens;
+                                    stats.completion_tokens = usage.completion_tokens;
+                                }
                             }
                             Err(e) => {
-                                eprintln!("Failed to parse response: {}. Raw JSON: {}", e, json_str);
+                                log::warn!("Failed to parse response: {}. Raw JSON: {}", e, json_str);
                             }
                         }
                     }
                 }
                 EndpointType::Ollama => {
-                    // Ollama returns each chunk as a complete JSON object
-                    if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                        // Get content from message.content
-                        if let Some(message) = response.get("message") {
-                            if let Some(content) = message.get("content") {
-                                if let Some(text) = content.as_str() {
-                                    // Skip the thinking tokens but preserve newlines
-                                    if text != "<think>" && text != "</think>" {
-                                        // If we get pure newlines, add just one
-                                        if text.trim().is_empty() && text.contains('\n') {
-                                            buffer.push('\n');
-                                            if tx.send("\n".to_string()).is_err() {
-                                                return Ok(());
-                                            }
-                                        } else {
-                                            buffer.push_str(text);
-                                            if tx.send(text.to_string()).is_err() {
-                                                return Ok(());
-                                            }
-                                        }
-                                    }
+                    // One JSON object per line, which chunks don't necessarily line up with
+                    while let Some(line) = next_line(&mut lines) {
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        let response = match serde_json::from_str::<serde_json::Value>(&line) {
+                            Ok(response) => response,
+                            Err(e) => {
+                                log::warn!("Failed to parse response: {}. Raw JSON: {}", e, line);
+                                continue;
+                            }
+                        };
+                        // Chat replies nest the text in `message`, generate replies put it at the top level
+                        let message = if ollama_generate { Some(&response) } else { response.get("message") };
+                        let content_key = if ollama_generate { "response" } else { "content" };
+                        if let Some(message) = message {
+                            let thinking = ollama_reasoning(message);
+                            let content = message.get(content_key).and_then(|v| v.as_str())
+                                // If we get pure newlines, add just one
+                                .map(|text| if text.trim().is_empty() && text.contains('\n') { "\n" } else { text });
+
+                            let text = tag_reasoning(thinking, content, &mut in_reasoning);
+                            if !text.is_empty() {
+                                buffer.push_str(&text);
+                                if tx.send(StreamMessage::Text(text)).is_err() {
+                                    return Ok(stats);
                                 }
                             }
                         }
                         
                         if response.get("done").and_then(|v| v.as_bool()).unwrap_or(false) {
-                            return Ok(());
+                            if in_reasoning {
+                                let _ = tx.send(StreamMessage::Text(REASONING_CLOSE.to_string()));
+                            }
+                            return Ok(ollama_stats(&response));
+                        }
+                    }
+                }
+                EndpointType::Anthropic => {
+                    // Server-sent events; the event name is repeated as "type" in the data
+                    while let Some(line) = next_line(&mut lines) {
+                        let Some(json_str) = line.strip_prefix("data: ") else {
+                            continue;
+                        };
+
+                        match serde_json::from_str::<AnthropicEvent>(json_str) {
+                            Ok(AnthropicEvent::MessageStart { message }) => {
+                                stats.prompt_tokens = message.usage.input_tokens;
+                            }
+                            Ok(AnthropicEvent::ContentBlockDelta { delta }) => {
+                                let content = match &delta {
+                                    AnthropicDelta::TextDelta { text } => tag_reasoning(None, Some(text), &mut in_reasoning),
+                                    AnthropicDelta::ThinkingDelta { thinking } => tag_reasoning(Some(thinking), None, &mut in_reasoning),
+                                    AnthropicDelta::Other => String::new(),
+                                };
+                                if !content.is_empty() {
+                                    buffer.push_str(&content);
+                                    first_token_at.get_or_insert_with(Instant::now);
+
+                                    if tx.send(StreamMessage::Text(content)).is_err() {
+                                        return Ok(stats);
+                                    }
+                                }
+                            }
+                            Ok(AnthropicEvent::MessageDelta { delta, usage }) => {
+                                stats.completion_tokens = usage.output_tokens;
+                                stats.finish_reason = delta.stop_reason;
+                            }
+                            Ok(AnthropicEvent::MessageStop) => {
+                                if in_reasoning {
+                                    let _ = tx.send(StreamMessage::Text(REASONING_CLOSE.to_string()));
+                                }
+                                stats.generation_secs = first_token_at.map(|start| start.elapsed().as_secs_f64());
+                                return Ok(stats);
+                            }
+                            Ok(AnthropicEvent::Error { error }) => {
+                                return Err(anyhow::anyhow!("Server error: {}", error.message));
+                            }
+                            Ok(AnthropicEvent::Other) => {}
+                            Err(e) => {
+                                log::warn!("Failed to parse response: {}. Raw JSON: {}", e, json_str);
+                            }
+                        }
+                    }
+                }
+                EndpointType::LlamaCpp => {
+                    // Server-sent events, one JSON chunk per data line
+                    while let Some(line) = next_line(&mut lines) {
+                        let Some(json_str) = line.strip_prefix("data: ") else {
+                            continue;
+                        };
+
+                        match serde_json::from_str::<LlamaCppChunk>(json_str) {
+                            Ok(chunk) => {
+                                if !chunk.content.is_empty() {
+                                    buffer.push_str(&chunk.content);
+                                    first_token_at.get_or_insert_with(Instant::now);
+
+                                    if tx.send(StreamMessage::Text(chunk.content)).is_err() {
+                                        return Ok(stats);
+                                    }
+                                }
+
+                                if chunk.stop {
+                                    stats.finish_reason = chunk.stop_type;
+                                    if let Some(timings) = chunk.timings {
+                                        stats.prompt_tokens = timings.prompt_n;
+                                        stats.completion_tokens = timings.predicted_n;
+                                        stats.generation_secs = timings.predicted_ms.map(|ms| ms / 1000.0);
+                                    }
+                                    return Ok(stats);
+                                }
+                            }
+                            Err(e) => {
+                                log::warn!("Failed to parse response: {}. Raw JSON: {}", e, json_str);
+                            }
                         }
                     }
                 }
             }
         }
+
+        // The stream ended without a usage chunk, fall back to the measured time
+        if stats.generation_secs.is_none() {
+            stats.generation_secs = first_token_at.map(|start| start.elapsed().as_secs_f64());
+        }
         
-        Ok(())
+        Ok(stats)
+    }
+
+    /// Read a reply requested with streaming off, which arrives as a single JSON object,
+    /// and send its text in one piece.
+    async fn complete_reply(&self, response: Response, options: &ChatOptions, tx: &Sender<StreamMessage>, cancel: &AtomicBool, sent_at: Instant) -> Result<GenerationStats> {
+        let body = response.text().await
+            .map_err(|e| anyhow::anyhow!("Error reading response: {}", e))?;
+        log::trace!("Received: {}", body);
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(GenerationStats::default());
+        }
+        if options.raw_stream {
+            let _ = tx.send(StreamMessage::Raw(body.clone()));
+        }
+
+        let mut in_reasoning = false;
+        let (mut text, mut stats) = match self.endpoint_type {
+            EndpointType::LMStudio | EndpointType::OpenAI => {
+                let response: CompleteChatResponse = serde_json::from_str(&body)
+                    .context("Failed to parse response")?;
+                let (mut first, others): (Vec<_>, Vec<_>) = response.choices.into_iter()
+                    .partition(|choice| choice.index == 0);
+                for choice in others {
+                    let mut in_reasoning = false;
+                    let mut text = tag_reasoning(
+                        choice.message.reasoning_text(),
+                        choice.message.content.as_deref(),
+                        &mut in_reasoning,
+                    );
+                    if in_reasoning {
+                        text.push_str(REASONING_CLOSE);
+                    }
+                    let _ = tx.send(StreamMessage::Alternative(choice.index, text));
+                }
+                let choice = first.pop()
+                    .ok_or_else(|| anyhow::anyhow!("The response has no choices: {}", body))?;
+                let text = tag_reasoning(
+                    choice.message.reasoning_text(),
+                    choice.message.content.as_deref(),
+                    &mut in_reasoning,
+                );
+                let stats = GenerationStats {
+                    prompt_tokens: response.usage.as_ref().and_then(|usage| usage.prompt_tokens),
+                    completion_tokens: response.usage.as_ref().and_then(|usage| usage.completion_tokens),
+                    generation_secs: None,
+                    finish_reason: choice.finish_reason,
+                };
+                (text, stats)
+            }
+            EndpointType::Ollama => {
+                let response: serde_json::Value = serde_json::from_str(&body)
+                    .context("Failed to parse response")?;
+                // Chat replies nest the text in `message`, generate replies put it at the top level
+                let (message, content_key) = if self.uses_ollama_generate() {
+                    (Some(&response), "response")
+                } else {
+                    (response.get("message"), "content")
+                };
+                let thinking = message.and_then(ollama_reasoning);
+                let content = message.and_then(|message| message.get(content_key)).and_then(|v| v.as_str());
+                (tag_reasoning(thinking, content, &mut in_reasoning), ollama_stats(&response))
+            }
+            EndpointType::Anthropic => {
+                let response: AnthropicResponse = serde_json::from_str(&body)
+                    .context("Failed to parse response")?;
+                let text: String = response.content.iter()
+                    .map(|block| match block {
+                        AnthropicBlock::Text { text } => tag_reasoning(None, Some(text), &mut in_reasoning),
+                        AnthropicBlock::Thinking { thinking } => tag_reasoning(Some(thinking), None, &mut in_reasoning),
+                        AnthropicBlock::Other => String::new(),
+                    })
+                    .collect();
+                let stats = GenerationStats {
+                    prompt_tokens: response.usage.input_tokens,
+                    completion_tokens: response.usage.output_tokens,
+                    generation_secs: None,
+                    finish_reason: response.stop_reason,
+                };
+                (text, stats)
+            }
+            EndpointType::LlamaCpp => {
+                let response: LlamaCppChunk = serde_json::from_str(&body)
+                    .context("Failed to parse response")?;
+                let mut stats = GenerationStats {
+                    finish_reason: response.stop_type,
+                    ..Default::default()
+                };
+                if let Some(timings) = response.timings {
+                    stats.prompt_tokens = timings.prompt_n;
+                    stats.completion_tokens = timings.predicted_n;
+                    stats.generation_secs = timings.predicted_ms.map(|ms| ms / 1000.0);
+                }
+                (response.content, stats)
+            }
+        };
+
+        if in_reasoning {
+            text.push_str(REASONING_CLOSE);
+        }
+        // Without tokens arriving one by one, the time includes reading the prompt
+        if stats.generation_secs.is_none() {
+            stats.generation_secs = Some(sent_at.elapsed().as_secs_f64());
+        }
+        if !text.is_empty() {
+            let _ = tx.send(StreamMessage::Text(text));
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(endpoint_type: EndpointType) -> LLMClient {
+        LLMClient::new(
+            "http".to_string(),
+            "localhost".to_string(),
+            "8080".to_string(),
+            String::new(),
+            endpoint_type,
+            ClientSettings::default(),
+        )
+    }
+
+    /// The body of a chat request for "Hi" with `options`.
+    fn request_body(endpoint_type: EndpointType, options: &ChatOptions) -> serde_json::Value {
+        let history = [ChatEntry::new("user", "Hi")];
+        client(endpoint_type).build_chat_request(&history, "Hi", &[], "model", options).unwrap().1
+    }
+
+    #[test]
+    fn decode_utf8_chunk_keeps_split_character() {
+        let mut pending = Vec::new();
+        let bytes = "é".as_bytes();
+
+        assert_eq!(decode_utf8_chunk(&mut pending, &bytes[..1]), "");
+        assert_eq!(decode_utf8_chunk(&mut pending, &bytes[1..]), "é");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn history_messages_leave_out_errors() {
+        let history = [
+            ChatEntry::new("user", "Hi"),
+            ChatEntry::new("error", "Connection refused"),
+            ChatEntry::new("assistant", "Hello!"),
+        ];
+
+        let expected = vec![
+            serde_json::json!({ "role": "user", "content": "Hi" }),
+            serde_json::json!({ "role": "assistant", "content": "Hello!" }),
+        ];
+        assert_eq!(LLMClient::history_messages(&history, None, None), expected);
+        // The error doesn't take up one of the two turns kept
+        assert_eq!(LLMClient::history_messages(&history, None, Some(2)), expected);
+    }
+
+    #[test]
+    fn chat_messages_reject_non_text_content() {
+        let array = [serde_json::json!({ "role": "user", "content": [{ "type": "text", "text": "Hi" }] })];
+        assert!(LLMClient::chat_messages(&array).is_err());
+
+        let number = [
+            serde_json::json!({ "role": "system", "content": "Be brief" }),
+            serde_json::json!({ "role": "user", "content": 42 }),
+        ];
+        assert!(LLMClient::chat_messages(&number).is_err());
+
+        let text = [serde_json::json!({ "role": "user", "content": "Hi" })];
+        assert_eq!(LLMClient::chat_messages(&text).unwrap()[0].content, "Hi");
+    }
+
+    #[test]
+    fn next_line_waits_for_line_end() {
+        let mut buffer = "data: {\"a\":".to_string();
+        assert_eq!(next_line(&mut buffer), None);
+
+        buffer.push_str("1}\r\ndata: [DONE]\n");
+        assert_eq!(next_line(&mut buffer).as_deref(), Some("data: {\"a\":1}"));
+        assert_eq!(next_line(&mut buffer).as_deref(), Some("data: [DONE]"));
+        assert_eq!(next_line(&mut buffer), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn max_tokens_only_sent_when_set() {
+        let unlimited = ChatOptions::default();
+        assert!(request_body(EndpointType::OpenAI, &unlimited).get("max_tokens").is_none());
+        assert!(request_body(EndpointType::Ollama, &unlimited)["options"].get("num_predict").is_none());
+
+        let limited = ChatOptions { max_tokens: 256, ..ChatOptions::default() };
+        assert_eq!(request_body(EndpointType::OpenAI, &limited)["max_tokens"], 256);
+        assert_eq!(request_body(EndpointType::Ollama, &limited)["options"]["num_predict"], 256);
+    }
+
+    #[test]
+    fn stop_only_sent_when_not_empty() {
+        let none = ChatOptions::default();
+        assert!(request_body(EndpointType::OpenAI, &none).get("stop").is_none());
+        assert!(request_body(EndpointType::Ollama, &none)["options"].get("stop").is_none());
+
+        let stop = ChatOptions { stop: vec!["\nUser:".to_string()], ..ChatOptions::default() };
+        assert_eq!(request_body(EndpointType::OpenAI, &stop)["stop"], serde_json::json!(["\nUser:"]));
+        assert_eq!(request_body(EndpointType::Ollama, &stop)["options"]["stop"], serde_json::json!(["\nUser:"]));
+    }
+
+    #[test]
+    fn json_format_only_sent_in_json_mode() {
+        let text = ChatOptions::default();
+        assert!(request_body(EndpointType::OpenAI, &text).get("response_format").is_none());
+        assert!(request_body(EndpointType::Ollama, &text).get("format").is_none());
+
+        let json = ChatOptions { json_mode: true, ..ChatOptions::default() };
+        assert_eq!(request_body(EndpointType::OpenAI, &json)["response_format"], serde_json::json!({ "type": "json_object" }));
+        assert_eq!(request_body(EndpointType::Ollama, &json)["format"], "json");
+    }
+
+    #[test]
+    fn neutral_sampling_left_out() {
+        let neutral = ChatOptions::default();
+        assert!(request_body(EndpointType::OpenAI, &neutral).get("top_p").is_none());
+        let llama = request_body(EndpointType::LlamaCpp, &neutral);
+        assert!(llama.get("top_p").is_none());
+        assert!(llama.get("top_k").is_none());
+        let ollama = neutral.to_ollama_options();
+        assert!(ollama.get("top_p").is_none());
+        assert!(ollama.get("top_k").is_none());
+
+        let set = ChatOptions { top_p: 0.5, top_k: 40, ..ChatOptions::default() };
+        assert_eq!(request_body(EndpointType::OpenAI, &set)["top_p"], 0.5);
+        let llama = request_body(EndpointType::LlamaCpp, &set);
+        assert_eq!(llama["top_p"], 0.5);
+        assert_eq!(llama["top_k"], 40);
+        let ollama = set.to_ollama_options();
+        assert_eq!(ollama["top_p"], 0.5);
+        assert_eq!(ollama["top_k"], 40);
     }
 }
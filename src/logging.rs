@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Log file the records are copied to, besides stderr.
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+/// The API key in use, never written out as it is.
+static SECRET: Mutex<Option<String>> = Mutex::new(None);
+
+/// How much of the app's own activity gets logged.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "Errors",
+            LogLevel::Warn => "Warnings",
+            LogLevel::Info => "Info",
+            LogLevel::Debug => "Debug (request bodies)",
+            LogLevel::Trace => "Trace (streamed responses)",
+        }
+    }
+
+    fn filter(&self) -> log::LevelFilter {
+        match self {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Writes records to stderr and the log file, with the API key masked.
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut text = String::from_utf8_lossy(buf).into_owned();
+        if let Ok(secret) = SECRET.lock() {
+            if let Some(secret) = secret.as_deref() {
+                text = text.replace(secret, "<redacted>");
+            }
+        }
+
+        std::io::stderr().write_all(text.as_bytes())?;
+        if let Ok(mut file) = LOG_FILE.lock() {
+            if let Some(file) = file.as_mut() {
+                file.write_all(text.as_bytes())?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Ok(mut file) = LOG_FILE.lock() {
+            if let Some(file) = file.as_mut() {
+                file.flush()?;
+            }
+        }
+        std::io::stderr().flush()
+    }
+}
+
+/// Install the logger. Other crates only log warnings; `RUST_LOG` can override that.
+pub fn init(level: LogLevel) {
+    let result = env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .filter_module(module_path!().split("::").next().unwrap_or_default(), log::LevelFilter::Trace)
+        .parse_default_env()
+        .target(env_logger::Target::Pipe(Box::new(LogWriter)))
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("Failed to set up logging: {}", e);
+    }
+    set_level(level);
+}
+
+pub fn set_level(level: LogLevel) {
+    log::set_max_level(level.filter());
+}
+
+/// Copy the log to `path` from now on, appending to it; an empty path stops writing a file.
+pub fn set_log_file(path: &str) -> Result<()> {
+    let file = if path.trim().is_empty() {
+        None
+    } else {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.trim())
+            .context(format!("Failed to open log file {}", path.trim()))?;
+        Some(file)
+    };
+
+    if let Ok(mut current) = LOG_FILE.lock() {
+        *current = file;
+    }
+    Ok(())
+}
+
+/// Mask `secret` in everything logged from now on, instead of the previous one.
+pub fn redact(secret: &str) {
+    if let Ok(mut current) = SECRET.lock() {
+        *current = Some(secret.to_string()).filter(|secret| !secret.is_empty());
+    }
+}
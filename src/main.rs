@@ -3,19 +3,42 @@
 use anyhow::Result;
 use eframe::egui;
 
+mod config;
 mod endpoint_type;
 mod llmclient;
+mod markdown;
+mod chat_export;
+mod conversation;
+mod curl;
+mod logging;
+mod prompt_history;
+mod sampling_preset;
 mod chatapp;
 mod chatapp_ui;
+mod command_palette;
+mod comparison;
+mod tabs;
 mod sdclient;
+mod sd_mask;
+mod sd_filename;
+mod png_info;
 
 use chatapp::ChatApp;
 
+/// Window size on first launch; later launches restore the last one.
+const DEFAULT_WINDOW_SIZE: [f32; 2] = [800.0, 600.0];
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Until the saved settings are loaded
+    logging::init(logging::LogLevel::Info);
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([800.0, 600.0]),
+            .with_inner_size(DEFAULT_WINDOW_SIZE)
+            .with_min_inner_size(config::MIN_WINDOW_SIZE),
+        // ChatApp restores the window itself, independent of the UI scale
+        persist_window: false,
         ..Default::default()
     };
 
@@ -0,0 +1,384 @@
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use egui_extras::syntax_highlighting::{self, CodeTheme};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// Drawn after the text of a reply that is still streaming.
+const STREAMING_CURSOR: &str = "▌";
+
+/// Render a markdown string into the given ui.
+pub fn render_markdown(ui: &mut egui::Ui, text: &str) {
+    let mut renderer = MarkdownRenderer::new(ui);
+    let parser = parser(text);
+
+    for event in parser {
+        renderer.handle_event(ui, event);
+    }
+
+    renderer.flush(ui);
+}
+
+/// Render a reply that is still streaming, with a cursor in `cursor_color` after its last word.
+/// A transparent color hides the cursor without moving the text around it.
+pub fn render_markdown_with_cursor(ui: &mut egui::Ui, text: &str, cursor_color: egui::Color32) {
+    let mut renderer = MarkdownRenderer::new(ui);
+    let events: Vec<Event> = parser(text).collect();
+    // Right after the last text, before the closing tags flush its paragraph
+    let last_text = events.iter().rposition(|event| matches!(event, Event::Text(_) | Event::Code(_)));
+    let mut cursor_shown = false;
+
+    for (i, event) in events.into_iter().enumerate() {
+        renderer.handle_event(ui, event);
+        // Code blocks are drawn as plain text, so one still open gets the cursor below it
+        if Some(i) == last_text && renderer.code_block.is_none() {
+            renderer.append_cursor(ui, cursor_color);
+            cursor_shown = true;
+        }
+    }
+
+    if !cursor_shown {
+        renderer.flush(ui);
+        renderer.append_cursor(ui, cursor_color);
+    }
+    renderer.flush(ui);
+}
+
+fn parser(text: &str) -> Parser<'_> {
+    Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS | Options::ENABLE_TABLES)
+}
+
+struct CodeBlock {
+    language: String,
+    code: String,
+}
+
+struct MarkdownRenderer {
+    job: LayoutJob,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    link: bool,
+    heading: Option<HeadingLevel>,
+    quote_depth: usize,
+    // One entry per open list: the next item number for ordered lists
+    lists: Vec<Option<u64>>,
+    code_block: Option<CodeBlock>,
+    table: Option<Table>,
+}
+
+/// A table being read, drawn as a grid once it ends.
+struct Table {
+    alignments: Vec<Alignment>,
+    /// Finished rows of cells, the header row first.
+    rows: Vec<Vec<LayoutJob>>,
+    row: Vec<LayoutJob>,
+    has_header: bool,
+}
+
+impl MarkdownRenderer {
+    fn new(ui: &egui::Ui) -> Self {
+        Self {
+            job: Self::new_job(ui),
+            strong: false,
+            emphasis: false,
+            strikethrough: false,
+            link: false,
+            heading: None,
+            quote_depth: 0,
+            lists: Vec::new(),
+            code_block: None,
+            table: None,
+        }
+    }
+
+    fn new_job(ui: &egui::Ui) -> LayoutJob {
+        let mut job = LayoutJob::default();
+        job.wrap.max_width = ui.available_width();
+        job
+    }
+
+    fn handle_event(&mut self, ui: &mut egui::Ui, event: Event) {
+        match event {
+            Event::Start(tag) => self.start_tag(ui, tag),
+            Event::End(tag) => self.end_tag(ui, tag),
+            Event::Text(text) => {
+                if let Some(block) = &mut self.code_block {
+                    block.code.push_str(&text);
+                } else {
+                    self.append(ui, &text, false);
+                }
+            }
+            Event::Code(code) => self.append(ui, &code, true),
+            Event::Html(html) | Event::InlineHtml(html) => self.append(ui, &html, false),
+            Event::InlineMath(math) | Event::DisplayMath(math) => self.append(ui, &math, true),
+            Event::FootnoteReference(name) => self.append(ui, &format!("[{}]", name), false),
+            Event::SoftBreak => self.append(ui, " ", false),
+            Event::HardBreak => self.append(ui, "\n", false),
+            Event::Rule => {
+                self.flush(ui);
+                ui.separator();
+            }
+            Event::TaskListMarker(checked) => {
+                self.append(ui, if checked { "☑ " } else { "☐ " }, false);
+            }
+        }
+    }
+
+    fn start_tag(&mut self, ui: &mut egui::Ui, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush(ui);
+                self.heading = Some(level);
+            }
+            Tag::BlockQuote(_) => {
+                self.flush(ui);
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush(ui);
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code_block = Some(CodeBlock { language, code: String::new() });
+            }
+            Tag::List(start) => {
+                self.flush(ui);
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush(ui);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.append(ui, &marker, false);
+            }
+            Tag::Table(alignments) => {
+                self.flush(ui);
+                self.table = Some(Table { alignments, rows: Vec::new(), row: Vec::new(), has_header: false });
+            }
+            Tag::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.has_header = true;
+                }
+            }
+            Tag::Emphasis => self.emphasis = true,
+            Tag::Strong => self.strong = true,
+            Tag::Strikethrough => self.strikethrough = true,
+            Tag::Link { .. } => self.link = true,
+            _ => {}
+        }
+    }
+
+    fn end_tag(&mut self, ui: &mut egui::Ui, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush(ui);
+                if self.lists.is_empty() {
+                    ui.add_space(4.0);
+                }
+            }
+            TagEnd::Heading(_) => {
+                self.flush(ui);
+                self.heading = None;
+            }
+            TagEnd::BlockQuote => {
+                self.flush(ui);
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => {
+                if let Some(block) = self.code_block.take() {
+                    render_code_block(ui, &block.language, &block.code);
+                }
+            }
+            TagEnd::List(_) => {
+                self.flush(ui);
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    ui.add_space(4.0);
+                }
+            }
+            TagEnd::Item => self.flush(ui),
+            TagEnd::TableCell => {
+                let job = std::mem::replace(&mut self.job, Self::new_job(ui));
+                if let Some(table) = &mut self.table {
+                    table.row.push(job);
+                }
+            }
+            // The header's cells come straight inside it, without a row
+            TagEnd::TableHead | TagEnd::TableRow => {
+                if let Some(table) = &mut self.table {
+                    let row = std::mem::take(&mut table.row);
+                    table.rows.push(row);
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    render_table(ui, table);
+                }
+                ui.add_space(4.0);
+            }
+            TagEnd::Emphasis => self.emphasis = false,
+            TagEnd::Strong => self.strong = false,
+            TagEnd::Strikethrough => self.strikethrough = false,
+            TagEnd::Link => self.link = false,
+            _ => {}
+        }
+    }
+
+    fn append(&mut self, ui: &egui::Ui, text: &str, code: bool) {
+        let visuals = ui.visuals();
+        let body = egui::TextStyle::Body.resolve(ui.style());
+
+        let font_id = if code {
+            egui::TextStyle::Monospace.resolve(ui.style())
+        } else {
+            match self.heading {
+                Some(HeadingLevel::H1) => egui::FontId::proportional(body.size * 1.6),
+                Some(HeadingLevel::H2) => egui::FontId::proportional(body.size * 1.4),
+                Some(HeadingLevel::H3) => egui::FontId::proportional(body.size * 1.2),
+                Some(_) => egui::FontId::proportional(body.size * 1.1),
+                None => body,
+            }
+        };
+
+        let color = if self.link {
+            visuals.hyperlink_color
+        } else if self.strong || self.heading.is_some() || self.in_table_header() {
+            visuals.strong_text_color()
+        } else if self.quote_depth > 0 {
+            visuals.weak_text_color()
+        } else {
+            visuals.text_color()
+        };
+
+        let mut format = TextFormat {
+            font_id,
+            color,
+            italics: self.emphasis || self.quote_depth > 0,
+            ..Default::default()
+        };
+
+        if code {
+            format.background = visuals.code_bg_color;
+        }
+        if self.strikethrough {
+            format.strikethrough = egui::Stroke::new(1.0, color);
+        }
+        if self.link {
+            format.underline = egui::Stroke::new(1.0, color);
+        }
+
+        self.job.append(text, 0.0, format);
+    }
+
+    fn in_table_header(&self) -> bool {
+        self.table.as_ref().is_some_and(|table| table.has_header && table.rows.is_empty())
+    }
+
+    fn append_cursor(&mut self, ui: &egui::Ui, color: egui::Color32) {
+        let format = TextFormat {
+            font_id: egui::TextStyle::Body.resolve(ui.style()),
+            color,
+            ..Default::default()
+        };
+        self.job.append(STREAMING_CURSOR, 0.0, format);
+    }
+
+    fn flush(&mut self, ui: &mut egui::Ui) {
+        if self.job.sections.is_empty() {
+            return;
+        }
+
+        let job = std::mem::replace(&mut self.job, Self::new_job(ui));
+        let indent = 16.0 * (self.lists.len().saturating_sub(1) + self.quote_depth) as f32;
+
+        if indent > 0.0 {
+            ui.horizontal(|ui| {
+                ui.add_space(indent);
+                ui.add(egui::Label::new(job).wrap(true).selectable(true));
+            });
+        } else {
+            ui.add(egui::Label::new(job).wrap(true).selectable(true));
+        }
+    }
+}
+
+fn render_table(ui: &mut egui::Ui, table: Table) {
+    // Rows can be ragged while a table streams in, so the widest one sets the columns
+    let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0).max(table.alignments.len());
+    if columns == 0 {
+        return;
+    }
+    let cell_width = (ui.available_width() / columns as f32).max(80.0);
+
+    egui::Frame::none()
+        .stroke(ui.visuals().widgets.noninteractive.bg_stroke)
+        .rounding(4.0)
+        .inner_margin(egui::style::Margin::same(4.0))
+        .show(ui, |ui| {
+            egui::Grid::new(ui.next_auto_id())
+                .num_columns(columns)
+                .striped(true)
+                .spacing([12.0, 4.0])
+                .show(ui, |ui| {
+                    for (row_index, row) in table.rows.into_iter().enumerate() {
+                        let header = table.has_header && row_index == 0;
+                        let mut cells = row.into_iter();
+                        for column in 0..columns {
+                            let mut job = cells.next().unwrap_or_default();
+                            job.wrap.max_width = cell_width;
+                            let align = match table.alignments.get(column) {
+                                Some(Alignment::Center) => egui::Align::Center,
+                                Some(Alignment::Right) => egui::Align::Max,
+                                _ => egui::Align::Min,
+                            };
+                            ui.with_layout(egui::Layout::top_down(align), |ui| {
+                                ui.add(egui::Label::new(job).wrap(true).selectable(true));
+                            });
+                        }
+                        ui.end_row();
+                        if header {
+                            for _ in 0..columns {
+                                ui.separator();
+                            }
+                            ui.end_row();
+                        }
+                    }
+                });
+        });
+}
+
+fn render_code_block(ui: &mut egui::Ui, language: &str, code: &str) {
+    let code = code.trim_end_matches('\n');
+
+    egui::Frame::none()
+        .fill(ui.visuals().extreme_bg_color)
+        .rounding(4.0)
+        .inner_margin(egui::style::Margin::same(8.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+
+            ui.horizontal(|ui| {
+                if !language.is_empty() {
+                    ui.label(egui::RichText::new(language).small().weak());
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("Copy").on_hover_text("Copy code to clipboard").clicked() {
+                        ui.output_mut(|o| o.copied_text = code.to_string());
+                    }
+                });
+            });
+
+            let theme = CodeTheme::from_style(ui.style());
+            let mut job = syntax_highlighting::highlight(ui.ctx(), &theme, code, language);
+            job.wrap.max_width = ui.available_width();
+            ui.add(egui::Label::new(job).wrap(true).selectable(true));
+        });
+
+    ui.add_space(4.0);
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+
+/// Text chunk keyword Automatic1111 reads generation parameters from ("PNG Info").
+const PARAMETERS_KEYWORD: &str = "parameters";
+
+/// Re-encode a PNG with `parameters` stored the way Automatic1111 writes it, replacing
+/// any text chunks the original carried.
+pub fn embed_parameters(png_bytes: &[u8], parameters: &str) -> Result<Vec<u8>> {
+    let mut decoder = png::Decoder::new(png_bytes);
+    // Expand palettes so the pixels can be written back without copying the palette
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().context("Not a valid PNG image")?;
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).context("Failed to decode PNG image")?;
+    pixels.truncate(frame.buffer_size());
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut output, frame.width, frame.height);
+        encoder.set_color(frame.color_type);
+        encoder.set_depth(frame.bit_depth);
+
+        // tEXt is Latin-1 only, prompts with other characters need iTXt
+        let latin1 = parameters.chars().all(|c| (c as u32) < 0x100);
+        if latin1 {
+            encoder.add_text_chunk(PARAMETERS_KEYWORD.to_string(), parameters.to_string())?;
+        } else {
+            encoder.add_itxt_chunk(PARAMETERS_KEYWORD.to_string(), parameters.to_string())?;
+        }
+
+        let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+        writer.write_image_data(&pixels).context("Failed to write PNG image data")?;
+    }
+
+    Ok(output)
+}
+
+/// Generation settings recovered from an Automatic1111 parameters text.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParameters {
+    pub prompt: String,
+    pub negative_prompt: String,
+    pub steps: Option<u32>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    pub cfg_scale: Option<f32>,
+    pub seed: Option<i64>,
+    pub size: Option<(u32, u32)>,
+}
+
+/// Parse the text A1111 embeds in its images:
+///
+/// ```text
+/// a prompt, possibly over several lines
+/// Negative prompt: blurry
+/// Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 1234, Size: 512x512, ...
+/// ```
+///
+/// Returns `None` when there is nothing to recover.
+pub fn parse_parameters(text: &str) -> Option<GenerationParameters> {
+    let mut lines: Vec<&str> = text.trim().lines().collect();
+    let mut parameters = GenerationParameters::default();
+
+    if lines.last().is_some_and(|line| line.contains("Steps: ")) {
+        let settings = lines.pop().unwrap_or_default();
+        for (key, value) in split_settings(settings) {
+            match key.as_str() {
+                "Steps" => parameters.steps = value.parse().ok(),
+                "Sampler" => parameters.sampler = Some(value),
+                "Schedule type" => parameters.scheduler = Some(value),
+                "CFG scale" => parameters.cfg_scale = value.parse().ok(),
+                "Seed" => parameters.seed = value.parse().ok(),
+                "Size" => {
+                    parameters.size = value.split_once('x')
+                        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    match lines.iter().position(|line| line.starts_with("Negative prompt:")) {
+        Some(i) => {
+            parameters.prompt = lines[..i].join("\n");
+            let mut negative = vec![lines[i].trim_start_matches("Negative prompt:").trim_start()];
+            negative.extend(&lines[i + 1..]);
+            parameters.negative_prompt = negative.join("\n");
+        }
+        None => parameters.prompt = lines.join("\n"),
+    }
+
+    if parameters.prompt.is_empty() && parameters.steps.is_none() {
+        return None;
+    }
+
+    Some(parameters)
+}
+
+/// Split "Key: value, Key: "quoted, value", ..." into pairs.
+fn split_settings(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = line.trim();
+
+    while let Some((key, after)) = rest.split_once(": ") {
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            // Quoted values may contain commas; \" escapes a quote
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in quoted.char_indices() {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => escaped = false,
+                }
+            }
+            let end = end.unwrap_or(quoted.len());
+            let remaining = quoted.get(end + 1..).unwrap_or("");
+            (quoted[..end].replace("\\\"", "\""), remaining)
+        } else {
+            match after.split_once(", ") {
+                Some((value, remaining)) => (value.to_string(), remaining),
+                None => (after.to_string(), ""),
+            }
+        };
+
+        pairs.push((key.trim().to_string(), value.trim().to_string()));
+        rest = remaining.trim_start_matches(',').trim_start();
+    }
+
+    pairs
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Most recent prompts kept per input.
+pub const MAX_RECENT_PROMPTS: usize = 20;
+
+/// Recently sent and starred prompts for one input box.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptHistory {
+    /// Newest first, without duplicates.
+    pub recent: Vec<String>,
+    pub favorites: Vec<String>,
+}
+
+impl PromptHistory {
+    /// Remember a sent prompt, moving it to the front if it was already there.
+    pub fn record(&mut self, prompt: &str) {
+        let prompt = prompt.trim();
+        if prompt.is_empty() {
+            return;
+        }
+
+        self.recent.retain(|recent| recent != prompt);
+        self.recent.insert(0, prompt.to_string());
+        self.recent.truncate(MAX_RECENT_PROMPTS);
+    }
+
+    pub fn is_favorite(&self, prompt: &str) -> bool {
+        self.favorites.iter().any(|favorite| favorite == prompt.trim())
+    }
+
+    /// Star `prompt`, or unstar it if it already is.
+    pub fn toggle_favorite(&mut self, prompt: &str) {
+        let prompt = prompt.trim();
+        if prompt.is_empty() {
+            return;
+        }
+
+        if self.is_favorite(prompt) {
+            self.favorites.retain(|favorite| favorite != prompt);
+        } else {
+            self.favorites.push(prompt.to_string());
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::llmclient::ChatOptions;
+
+/// A named set of sampling parameters applied with one click.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplingPreset {
+    pub name: String,
+    pub temperature: f32,
+    /// Presets saved before top P existed leave it neutral.
+    #[serde(default = "neutral_top_p")]
+    pub top_p: f32,
+    pub presence_penalty: f32,
+    pub frequency_penalty: f32,
+}
+
+impl SamplingPreset {
+    fn new(name: &str, temperature: f32, top_p: f32, presence_penalty: f32, frequency_penalty: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            temperature,
+            top_p,
+            presence_penalty,
+            frequency_penalty,
+        }
+    }
+
+    /// The presets that are always offered, from most to least deterministic.
+    pub fn builtin() -> [SamplingPreset; 3] {
+        [
+            SamplingPreset::new("Precise", 0.2, 0.9, 0.0, 0.0),
+            SamplingPreset::new("Balanced", 0.7, 1.0, 0.0, 0.0),
+            // Trimming the unlikely tail and mild penalties keep long, high-temperature replies coherent
+            SamplingPreset::new("Creative", 1.2, 0.95, 0.6, 0.3),
+        ]
+    }
+
+    /// Whether these are the parameters currently set.
+    pub fn matches(&self, options: &ChatOptions) -> bool {
+        let close = |a: f32, b: f32| (a - b).abs() < 0.001;
+        close(self.temperature, options.temperature)
+            && close(self.top_p, options.top_p)
+            && close(self.presence_penalty, options.presence_penalty)
+            && close(self.frequency_penalty, options.frequency_penalty)
+    }
+}
+
+fn neutral_top_p() -> f32 {
+    ChatOptions::default().top_p
+}
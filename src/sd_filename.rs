@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use crate::sdclient::GeneratedImage;
+
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "sd_{date}_{time}_{seed}";
+
+/// Tokens understood in the file name template, for the settings hint.
+pub const FILENAME_TOKENS: &str = "{seed} {model} {sampler} {steps} {date} {time} {prompt_short} {index}";
+
+/// Words of the prompt kept for `{prompt_short}`.
+const PROMPT_SHORT_WORDS: usize = 6;
+/// Longest file name written, leaving room for a counter and the extension.
+const MAX_FILE_NAME_LEN: usize = 150;
+
+/// Fill in `template` for `image`, the `index`th (from 1) of the images being saved.
+/// The result is safe to use as a file name, without the extension.
+pub fn render_file_name(template: &str, image: &GeneratedImage, index: usize) -> String {
+    let details = image.details().unwrap_or_default();
+    let now = chrono::Local::now();
+    let prompt_short = details.prompt.as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .take(PROMPT_SHORT_WORDS)
+        .collect::<Vec<_>>()
+        .join("_");
+
+    let template = if template.trim().is_empty() { DEFAULT_FILENAME_TEMPLATE } else { template };
+    let name = template
+        .replace("{seed}", &details.seed.map(|seed| seed.to_string()).unwrap_or_default())
+        .replace("{model}", details.model.as_deref().unwrap_or_default())
+        .replace("{sampler}", details.sampler.as_deref().unwrap_or_default())
+        .replace("{steps}", &details.steps.map(|steps| steps.to_string()).unwrap_or_default())
+        .replace("{date}", &now.format("%Y%m%d").to_string())
+        .replace("{time}", &now.format("%H%M%S").to_string())
+        .replace("{prompt_short}", &prompt_short)
+        .replace("{index}", &index.to_string());
+    sanitize_file_name(&name)
+}
+
+/// Replace characters no common file system allows, and avoid names Windows reserves.
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut sanitized: String = name.chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .take(MAX_FILE_NAME_LEN)
+        .collect();
+    // Windows drops trailing dots and spaces, which could merge two names
+    sanitized = sanitized.trim().trim_end_matches('.').to_string();
+
+    if sanitized.is_empty() {
+        return "image".to_string();
+    }
+    let stem = sanitized.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4 && (stem.starts_with("COM") || stem.starts_with("LPT")) && stem.ends_with(|c: char| c.is_ascii_digit()));
+    if reserved {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+/// `folder/name.extension`, with a counter added if that file already exists.
+pub fn unique_path(folder: &Path, name: &str, extension: &str) -> PathBuf {
+    let mut path = folder.join(format!("{}.{}", name, extension));
+    let mut counter = 2;
+    while path.exists() {
+        path = folder.join(format!("{}_{}.{}", name, counter, extension));
+        counter += 1;
+    }
+    path
+}
@@ -0,0 +1,188 @@
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use eframe::egui;
+use image::{GrayImage, Luma, RgbaImage};
+use std::io::Cursor;
+
+/// Mask value marking pixels to regenerate; A1111 keeps the black ones.
+const MASKED: Luma<u8> = Luma([255]);
+const KEPT: Luma<u8> = Luma([0]);
+
+/// What the masked area starts from before inpainting, in the order of A1111's `inpainting_fill`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InpaintingFill {
+    Fill,
+    Original,
+    LatentNoise,
+    LatentNothing,
+}
+
+impl InpaintingFill {
+    pub const ALL: [InpaintingFill; 4] = [
+        InpaintingFill::Fill,
+        InpaintingFill::Original,
+        InpaintingFill::LatentNoise,
+        InpaintingFill::LatentNothing,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InpaintingFill::Fill => "Fill",
+            InpaintingFill::Original => "Original",
+            InpaintingFill::LatentNoise => "Latent noise",
+            InpaintingFill::LatentNothing => "Latent nothing",
+        }
+    }
+
+    pub fn api_value(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// Edge of the source image to grow when outpainting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutpaintSide {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl OutpaintSide {
+    pub const ALL: [OutpaintSide; 4] = [OutpaintSide::Left, OutpaintSide::Right, OutpaintSide::Top, OutpaintSide::Bottom];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutpaintSide::Left => "←",
+            OutpaintSide::Right => "→",
+            OutpaintSide::Top => "↑",
+            OutpaintSide::Bottom => "↓",
+        }
+    }
+}
+
+/// An inpainting mask over the img2img source, the same size as it, with a texture to overlay.
+pub struct SDMask {
+    pub image: GrayImage,
+    pub texture: egui::TextureHandle,
+    /// Where the current brush stroke last painted, in image pixels.
+    last_point: Option<egui::Pos2>,
+}
+
+impl SDMask {
+    pub fn new(ctx: &egui::Context, image: GrayImage) -> Self {
+        let texture = ctx.load_texture("sd-mask", overlay_image(&image), egui::TextureOptions::default());
+        Self { image, texture, last_point: None }
+    }
+
+    pub fn blank(ctx: &egui::Context, width: u32, height: u32) -> Self {
+        Self::new(ctx, GrayImage::from_pixel(width, height, KEPT))
+    }
+
+    /// Read a mask file, stretching it to the source image if the sizes differ.
+    pub fn from_file_bytes(ctx: &egui::Context, bytes: &[u8], width: u32, height: u32) -> Result<Self> {
+        let mut image = image::load_from_memory(bytes).context("Failed to decode mask")?.to_luma8();
+        if image.dimensions() != (width, height) {
+            image = image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle);
+        }
+        Ok(Self::new(ctx, image))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.image.pixels().all(|pixel| *pixel == KEPT)
+    }
+
+    pub fn clear(&mut self) {
+        self.image.pixels_mut().for_each(|pixel| *pixel = KEPT);
+        self.update_texture();
+    }
+
+    /// Paint (or erase) a line of `radius` from where the stroke last was to `point`.
+    pub fn stroke_to(&mut self, point: egui::Pos2, radius: f32, erase: bool) {
+        let from = self.last_point.unwrap_or(point);
+        // Dabs close enough together that a quick drag still leaves a solid line
+        let spacing = (radius / 2.0).max(1.0);
+        let dabs = ((point - from).length() / spacing).ceil().max(1.0) as usize;
+        for i in 0..=dabs {
+            self.dab(from.lerp(point, i as f32 / dabs as f32), radius, if erase { KEPT } else { MASKED });
+        }
+        self.last_point = Some(point);
+        self.update_texture();
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.last_point = None;
+    }
+
+    fn dab(&mut self, center: egui::Pos2, radius: f32, value: Luma<u8>) {
+        let (width, height) = self.image.dimensions();
+        let min_x = (center.x - radius).floor().max(0.0) as u32;
+        let min_y = (center.y - radius).floor().max(0.0) as u32;
+        let max_x = ((center.x + radius).ceil().max(0.0) as u32).min(width);
+        let max_y = ((center.y + radius).ceil().max(0.0) as u32).min(height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let offset = egui::pos2(x as f32 + 0.5, y as f32 + 0.5) - center;
+                if offset.length_sq() <= radius * radius {
+                    self.image.put_pixel(x, y, value);
+                }
+            }
+        }
+    }
+
+    fn update_texture(&mut self) {
+        self.texture.set(overlay_image(&self.image), egui::TextureOptions::default());
+    }
+
+    pub fn to_png_base64(&self) -> Result<String> {
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageLuma8(self.image.clone())
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+            .context("Failed to encode mask")?;
+        Ok(general_purpose::STANDARD.encode(bytes))
+    }
+}
+
+/// The masked pixels as a translucent red layer, transparent elsewhere.
+fn overlay_image(mask: &GrayImage) -> egui::ColorImage {
+    let size = [mask.width() as usize, mask.height() as usize];
+    let pixels = mask.pixels()
+        .map(|Luma([value])| egui::Color32::from_rgba_unmultiplied(255, 0, 0, value / 2))
+        .collect();
+    egui::ColorImage { size, pixels }
+}
+
+/// Grow the image in `bytes` by `amount` pixels on `side` for outpainting, smearing the edge
+/// pixels outwards as a starting point. Returns the PNG of the larger image and its mask, which
+/// covers the new strip plus whatever `mask` already covered.
+pub fn extend_canvas(bytes: &[u8], mask: Option<&GrayImage>, side: OutpaintSide, amount: u32) -> Result<(Vec<u8>, GrayImage)> {
+    let source = image::load_from_memory(bytes).context("Failed to decode source image")?.to_rgba8();
+    let (width, height) = source.dimensions();
+    let mask = mask.filter(|mask| mask.dimensions() == (width, height));
+    let (new_width, new_height, offset_x, offset_y) = match side {
+        OutpaintSide::Left => (width + amount, height, amount, 0),
+        OutpaintSide::Right => (width + amount, height, 0, 0),
+        OutpaintSide::Top => (width, height + amount, 0, amount),
+        OutpaintSide::Bottom => (width, height + amount, 0, 0),
+    };
+
+    let extended = RgbaImage::from_fn(new_width, new_height, |x, y| {
+        let source_x = x.saturating_sub(offset_x).min(width - 1);
+        let source_y = y.saturating_sub(offset_y).min(height - 1);
+        *source.get_pixel(source_x, source_y)
+    });
+    let new_mask = GrayImage::from_fn(new_width, new_height, |x, y| {
+        let inside = (offset_x..offset_x + width).contains(&x) && (offset_y..offset_y + height).contains(&y);
+        match (inside, mask) {
+            (false, _) => MASKED,
+            (true, Some(mask)) => *mask.get_pixel(x - offset_x, y - offset_y),
+            (true, None) => KEPT,
+        }
+    });
+
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgba8(extended)
+        .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .context("Failed to encode extended image")?;
+    Ok((png, new_mask))
+}
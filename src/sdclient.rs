@@ -1,11 +1,13 @@
 use anyhow::{Result, Context};
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::sync::mpsc::SyncSender;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::time::Duration;
 use base64::{Engine as _, engine::general_purpose};
 
-#[derive(Debug, Serialize)]
+use crate::curl;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct TextToImageRequest {
     pub prompt: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -19,6 +21,10 @@ pub struct TextToImageRequest {
     pub scheduler: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seed: Option<i64>,
+    /// Images generated per batch, in parallel.
+    pub batch_size: u32,
+    /// Number of batches run one after another.
+    pub n_iter: u32,
     // Hires.fix parameters (optional with skip_serializing_if)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enable_hr: Option<bool>,
@@ -32,14 +38,53 @@ pub struct TextToImageRequest {
     pub denoising_strength: Option<f32>,
     #[serde(skip_serializing_if = "TextToImageRequest::is_empty_value")]
     pub alwayson_scripts: serde_json::Value,
+    /// Base64 source images; when set the request goes to img2img instead,
+    /// with `denoising_strength` controlling how much of them is kept.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub init_images: Vec<String>,
+    /// Base64 inpainting mask for img2img; white areas are regenerated, black ones kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mask: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mask_blur: Option<u32>,
+    /// What the masked area starts from: 0 fill, 1 original, 2 latent noise, 3 latent nothing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inpainting_fill: Option<u32>,
+    /// Inpaint the masked area alone at full resolution, then paste it back.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inpaint_full_res: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inpaint_full_res_padding: Option<u32>,
+    /// Make the image repeat seamlessly in both directions, for textures.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tiling: Option<bool>,
+    /// Run a face restoration model (CodeFormer, GFPGAN) over the finished images.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_faces: Option<bool>,
+    /// Server settings used for this request only, e.g. which face restoration model runs.
+    #[serde(skip_serializing_if = "TextToImageRequest::is_empty_value")]
+    pub override_settings: serde_json::Value,
 }
 
 impl TextToImageRequest {
     fn is_empty_value(value: &serde_json::Value) -> bool {
-        value.as_object().map_or(false, |obj| obj.is_empty())
+        value.as_object().is_some_and(|obj| obj.is_empty())
     }
 }
 
+/// Upscalers offered for the hires.fix pass.
+pub const HIRES_UPSCALERS: &[&str] = &[
+    "Latent",
+    "Latent (antialiased)",
+    "Latent (bicubic)",
+    "Latent (nearest)",
+    "ESRGAN_4x",
+    "R-ESRGAN 4x+",
+    "R-ESRGAN 4x+ Anime6B",
+    "SwinIR_4x",
+];
+
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct TextToImageResponse {
     pub images: Vec<String>, // Base64 encoded images
@@ -47,41 +92,234 @@ pub struct TextToImageResponse {
     pub info: String,
 }
 
+/// A generated image together with the generation info reported by the server.
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+    pub bytes: Vec<u8>,
+    /// JSON-encoded info shared by every image of the request.
+    pub info: String,
+    /// Position of this image within the request's results.
+    pub index: usize,
+}
+
+impl GeneratedImage {
+    /// The seed actually used for this image, read from the `info` string.
+    pub fn seed(&self) -> Option<i64> {
+        let info = serde_json::from_str::<serde_json::Value>(&self.info).ok()?;
+        info.get("all_seeds")
+            .and_then(|seeds| seeds.get(self.index))
+            .and_then(|seed| seed.as_i64())
+            .or_else(|| info.get("seed")?.as_i64())
+    }
+
+    /// The settings the server actually used for this image, read from the `info` string.
+    pub fn details(&self) -> Option<GenerationDetails> {
+        let info = serde_json::from_str::<serde_json::Value>(&self.info).ok()?;
+        let text = |key: &str| info.get(key)?.as_str().filter(|value| !value.is_empty()).map(str::to_string);
+        let number = |key: &str| info.get(key)?.as_u64().and_then(|value| u32::try_from(value).ok());
+
+        Some(GenerationDetails {
+            seed: self.seed(),
+            subseed: info.get("all_subseeds")
+                .and_then(|subseeds| subseeds.get(self.index))
+                .and_then(|subseed| subseed.as_i64())
+                .or_else(|| info.get("subseed")?.as_i64()),
+            prompt: info.get("all_prompts")
+                .and_then(|prompts| prompts.get(self.index))
+                .and_then(|prompt| prompt.as_str())
+                .map(str::to_string)
+                .or_else(|| text("prompt")),
+            model: text("sd_model_name"),
+            model_hash: text("sd_model_hash"),
+            steps: number("steps"),
+            sampler: text("sampler_name"),
+            cfg_scale: info.get("cfg_scale").and_then(|value| value.as_f64()).map(|value| value as f32),
+            width: number("width"),
+            height: number("height"),
+        })
+    }
+
+    /// The human-readable parameters text for this image, as shown in A1111's PNG Info.
+    pub fn infotext(&self) -> Option<String> {
+        let info = serde_json::from_str::<serde_json::Value>(&self.info).ok()?;
+        info.get("infotexts")?
+            .get(self.index)?
+            .as_str()
+            .map(str::to_string)
+    }
+}
+
+/// Generation settings as reported back by the server.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationDetails {
+    pub seed: Option<i64>,
+    pub subseed: Option<i64>,
+    pub prompt: Option<String>,
+    pub model: Option<String>,
+    pub model_hash: Option<String>,
+    pub steps: Option<u32>,
+    pub sampler: Option<String>,
+    pub cfg_scale: Option<f32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct ProgressResponse {
     pub progress: f32,        // 0-1 progress value
     pub eta_relative: f32,    // estimated time remaining in seconds
     pub state: serde_json::Value,
+    pub current_image: Option<String>, // Base64 encoded live preview
 }
 
+/// Progress of the running generation as reported by `/sdapi/v1/progress`.
+#[derive(Debug, Clone, Default)]
+pub struct SDProgress {
+    pub percent: f32,
+    pub eta_seconds: f32,
+    pub preview: Option<Vec<u8>>,
+}
+
+// Forks of A1111 such as Forge and reForge add fields and leave some out or null, so only a
+// name is really required and everything else is kept in `extra`.
+
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct SDModel {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub title: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub model_name: String,
     pub hash: Option<String>,
     pub sha256: Option<String>,
     pub filename: Option<String>,
     pub config: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
+impl SDModel {
+    /// Fill in a missing title or model name from the other fields, or drop a nameless entry.
+    fn with_names(mut self) -> Option<Self> {
+        if self.model_name.is_empty() {
+            // Titles look like "name.safetensors [hash]"
+            let from_title = self.title.split(" [").next().unwrap_or_default();
+            let stem = file_stem(from_title).or_else(|| file_stem(self.filename.as_deref()?))?;
+            self.model_name = stem.to_string();
+        }
+        if self.title.is_empty() {
+            self.title = self.model_name.clone();
+        }
+        Some(self)
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct LoRA {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub name: String,
     pub alias: Option<String>,
     pub path: Option<String>,
     pub metadata: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl LoRA {
+    /// The prompt tag needs a name, so take it from the alias or file when it's missing.
+    fn with_name(mut self) -> Option<Self> {
+        if self.name.is_empty() {
+            let alias = self.alias.as_deref().filter(|alias| !alias.is_empty());
+            self.name = alias.or_else(|| file_stem(self.path.as_deref()?))?.to_string();
+        }
+        Some(self)
+    }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct Sampler {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub name: String,
     pub aliases: Option<Vec<String>>,
     pub options: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Read `null` the same as a missing field.
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// The file name of `path` without its extension, if there is one.
+fn file_stem(path: &str) -> Option<&str> {
+    std::path::Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| !stem.is_empty())
+}
+
+/// Parse the body returned by `endpoint`, naming it and the offending field in the error.
+fn parse_response<T: DeserializeOwned>(endpoint: &str, body: &str) -> Result<T> {
+    serde_json::from_str(body)
+        .map_err(|e| anyhow::anyhow!("Unexpected response from {}: {}", endpoint, e))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PngInfoResponse {
+    #[serde(default)]
+    pub info: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Clone)]
+pub struct SDVae {
+    pub model_name: String,
+    pub filename: Option<String>,
+}
+
+/// VAE choices A1111 understands besides the installed files.
+pub const VAE_AUTOMATIC: &str = "Automatic";
+pub const VAE_NONE: &str = "None";
+
+/// Server options set before generating; both live in the same `/sdapi/v1/options` call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SDModelOptions {
+    /// Checkpoint to load, left unchanged when empty.
+    pub model: String,
+    pub vae: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Clone)]
+pub struct Upscaler {
+    pub name: String,
+    pub model_name: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Clone)]
+pub struct FaceRestorer {
+    pub name: String,
+    pub cmd_dir: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtraSingleImageResponse {
+    pub image: String, // Base64 encoded image
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ScheduleType {
     pub name: String,
+    pub label: Option<String>,
 }
 
 #[derive(Clone)]
@@ -106,7 +344,7 @@ impl SDClient {
     pub async fn get_available_models(&self) -> Result<Vec<SDModel>> {
         let url = format!("{}/sdapi/v1/sd-models", self.base_url.trim_end_matches('/'));
         
-        println!("Fetching available SD models from: {}", url);
+        log::info!("Fetching available SD models from: {}", url);
         
         let response = self.client
             .get(&url)
@@ -118,18 +356,16 @@ impl SDClient {
             return Err(anyhow::anyhow!("Failed to fetch models: {}", response.status()));
         }
         
-        let models: Vec<SDModel> = response
-            .json()
-            .await
-            .context("Failed to parse SD models response")?;
-            
-        Ok(models)
+        let body = response.text().await.context("Failed to read SD models response")?;
+        let models: Vec<SDModel> = parse_response(&url, &body)?;
+
+        Ok(models.into_iter().filter_map(SDModel::with_names).collect())
     }
     
     pub async fn get_available_loras(&self) -> Result<Vec<LoRA>> {
         let url = format!("{}/sdapi/v1/loras", self.base_url.trim_end_matches('/'));
         
-        println!("Fetching available LoRAs from: {}", url);
+        log::info!("Fetching available LoRAs from: {}", url);
         
         let response = self.client
             .get(&url)
@@ -141,18 +377,16 @@ impl SDClient {
             return Err(anyhow::anyhow!("Failed to fetch LoRAs: {}", response.status()));
         }
         
-        let loras: Vec<LoRA> = response
-            .json()
-            .await
-            .context("Failed to parse LoRAs response")?;
-            
-        Ok(loras)
+        let body = response.text().await.context("Failed to read LoRAs response")?;
+        let loras: Vec<LoRA> = parse_response(&url, &body)?;
+
+        Ok(loras.into_iter().filter_map(LoRA::with_name).collect())
     }
     
     pub async fn get_available_samplers(&self) -> Result<Vec<Sampler>> {
         let url = format!("{}/sdapi/v1/samplers", self.base_url.trim_end_matches('/'));
         
-        println!("Fetching available samplers from: {}", url);
+        log::info!("Fetching available samplers from: {}", url);
         
         let response = self.client
             .get(&url)
@@ -164,42 +398,169 @@ impl SDClient {
             return Err(anyhow::anyhow!("Failed to fetch samplers: {}", response.status()));
         }
         
-        let samplers: Vec<Sampler> = response
-            .json()
+        let body = response.text().await.context("Failed to read samplers response")?;
+        let samplers: Vec<Sampler> = parse_response(&url, &body)?;
+
+        Ok(samplers.into_iter().filter(|sampler| !sampler.name.is_empty()).collect())
+    }
+    
+    pub async fn get_available_schedulers(&self) -> Result<Vec<String>> {
+        let url = format!("{}/sdapi/v1/schedulers", self.base_url.trim_end_matches('/'));
+
+        log::info!("Fetching available schedulers from: {}", url);
+
+        let response = self.client
+            .get(&url)
+            .send()
             .await
-            .context("Failed to parse samplers response")?;
-            
-        Ok(samplers)
+            .context("Failed to fetch available schedulers")?;
+
+        // Older Automatic1111 builds don't have this endpoint, fall back to the common schedulers
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            log::warn!("Schedulers endpoint not available, using default list");
+            return Ok(vec![
+                "Automatic".to_string(),
+                "Uniform".to_string(),
+                "Karras".to_string(),
+                "Exponential".to_string(),
+                "Polyexponential".to_string(),
+            ]);
+        }
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to fetch schedulers: {}", response.status()));
+        }
+
+        let body = response.text().await.context("Failed to read schedulers response")?;
+        let schedulers: Vec<ScheduleType> = parse_response(&url, &body)?;
+
+        // The txt2img scheduler field accepts the display label
+        let mut names: Vec<String> = schedulers
+            .into_iter()
+            .map(|s| s.label.unwrap_or(s.name))
+            .collect();
+
+        // "Automatic" lets the sampler pick its own schedule and isn't part of the list
+        if !names.iter().any(|name| name == "Automatic") {
+            names.insert(0, "Automatic".to_string());
+        }
+
+        Ok(names)
     }
     
-    pub async fn get_available_schedulers(&self, sampler_name: &str) -> Result<Vec<String>> {
-        // The Automatic1111 API doesn't directly expose a method to get schedule types
-        // Based on the warning message, we know common ones are:
-        // "Automatic", "Uniform", "Karras", "Exponential", "Polyexponential"
-        
-        // Hard-coded list of common schedulers - this could be improved with a more API-based approach
-        // if the Automatic1111 API eventually exposes a method for this
-        let default_schedulers = vec![
-            "Automatic".to_string(),
-            "Uniform".to_string(), 
-            "Karras".to_string(), 
-            "Exponential".to_string(), 
-            "Polyexponential".to_string()
-        ];
-
-        // Optionally, we could try to parse these from sampler.options if available in the future
-        // For now, return the default list
-        Ok(default_schedulers)
+    pub async fn get_available_vaes(&self) -> Result<Vec<String>> {
+        let url = format!("{}/sdapi/v1/sd-vae", self.base_url.trim_end_matches('/'));
+
+        log::info!("Fetching available VAEs from: {}", url);
+
+        let response = self.client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to fetch available VAEs")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to fetch VAEs: {}", response.status()));
+        }
+
+        let body = response.text().await.context("Failed to read VAEs response")?;
+        let vaes: Vec<SDVae> = parse_response(&url, &body)?;
+
+        let mut names = vec![VAE_AUTOMATIC.to_string(), VAE_NONE.to_string()];
+        names.extend(vaes.into_iter().map(|vae| vae.model_name));
+        Ok(names)
     }
     
-    pub async fn change_model(&self, model_name: &str) -> Result<()> {
+    pub async fn get_available_upscalers(&self) -> Result<Vec<String>> {
+        let url = format!("{}/sdapi/v1/upscalers", self.base_url.trim_end_matches('/'));
+
+        log::info!("Fetching available upscalers from: {}", url);
+
+        let response = self.client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to fetch available upscalers")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to fetch upscalers: {}", response.status()));
+        }
+
+        let body = response.text().await.context("Failed to read upscalers response")?;
+        let upscalers: Vec<Upscaler> = parse_response(&url, &body)?;
+
+        // "None" only makes sense as a second upscaler, not for a single pass
+        Ok(upscalers.into_iter()
+            .map(|upscaler| upscaler.name)
+            .filter(|name| name != "None")
+            .collect())
+    }
+
+    pub async fn get_available_face_restorers(&self) -> Result<Vec<String>> {
+        let url = format!("{}/sdapi/v1/face-restorers", self.base_url.trim_end_matches('/'));
+
+        log::info!("Fetching available face restorers from: {}", url);
+
+        let response = self.client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to fetch available face restorers")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to fetch face restorers: {}", response.status()));
+        }
+
+        let body = response.text().await.context("Failed to read face restorers response")?;
+        let restorers: Vec<FaceRestorer> = parse_response(&url, &body)?;
+
+        Ok(restorers.into_iter().map(|restorer| restorer.name).collect())
+    }
+
+    /// Upscale an existing image with the extras tab, without generating anything new.
+    pub async fn upscale_image(&self, image_bytes: &[u8], upscaler: &str, scale: f32) -> Result<Vec<u8>> {
+        let url = format!("{}/sdapi/v1/extra-single-image", self.base_url.trim_end_matches('/'));
+
+        let request_body = serde_json::json!({
+            "image": general_purpose::STANDARD.encode(image_bytes),
+            "resize_mode": 0, // Scale by a factor rather than to a fixed size
+            "upscaling_resize": scale,
+            "upscaler_1": upscaler
+        });
+
+        let response = self.client
+            .post(&url)
+            .json(&request_body)
+            .send()
+            .await
+            .context("Failed to send upscale request")?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Upscaling failed: {}", error_text));
+        }
+
+        let result: ExtraSingleImageResponse = response
+            .json()
+            .await
+            .context("Failed to parse upscale response")?;
+
+        general_purpose::STANDARD
+            .decode(&result.image)
+            .context("Failed to decode upscaled image")
+    }
+    
+    pub async fn change_model(&self, options: &SDModelOptions) -> Result<()> {
         let url = format!("{}/sdapi/v1/options", self.base_url.trim_end_matches('/'));
         
-        println!("Changing model to: {}", model_name);
+        log::info!("Changing model to: {} (VAE: {})", options.model, options.vae);
         
-        let request_body = serde_json::json!({
-            "sd_model_checkpoint": model_name
+        let mut request_body = serde_json::json!({
+            "sd_vae": options.vae
         });
+        if !options.model.is_empty() {
+            request_body["sd_model_checkpoint"] = serde_json::json!(options.model);
+        }
         
         let response = self.client
             .post(&url)
@@ -215,20 +576,35 @@ impl SDClient {
         Ok(())
     }
     
-    pub async fn generate_image(&self, mut request: TextToImageRequest) -> Result<Vec<u8>> {
-        let url = format!("{}/sdapi/v1/txt2img", self.base_url.trim_end_matches('/'));
+    /// The URL to post `request` to, and the request with defaults filled in.
+    fn prepare_generation(&self, mut request: TextToImageRequest) -> (String, TextToImageRequest) {
+        let mode = if request.init_images.is_empty() { "txt2img" } else { "img2img" };
+        let url = format!("{}/sdapi/v1/{}", self.base_url.trim_end_matches('/'), mode);
         
-        // Set default values for hires.fix
-        request.enable_hr = Some(true);
-        request.hr_scale = Some(2.0);
-        request.hr_upscaler = Some("Latent".to_string());
-        request.hr_second_pass_steps = Some(request.steps / 2);  // Half the original steps
-        request.denoising_strength = Some(0.55);  // Good default value
+        // Fill in defaults for any hires.fix values the caller left unset (txt2img only)
+        if request.enable_hr == Some(true) && request.init_images.is_empty() {
+            request.hr_scale.get_or_insert(2.0);
+            request.hr_upscaler.get_or_insert_with(|| "Latent".to_string());
+            request.hr_second_pass_steps.get_or_insert(request.steps / 2);  // Half the original steps
+            request.denoising_strength.get_or_insert(0.55);  // Good default value
+        }
+
+        (url, request)
+    }
+
+    /// A `curl` command sending the same generation request.
+    pub fn curl_command(&self, request: TextToImageRequest) -> String {
+        let (url, request) = self.prepare_generation(request);
+        curl::curl_command(&url, &[], &request)
+    }
+
+    pub async fn generate_image(&self, request: TextToImageRequest) -> Result<Vec<GeneratedImage>> {
+        let (url, request) = self.prepare_generation(request);
         
-        println!("Sending request to Stable Diffusion API: {}", url);
+        log::info!("Sending request to Stable Diffusion API: {}", url);
         
         // Print the request as JSON for debugging
-        println!("Request payload: {}", serde_json::to_string_pretty(&request).unwrap_or_default());
+        log::debug!("Request payload: {}", serde_json::to_string_pretty(&request).unwrap_or_default());
         
         let response = self.client
             .post(&url)
@@ -256,16 +632,85 @@ impl SDClient {
             return Err(anyhow::anyhow!("No images returned from the server"));
         }
         
-        // Decode the base64 image
-        let image_data = general_purpose::STANDARD
-            .decode(&response_data.images[0])
-            .context("Failed to decode base64 image")?;
-            
-        Ok(image_data)
+        // With several images the server may put a grid of all of them first
+        let first_image = serde_json::from_str::<serde_json::Value>(&response_data.info)
+            .ok()
+            .and_then(|info| info.get("index_of_first_image")?.as_u64())
+            .unwrap_or(0) as usize;
+
+        // Decode the base64 images
+        let images: Vec<GeneratedImage> = response_data.images
+            .iter()
+            .skip(first_image)
+            .enumerate()
+            .map(|(index, encoded)| {
+                let bytes = general_purpose::STANDARD
+                    .decode(encoded)
+                    .context("Failed to decode base64 image")?;
+                Ok(GeneratedImage {
+                    bytes,
+                    info: response_data.info.clone(),
+                    index,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        if images.is_empty() {
+            return Err(anyhow::anyhow!("No images returned from the server"));
+        }
+
+        Ok(images)
     }
     
-    pub async fn check_progress(&self) -> Result<f32> {
-        let url = format!("{}/sdapi/v1/progress", self.base_url.trim_end_matches('/'));
+    /// Read the generation parameters text embedded in an image, `None` if it has none.
+    pub async fn get_png_info(&self, image_bytes: &[u8]) -> Result<Option<String>> {
+        let url = format!("{}/sdapi/v1/png-info", self.base_url.trim_end_matches('/'));
+
+        log::info!("Reading PNG info from: {}", url);
+
+        let request_body = serde_json::json!({
+            "image": format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(image_bytes))
+        });
+
+        let response = self.client
+            .post(&url)
+            .json(&request_body)
+            .send()
+            .await
+            .context("Failed to read PNG info")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to read PNG info: {}", response.status()));
+        }
+
+        let png_info: PngInfoResponse = response
+            .json()
+            .await
+            .context("Failed to parse PNG info response")?;
+
+        Ok(Some(png_info.info).filter(|info| !info.trim().is_empty()))
+    }
+
+    pub async fn interrupt(&self) -> Result<()> {
+        let url = format!("{}/sdapi/v1/interrupt", self.base_url.trim_end_matches('/'));
+
+        log::info!("Interrupting generation: {}", url);
+
+        let response = self.client
+            .post(&url)
+            .send()
+            .await
+            .context("Failed to interrupt generation")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to interrupt generation: {}", response.status()));
+        }
+
+        Ok(())
+    }
+
+    pub async fn check_progress(&self) -> Result<SDProgress> {
+        let url = format!("{}/sdapi/v1/progress?skip_current_image=false", self.base_url.trim_end_matches('/'));
         
         let response = self.client
             .get(&url)
@@ -286,6 +731,97 @@ impl SDClient {
             .await
             .context("Failed to parse progress response")?;
             
-        Ok(progress_data.progress * 100.0) // Convert to percentage
+        // The preview is only present once the sampler has produced a step
+        let preview = progress_data.current_image
+            .filter(|image| !image.is_empty())
+            .and_then(|image| general_purpose::STANDARD.decode(image).ok());
+
+        Ok(SDProgress {
+            percent: progress_data.progress * 100.0, // Convert to percentage
+            eta_seconds: progress_data.eta_relative,
+            preview,
+        })
+    }
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trimmed from a Forge server; hashes are null until computed and entries carry extra fields.
+    const FORGE_SD_MODELS: &str = r#"[
+        {"title": "flux1-dev-bnb-nf4-v2.safetensors", "model_name": "flux1-dev-bnb-nf4-v2", "hash": null, "sha256": null,
+         "filename": "/forge/models/Stable-diffusion/flux1-dev-bnb-nf4-v2.safetensors", "config": null},
+        {"title": null, "model_name": null, "filename": "/forge/models/Stable-diffusion/sdxl/juggernautXL.safetensors",
+         "hash": "d91d35736d", "sha256": null, "config": null, "type": "sdxl"}
+    ]"#;
+
+    const FORGE_LORAS: &str = r#"[
+        {"name": "add-detail-xl", "alias": "add-detail-xl", "path": "/forge/models/Lora/add-detail-xl.safetensors", "metadata": {}},
+        {"name": null, "alias": "", "path": "/forge/models/Lora/flux/realism.safetensors", "metadata": null, "version": "flux"}
+    ]"#;
+
+    const FORGE_SAMPLERS: &str = r#"[
+        {"name": "Euler a", "aliases": ["k_euler_a", "k_euler_ancestral"], "options": {"uses_ensd": "True"}},
+        {"name": "DPM++ 2M", "aliases": null, "options": {}, "scheduler": "karras"}
+    ]"#;
+
+    const FORGE_PROGRESS: &str = r#"{
+        "progress": 0.45, "eta_relative": 6.3,
+        "state": {"skipped": false, "interrupted": false, "stopping_generation": false, "job": "",
+                  "job_count": 1, "job_timestamp": "20241017120000", "job_no": 0, "sampling_step": 9, "sampling_steps": 20},
+        "current_image": null, "textinfo": null, "current_task": "task(abc123)"
+    }"#;
+
+    const FORGE_TXT2IMG_INFO: &str = r#"{
+        "prompt": "a lighthouse at dusk", "all_prompts": ["a lighthouse at dusk", "a lighthouse at dusk"],
+        "seed": 1234, "all_seeds": [1234, 1235], "subseed": 99, "all_subseeds": [99, 100],
+        "width": 896, "height": 1152, "sampler_name": "Euler", "cfg_scale": 1.0, "distilled_cfg_scale": 3.5,
+        "steps": 20, "sd_model_name": "flux1-dev-bnb-nf4-v2", "sd_model_hash": null,
+        "infotexts": ["a lighthouse at dusk\nSteps: 20, Seed: 1234", "a lighthouse at dusk\nSteps: 20, Seed: 1235"]
+    }"#;
+
+    #[test]
+    fn forge_model_lists_deserialize() {
+        let models: Vec<SDModel> = parse_response("/sdapi/v1/sd-models", FORGE_SD_MODELS).unwrap();
+        let models: Vec<SDModel> = models.into_iter().filter_map(SDModel::with_names).collect();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[1].model_name, "juggernautXL");
+        assert_eq!(models[1].title, "juggernautXL");
+        assert!(models[1].extra.contains_key("type"));
+
+        let loras: Vec<LoRA> = parse_response("/sdapi/v1/loras", FORGE_LORAS).unwrap();
+        let loras: Vec<LoRA> = loras.into_iter().filter_map(LoRA::with_name).collect();
+        assert_eq!(loras.len(), 2);
+        assert_eq!(loras[1].name, "realism");
+
+        let samplers: Vec<Sampler> = parse_response("/sdapi/v1/samplers", FORGE_SAMPLERS).unwrap();
+        assert_eq!(samplers[1].name, "DPM++ 2M");
+        assert!(samplers[1].extra.contains_key("scheduler"));
+    }
+
+    #[test]
+    fn forge_progress_deserializes() {
+        let progress: ProgressResponse = parse_response("/sdapi/v1/progress", FORGE_PROGRESS).unwrap();
+        assert_eq!(progress.progress, 0.45);
+        assert!(progress.current_image.is_none());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn forge_txt2img_info_is_read() {
+        let image = GeneratedImage { bytes: Vec::new(), info: FORGE_TXT2IMG_INFO.to_string(), index: 1 };
+        assert_eq!(image.seed(), Some(1235));
+
+        let details = image.details().unwrap();
+        assert_eq!(details.subseed, Some(100));
+        assert_eq!(details.steps, Some(20));
+        assert_eq!(details.model.as_deref(), Some("flux1-dev-bnb-nf4-v2"));
+        assert_eq!(details.model_hash, None);
+        assert_eq!(image.infotext().as_deref(), Some("a lighthouse at dusk\nSteps: 20, Seed: 1235"));
+    }
+
+    #[test]
+    fn parse_errors_name_the_endpoint() {
+        let error = parse_response::<Vec<Sampler>>("/sdapi/v1/samplers", r#"{"detail": "Not Found"}"#).unwrap_err();
+        assert!(error.to_string().contains("/sdapi/v1/samplers"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::chatapp::ChatApp;
+use eframe::egui;
+
+/// A top-level tab. To add one, implement this and list it in [`TABS`].
+pub trait AppTab: Sync {
+    fn label(&self) -> &'static str;
+
+    fn render(&self, app: &mut ChatApp, ui: &mut egui::Ui, ctx: &egui::Context);
+
+    /// Panels shown beside the central area while this tab is open.
+    fn side_panels(&self, _app: &mut ChatApp, _ctx: &egui::Context) {}
+
+    /// What dropping files on the window will do, shown while they are dragged over it.
+    fn drop_hint(&self) -> &'static str {
+        "Drop files here"
+    }
+
+    /// Handle one file dropped while this tab is open.
+    fn drop_file(&self, app: &mut ChatApp, _ctx: &egui::Context, name: &str, _bytes: Vec<u8>) {
+        self.report_error(app, format!("{} can't be dropped here", name));
+    }
+
+    fn report_error(&self, app: &mut ChatApp, message: String) {
+        app.error_message = Some(message);
+    }
+}
+
+/// The tabs in the order they appear; `ChatApp::active_tab` indexes into this.
+pub const TABS: &[&dyn AppTab] = &[&ChatTab, &StableDiffusionTab];
+
+pub struct ChatTab;
+
+impl AppTab for ChatTab {
+    fn label(&self) -> &'static str {
+        "Chat"
+    }
+
+    fn render(&self, app: &mut ChatApp, ui: &mut egui::Ui, ctx: &egui::Context) {
+        app.render_chat_tab(ui, ctx);
+    }
+
+    fn side_panels(&self, app: &mut ChatApp, ctx: &egui::Context) {
+        app.render_conversation_sidebar(ctx);
+    }
+
+    fn drop_hint(&self) -> &'static str {
+        "Drop text files or images to attach"
+    }
+
+    /// Images are attached to the next message, text goes into the input box.
+    fn drop_file(&self, app: &mut ChatApp, ctx: &egui::Context, name: &str, bytes: Vec<u8>) {
+        if image::guess_format(&bytes).is_ok() {
+            if let Err(e) = app.attach_image_bytes(ctx, &bytes) {
+                app.error_message = Some(format!("Failed to attach image: {}", e));
+            }
+        } else {
+            app.insert_dropped_text(name, &bytes);
+        }
+    }
+}
+
+pub struct StableDiffusionTab;
+
+impl AppTab for StableDiffusionTab {
+    fn label(&self) -> &'static str {
+        "Stable Diffusion"
+    }
+
+    fn render(&self, app: &mut ChatApp, ui: &mut egui::Ui, ctx: &egui::Context) {
+        app.render_stable_diffusion_tab(ui, ctx);
+    }
+
+    fn drop_hint(&self) -> &'static str {
+        "Drop an image to use it for img2img"
+    }
+
+    fn drop_file(&self, app: &mut ChatApp, ctx: &egui::Context, name: &str, bytes: Vec<u8>) {
+        if image::guess_format(&bytes).is_ok() {
+            app.set_sd_init_image(ctx, bytes);
+        } else {
+            self.report_error(app, format!("{} is not an image", name));
+        }
+    }
+
+    fn report_error(&self, app: &mut ChatApp, message: String) {
+        app.sd_error_message = Some(message);
+    }
+}